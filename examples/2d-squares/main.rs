@@ -89,6 +89,7 @@ fn init(
     let vtx_def = vtx::VertexAttrib {
         buffer_infos: vec![vtx::VertexAttribBufferInfo {
             stride: ::std::mem::size_of::<VertexData>(),
+            rate: vtx::VertexInputRate::Vertex,
             index: 0,
             elements: vec![vtx::VertexAttribBufferElementInfo {
                 location: 0,
@@ -137,6 +138,9 @@ fn init(
                 // velocities
                 (1, material::MaterialParameterType::StorageBuffer),
             ],
+            arrays: &[],
+            visibility: &[],
+            immutable_samplers: &[],
         };
         ctx.material_create(create_info).ok()?
     };
@@ -148,22 +152,25 @@ fn init(
         let writes = &[
             material::InstanceWrite {
                 binding: 0,
-                data: material::InstanceWriteData::Buffer {
+                array_offset: 0,
+                data: &[material::InstanceWriteData::Buffer {
                     buffer: instance_buffer,
                     region: None..None,
-                },
+                }],
             },
             material::InstanceWrite {
                 binding: 1,
-                data: material::InstanceWriteData::Buffer {
+                array_offset: 0,
+                data: &[material::InstanceWriteData::Buffer {
                     buffer: velocity_buffer,
                     region: None..None,
-                },
+                }],
             },
         ];
 
         unsafe {
-            ctx.material_write_instance(instance_material, writes);
+            ctx.material_write_instance(instance_material, writes)
+                .ok()?;
         }
     }
 
@@ -227,6 +234,7 @@ unsafe fn create_graph(
             };
 
             ctx.compute_shader_create(info)
+                .expect("Can't create shader")
         };
 
         struct MovePass {
@@ -270,16 +278,18 @@ unsafe fn create_graph(
 
                 let Delta(delta) = store.get().unwrap();
 
-                dispatcher.with_config((), |cmd| {
-                    cmd.push_constant::<u32>(0, wide);
-                    cmd.push_constant::<u32>(4, NUM_THINGS as u32);
+                dispatcher.with_config((), |cmd| -> Result<(), graph::PushConstantError> {
+                    cmd.push_constant::<u32>(0, wide)?;
+                    cmd.push_constant::<u32>(4, NUM_THINGS as u32)?;
 
-                    cmd.push_constant::<f32>(8, *delta as f32);
+                    cmd.push_constant::<f32>(8, *delta as f32)?;
 
                     cmd.bind_material(0, self.mat_instance);
 
                     cmd.dispatch([wide, batch_size, 1]);
-                })?;
+
+                    Ok(())
+                })??;
 
                 Ok(())
             }
@@ -303,7 +313,7 @@ unsafe fn create_graph(
                 )),
             };
 
-            ctx.vertex_shader_create(info)
+            ctx.vertex_shader_create(info).expect("Can't create shader")
         };
 
         let fragment_shader = {
@@ -316,6 +326,7 @@ unsafe fn create_graph(
             };
 
             ctx.fragment_shader_create(info)
+                .expect("Can't create shader")
         };
 
         struct Pass2D {
@@ -344,11 +355,15 @@ unsafe fn create_graph(
                             specialization: vec![],
                         }),
                         geometry: None,
+                        tess_control: None,
+                        tess_evaluation: None,
                     },
                     primitive: graph::Primitive::TriangleStrip,
+                    rasterizer: Default::default(),
                     blend_modes: vec![graph::BlendMode::Alpha],
+                    color_masks: vec![],
                     materials: vec![(0, self.mat_instance.material())],
-                    push_constants: Some(0..20),
+                    push_constants: vec![graph::PushConstantRange::graphics(0..20)],
                 }
             }
 
@@ -381,16 +396,18 @@ unsafe fn create_graph(
                 let things = NUM_THINGS;
                 let Scale(s) = store.get().unwrap();
 
-                dispatcher.with_config((), |cmd| {
-                    cmd.push_constant::<[f32; 4]>(0, [1.0, 1.0, 1.0, 1.0]);
+                dispatcher.with_config((), |cmd| -> Result<(), graph::PushConstantError> {
+                    cmd.push_constant::<[f32; 4]>(0, [1.0, 1.0, 1.0, 1.0])?;
 
-                    cmd.push_constant::<f32>(16, *s);
+                    cmd.push_constant::<f32>(16, *s)?;
 
-                    cmd.bind_vertex_buffers(&[(self.buffer, 0)]);
+                    cmd.bind_vertex_buffers(0, &[(self.buffer, 0)]);
                     cmd.bind_material(0, self.mat_instance);
 
                     cmd.draw(0..4, 0..things as u32);
-                })?;
+
+                    Ok(())
+                })??;
 
                 Ok(())
             }
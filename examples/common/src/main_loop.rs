@@ -204,6 +204,7 @@ impl<U: UserData> MainLoop<U> {
 
         let context = graph::ExecutionContext {
             reference_size: (self.size.0 as u32, self.size.1 as u32),
+            ..Default::default()
         };
 
         // execute
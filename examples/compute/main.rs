@@ -20,6 +20,9 @@ fn main() {
     let material = {
         let create_info = material::MaterialCreateInfo {
             parameters: &[(0, material::MaterialParameterType::StorageBuffer)],
+            arrays: &[],
+            visibility: &[],
+            immutable_samplers: &[],
         };
         unsafe { ctx.material_create(create_info).unwrap() }
     };
@@ -68,12 +71,14 @@ fn main() {
             material_instance,
             &[material::InstanceWrite {
                 binding: 0,
-                data: material::InstanceWriteData::Buffer {
+                array_offset: 0,
+                data: &[material::InstanceWriteData::Buffer {
                     buffer,
                     region: None..None,
-                },
+                }],
             }],
-        );
+        )
+        .unwrap();
     }
 
     let graph = unsafe { create_graph(&mut ctx, material_instance) }.unwrap();
@@ -90,6 +95,7 @@ fn main() {
                 &mut store,
                 &ExecutionContext {
                     reference_size: (1, 1),
+                    ..Default::default()
                 },
             )
             .unwrap();
@@ -135,6 +141,7 @@ unsafe fn create_graph(
         };
 
         ctx.compute_shader_create(info)
+            .expect("Can't create shader")
     };
 
     let mut builder = GraphBuilder::new("Adder");
@@ -96,6 +96,7 @@ fn init_resources(
             vertex_attrib::VertexAttribBufferInfo {
                 index: 0,
                 stride: std::mem::size_of::<[f32; 3]>(),
+                rate: vertex_attrib::VertexInputRate::Vertex,
                 elements: vec![vertex_attrib::VertexAttribBufferElementInfo {
                     location: 0,
                     offset: 0,
@@ -105,6 +106,7 @@ fn init_resources(
             vertex_attrib::VertexAttribBufferInfo {
                 index: 1,
                 stride: std::mem::size_of::<[f32; 3]>(),
+                rate: vertex_attrib::VertexInputRate::Vertex,
                 elements: vec![vertex_attrib::VertexAttribBufferElementInfo {
                     location: 1,
                     offset: 0,
@@ -155,7 +157,7 @@ unsafe fn create_graph(
                 )),
             };
 
-            ctx.vertex_shader_create(info)
+            ctx.vertex_shader_create(info).expect("Can't create shader")
         };
 
         let fragment_shader = {
@@ -168,6 +170,7 @@ unsafe fn create_graph(
             };
 
             ctx.fragment_shader_create(info)
+                .expect("Can't create shader")
         };
 
         struct Pass {
@@ -232,11 +235,15 @@ unsafe fn create_graph(
                             specialization: vec![],
                         }),
                         geometry: None,
+                        tess_control: None,
+                        tess_evaluation: None,
                     },
                     primitive: config.primitive,
+                    rasterizer: Default::default(),
                     blend_modes: vec![config.blend_mode],
+                    color_masks: vec![],
                     materials: vec![],
-                    push_constants: Some(0..128),
+                    push_constants: vec![graph::PushConstantRange::graphics(0..128)],
                 }
             }
 
@@ -304,54 +311,64 @@ unsafe fn create_graph(
                     use_depth: true,
                 };
 
-                dispatcher.with_config(config, |cmd| {
-                    unsafe fn push_matrix(
-                        cmd: &mut graph::GraphicsCommandBuffer<'_>,
-                        offset: u32,
-                        mat: Matrix4<f32>,
-                    ) {
-                        cmd.push_constant(offset, mat.x);
-                        cmd.push_constant(offset + 16, mat.y);
-                        cmd.push_constant(offset + 32, mat.z);
-                        cmd.push_constant(offset + 48, mat.w);
-                    }
+                dispatcher.with_config(
+                    config,
+                    |cmd| -> Result<(), graph::PushConstantError> {
+                        unsafe fn push_matrix(
+                            cmd: &mut graph::GraphicsCommandBuffer<'_>,
+                            offset: u32,
+                            mat: Matrix4<f32>,
+                        ) -> Result<(), graph::PushConstantError> {
+                            cmd.push_constant(offset, mat.x)?;
+                            cmd.push_constant(offset + 16, mat.y)?;
+                            cmd.push_constant(offset + 32, mat.z)?;
+                            cmd.push_constant(offset + 48, mat.w)
+                        }
 
-                    push_matrix(cmd, 0, mvp);
-                    push_matrix(cmd, 64, m);
+                        push_matrix(cmd, 0, mvp)?;
+                        push_matrix(cmd, 64, m)?;
 
-                    cmd.bind_vertex_buffers(&[(self.position, 0), (self.normal, 0)]);
+                        cmd.bind_vertex_buffers(0, &[(self.position, 0), (self.normal, 0)]);
 
-                    cmd.bind_index_buffer(self.index, 0, graph::IndexType::U32);
+                        cmd.bind_index_buffer(self.index, 0, graph::IndexType::U32);
+
+                        cmd.draw_indexed(0..self.vertices as u32, 0, 0..1);
 
-                    cmd.draw_indexed(0..self.vertices as u32, 0, 0..1);
-                })?;
+                        Ok(())
+                    },
+                )??;
 
                 if self.draw_lines {
                     config.primitive = graph::Primitive::LineList;
                     config.blend_mode = graph::BlendMode::Add;
                     config.use_depth = false;
 
-                    dispatcher.with_config(config, |cmd| {
-                        unsafe fn push_matrix(
-                            cmd: &mut graph::GraphicsCommandBuffer<'_>,
-                            offset: u32,
-                            mat: Matrix4<f32>,
-                        ) {
-                            cmd.push_constant(offset, mat.x);
-                            cmd.push_constant(offset + 16, mat.y);
-                            cmd.push_constant(offset + 32, mat.z);
-                            cmd.push_constant(offset + 48, mat.w);
-                        }
+                    dispatcher.with_config(
+                        config,
+                        |cmd| -> Result<(), graph::PushConstantError> {
+                            unsafe fn push_matrix(
+                                cmd: &mut graph::GraphicsCommandBuffer<'_>,
+                                offset: u32,
+                                mat: Matrix4<f32>,
+                            ) -> Result<(), graph::PushConstantError> {
+                                cmd.push_constant(offset, mat.x)?;
+                                cmd.push_constant(offset + 16, mat.y)?;
+                                cmd.push_constant(offset + 32, mat.z)?;
+                                cmd.push_constant(offset + 48, mat.w)
+                            }
 
-                        push_matrix(cmd, 0, mvp);
-                        push_matrix(cmd, 64, m);
+                            push_matrix(cmd, 0, mvp)?;
+                            push_matrix(cmd, 64, m)?;
 
-                        cmd.bind_vertex_buffers(&[(self.position, 0), (self.normal, 0)]);
+                            cmd.bind_vertex_buffers(0, &[(self.position, 0), (self.normal, 0)]);
 
-                        cmd.bind_index_buffer(self.index, 0, graph::IndexType::U32);
+                            cmd.bind_index_buffer(self.index, 0, graph::IndexType::U32);
 
-                        cmd.draw_indexed(0..self.vertices as u32, 0, 0..1);
-                    })?;
+                            cmd.draw_indexed(0..self.vertices as u32, 0, 0..1);
+
+                            Ok(())
+                        },
+                    )??;
                 }
 
                 Ok(())
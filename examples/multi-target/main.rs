@@ -134,6 +134,9 @@ fn init(
                 (0, material::MaterialParameterType::SampledImage),
                 (1, material::MaterialParameterType::Sampler),
             ],
+            arrays: &[],
+            visibility: &[],
+            immutable_samplers: &[],
         };
 
         ctx.material_create(create_info).unwrap()
@@ -147,14 +150,17 @@ fn init(
             &[
                 nitrogen::material::InstanceWrite {
                     binding: 0,
-                    data: nitrogen::material::InstanceWriteData::Image { image: img },
+                    array_offset: 0,
+                    data: &[nitrogen::material::InstanceWriteData::Image { image: img }],
                 },
                 nitrogen::material::InstanceWrite {
                     binding: 1,
-                    data: nitrogen::material::InstanceWriteData::Sampler { sampler },
+                    array_offset: 0,
+                    data: &[nitrogen::material::InstanceWriteData::Sampler { sampler }],
                 },
             ],
-        );
+        )
+        .unwrap();
     }
 
     let graph = setup_graphs(
@@ -118,7 +118,7 @@ fn create_graph(ctx: &mut Context) -> graph::GraphHandle {
             )),
         };
 
-        ctx.vertex_shader_create(info)
+        ctx.vertex_shader_create(info).expect("Can't create shader")
     };
 
     let fragment = {
@@ -131,6 +131,7 @@ fn create_graph(ctx: &mut Context) -> graph::GraphHandle {
         };
 
         ctx.fragment_shader_create(info)
+            .expect("Can't create shader")
     };
 
     // Opaque pass
@@ -161,11 +162,15 @@ fn create_graph(ctx: &mut Context) -> graph::GraphHandle {
                             specialization: vec![],
                         }),
                         geometry: None,
+                        tess_control: None,
+                        tess_evaluation: None,
                     },
                     primitive: graph::Primitive::TriangleStrip,
+                    rasterizer: Default::default(),
                     blend_modes: vec![graph::BlendMode::Alpha],
+                    color_masks: vec![],
                     materials: vec![],
-                    push_constants: Some(
+                    push_constants: vec![graph::PushConstantRange::graphics(
                         // (0..8) canvas_size
                         // (8..16) quad_pos
                         // (16..24) quad_size
@@ -173,7 +178,7 @@ fn create_graph(ctx: &mut Context) -> graph::GraphHandle {
                         // (28..32) padding
                         // (32..48) quad_color
                         0..48,
-                    ),
+                    )],
                 }
             }
 
@@ -220,20 +225,22 @@ fn create_graph(ctx: &mut Context) -> graph::GraphHandle {
                 dispatcher.clear_image(canvas, Color([0.7, 0.7, 1.0, 1.0]));
                 dispatcher.clear_image(depth, DepthStencil(1.0, 0));
 
-                dispatcher.with_config((), |cmd| {
-                    cmd.push_constant::<[f32; 2]>(0, [size.0, size.1]);
+                dispatcher.with_config((), |cmd| -> Result<(), graph::PushConstantError> {
+                    cmd.push_constant::<[f32; 2]>(0, [size.0, size.1])?;
 
                     for quad in &quads.quads {
-                        cmd.push_constant::<[f32; 2]>(8, quad.pos);
-                        cmd.push_constant::<[f32; 2]>(16, quad.size);
+                        cmd.push_constant::<[f32; 2]>(8, quad.pos)?;
+                        cmd.push_constant::<[f32; 2]>(16, quad.size)?;
 
-                        cmd.push_constant::<f32>(24, quad.depth);
+                        cmd.push_constant::<f32>(24, quad.depth)?;
 
-                        cmd.push_constant::<[f32; 4]>(32, quad.color);
+                        cmd.push_constant::<[f32; 4]>(32, quad.color)?;
 
                         cmd.draw(0..4, 0..1);
                     }
-                })?;
+
+                    Ok(())
+                })??;
 
                 Ok(())
             }
@@ -272,11 +279,15 @@ fn create_graph(ctx: &mut Context) -> graph::GraphHandle {
                             specialization: vec![],
                         }),
                         geometry: None,
+                        tess_control: None,
+                        tess_evaluation: None,
                     },
                     primitive: graph::Primitive::TriangleStrip,
+                    rasterizer: Default::default(),
                     blend_modes: vec![graph::BlendMode::Alpha],
+                    color_masks: vec![],
                     materials: vec![],
-                    push_constants: Some(
+                    push_constants: vec![graph::PushConstantRange::graphics(
                         // (0..8) canvas_size
                         // (8..16) quad_pos
                         // (16..24) quad_size
@@ -284,7 +295,7 @@ fn create_graph(ctx: &mut Context) -> graph::GraphHandle {
                         // (28..32) padding
                         // (32..48) quad_color
                         0..48,
-                    ),
+                    )],
                 }
             }
 
@@ -303,20 +314,22 @@ fn create_graph(ctx: &mut Context) -> graph::GraphHandle {
                 let size = store.get::<main_loop::CanvasSize>().unwrap();
                 let quads = store.get::<QuadsAlpha>().unwrap();
 
-                dispatcher.with_config((), |cmd| {
-                    cmd.push_constant::<[f32; 2]>(0, [size.0, size.1]);
+                dispatcher.with_config((), |cmd| -> Result<(), graph::PushConstantError> {
+                    cmd.push_constant::<[f32; 2]>(0, [size.0, size.1])?;
 
                     for quad in &quads.quads {
-                        cmd.push_constant::<[f32; 2]>(8, quad.pos);
-                        cmd.push_constant::<[f32; 2]>(16, quad.size);
+                        cmd.push_constant::<[f32; 2]>(8, quad.pos)?;
+                        cmd.push_constant::<[f32; 2]>(16, quad.size)?;
 
-                        cmd.push_constant::<f32>(24, quad.depth);
+                        cmd.push_constant::<f32>(24, quad.depth)?;
 
-                        cmd.push_constant::<[f32; 4]>(32, quad.color);
+                        cmd.push_constant::<[f32; 4]>(32, quad.color)?;
 
                         cmd.draw(0..4, 0..1);
                     }
-                })?;
+
+                    Ok(())
+                })??;
 
                 Ok(())
             }
@@ -3,6 +3,7 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use nitrogen::display::SwapchainSetupConfig;
+use nitrogen::vertex_attrib::Vertex;
 use nitrogen::Context;
 
 struct AppState {
@@ -67,6 +68,7 @@ fn main() {
 
         let exec_context = nitrogen::graph::ExecutionContext {
             reference_size: (ctx_size.0 as _, ctx_size.1 as _),
+            ..Default::default()
         };
 
         unsafe {
@@ -106,9 +108,11 @@ fn main() {
 }
 
 #[repr(C)]
-#[derive(Copy, Clone, Debug)]
-struct Vertex {
+#[derive(Copy, Clone, Debug, Vertex)]
+struct TriangleVertex {
+    #[location(0)]
     pos: [f32; 2],
+    #[location(1)]
     uv: [f32; 2],
 }
 
@@ -119,46 +123,30 @@ unsafe fn create_appstate(ctx: &mut Context) -> AppState {
         use nitrogen::vertex_attrib::*;
 
         VertexAttrib {
-            buffer_infos: vec![VertexAttribBufferInfo {
-                index: 0,
-                stride: std::mem::size_of::<Vertex>(),
-                elements: vec![
-                    // position
-                    VertexAttribBufferElementInfo {
-                        location: 0,
-                        format: nitrogen::gfx::format::Format::Rg32Sfloat,
-                        offset: 0,
-                    },
-                    VertexAttribBufferElementInfo {
-                        location: 1,
-                        format: nitrogen::gfx::format::Format::Rg32Sfloat,
-                        offset: std::mem::size_of::<[f32; 2]>() as _,
-                    },
-                ],
-            }],
+            buffer_infos: vec![TriangleVertex::buffer_info(0, VertexInputRate::Vertex)],
         }
     };
     let vertex_buffer = {
         let data = [
             // bottom left
-            Vertex {
+            TriangleVertex {
                 pos: [0.75, 0.75],
                 uv: [0.0, 0.0],
             },
             // top
-            Vertex {
+            TriangleVertex {
                 pos: [0.0, -0.75],
                 uv: [0.0, 1.0],
             },
             // bottom right
-            Vertex {
+            TriangleVertex {
                 pos: [-0.75, 0.75],
                 uv: [1.0, 1.0],
             },
         ];
 
         let info = nitrogen::buffer::CpuVisibleCreateInfo {
-            size: (std::mem::size_of::<Vertex>() * 3) as _,
+            size: (std::mem::size_of::<TriangleVertex>() * 3) as _,
             is_transient: false,
             usage: {
                 use nitrogen::gfx::buffer::Usage;
@@ -233,6 +221,10 @@ fn create_graph(
             res.image_write_color("Output", 0);
         }
 
+        fn configs(&self) -> Vec<Self::Config> {
+            vec![()]
+        }
+
         fn configure(&self, _config: &Self::Config) -> graph::GraphicsPipelineInfo {
             graph::GraphicsPipelineInfo {
                 vertex_attrib: Some(self.vtx.clone()),
@@ -248,11 +240,15 @@ fn create_graph(
                         specialization: vec![],
                     }),
                     geometry: None,
+                    tess_control: None,
+                    tess_evaluation: None,
                 },
                 primitive: graph::Primitive::TriangleList,
+                rasterizer: Default::default(),
                 blend_modes: vec![graph::BlendMode::Alpha],
+                color_masks: vec![],
                 materials: vec![],
-                push_constants: None,
+                push_constants: vec![],
             }
         }
 
@@ -266,7 +262,7 @@ fn create_graph(
             dispatcher.clear_image(output, graph::ImageClearValue::Color([0.0, 0.0, 0.0, 0.0]));
 
             dispatcher.with_config((), |cmd| {
-                cmd.bind_vertex_buffers(&[(self.vertex_buffer, 0)]);
+                cmd.bind_vertex_buffers(0, &[(self.vertex_buffer, 0)]);
 
                 cmd.draw(0..3, 0..1);
             })?;
@@ -284,7 +280,7 @@ fn create_graph(
             entry_point: "VertexMain".into(),
         };
 
-        ctx.vertex_shader_create(info)
+        ctx.vertex_shader_create(info).expect("Can't create shader")
     };
 
     let shader_fragment = {
@@ -297,6 +293,7 @@ fn create_graph(
         };
 
         ctx.fragment_shader_create(info)
+            .expect("Can't create shader")
     };
 
     let pass = TrianglePass {
@@ -80,6 +80,9 @@ fn init_resources(
                 (1, nitrogen::material::MaterialParameterType::Sampler),
                 (2, nitrogen::material::MaterialParameterType::UniformBuffer),
             ],
+            arrays: &[],
+            visibility: &[],
+            immutable_samplers: &[],
         };
 
         ctx.material_create(create_info).unwrap()
@@ -199,14 +202,17 @@ fn init_resources(
             &[
                 nitrogen::material::InstanceWrite {
                     binding: 0,
-                    data: nitrogen::material::InstanceWriteData::Image { image },
+                    array_offset: 0,
+                    data: &[nitrogen::material::InstanceWriteData::Image { image }],
                 },
                 nitrogen::material::InstanceWrite {
                     binding: 1,
-                    data: nitrogen::material::InstanceWriteData::Sampler { sampler },
+                    array_offset: 0,
+                    data: &[nitrogen::material::InstanceWriteData::Sampler { sampler }],
                 },
             ],
-        );
+        )
+        .unwrap();
     }
 
     let quad_data = QuadData {
@@ -247,7 +253,7 @@ fn create_graph(ctx: &mut Context, mat: material::MaterialHandle) -> graph::Grap
                 )),
             };
 
-            ctx.vertex_shader_create(info)
+            ctx.vertex_shader_create(info).expect("Can't create shader")
         };
 
         let fragment = {
@@ -260,6 +266,7 @@ fn create_graph(ctx: &mut Context, mat: material::MaterialHandle) -> graph::Grap
             };
 
             ctx.fragment_shader_create(info)
+                .expect("Can't create shader")
         };
 
         struct TestPass {
@@ -277,6 +284,7 @@ fn create_graph(ctx: &mut Context, mat: material::MaterialHandle) -> graph::Grap
                         buffer_infos: vec![
                             vertex_attrib::VertexAttribBufferInfo {
                                 stride: std::mem::size_of::<[f32; 2]>(),
+                                rate: vertex_attrib::VertexInputRate::Vertex,
                                 index: 0,
                                 elements: vec![
                                     nitrogen::vertex_attrib::VertexAttribBufferElementInfo {
@@ -289,6 +297,7 @@ fn create_graph(ctx: &mut Context, mat: material::MaterialHandle) -> graph::Grap
                             // uv
                             vertex_attrib::VertexAttribBufferInfo {
                                 stride: std::mem::size_of::<[f32; 2]>(),
+                                rate: vertex_attrib::VertexInputRate::Vertex,
                                 index: 1,
                                 elements: vec![
                                     nitrogen::vertex_attrib::VertexAttribBufferElementInfo {
@@ -312,11 +321,15 @@ fn create_graph(ctx: &mut Context, mat: material::MaterialHandle) -> graph::Grap
                             specialization: vec![],
                         }),
                         geometry: None,
+                        tess_control: None,
+                        tess_evaluation: None,
                     },
                     primitive: graph::Primitive::TriangleStrip,
+                    rasterizer: Default::default(),
                     blend_modes: vec![graph::BlendMode::Alpha],
+                    color_masks: vec![],
                     materials: vec![(0, self.mat)],
-                    push_constants: None,
+                    push_constants: vec![],
                 }
             }
 
@@ -338,7 +351,7 @@ fn create_graph(ctx: &mut Context, mat: material::MaterialHandle) -> graph::Grap
                 dispatcher.clear_image(canvas, ImageClearValue::Color([0.0, 0.0, 0.0, 0.0]));
 
                 dispatcher.with_config((), |cmd| {
-                    cmd.bind_vertex_buffers(&[(quad.vtx_pos, 0), (quad.vtx_uv, 0)]);
+                    cmd.bind_vertex_buffers(0, &[(quad.vtx_pos, 0), (quad.vtx_uv, 0)]);
                     cmd.bind_material(0, quad.mat_instance);
                     cmd.draw(0..4, 0..1);
                 })?;
@@ -367,7 +380,7 @@ fn create_graph(ctx: &mut Context, mat: material::MaterialHandle) -> graph::Grap
                 )),
             };
 
-            ctx.vertex_shader_create(info)
+            ctx.vertex_shader_create(info).expect("Can't create shader")
         };
 
         let fragment = {
@@ -380,6 +393,7 @@ fn create_graph(ctx: &mut Context, mat: material::MaterialHandle) -> graph::Grap
             };
 
             ctx.fragment_shader_create(info)
+                .expect("Can't create shader")
         };
 
         struct ReadPass {
@@ -396,6 +410,7 @@ fn create_graph(ctx: &mut Context, mat: material::MaterialHandle) -> graph::Grap
                         buffer_infos: vec![
                             vertex_attrib::VertexAttribBufferInfo {
                                 stride: std::mem::size_of::<[f32; 2]>(),
+                                rate: vertex_attrib::VertexInputRate::Vertex,
                                 index: 0,
                                 elements: vec![
                                     nitrogen::vertex_attrib::VertexAttribBufferElementInfo {
@@ -408,6 +423,7 @@ fn create_graph(ctx: &mut Context, mat: material::MaterialHandle) -> graph::Grap
                             // uv
                             vertex_attrib::VertexAttribBufferInfo {
                                 stride: std::mem::size_of::<[f32; 2]>(),
+                                rate: vertex_attrib::VertexInputRate::Vertex,
                                 index: 1,
                                 elements: vec![
                                     nitrogen::vertex_attrib::VertexAttribBufferElementInfo {
@@ -431,11 +447,15 @@ fn create_graph(ctx: &mut Context, mat: material::MaterialHandle) -> graph::Grap
                             specialization: vec![],
                         }),
                         geometry: None,
+                        tess_control: None,
+                        tess_evaluation: None,
                     },
                     primitive: graph::Primitive::TriangleStrip,
+                    rasterizer: Default::default(),
                     blend_modes: vec![graph::BlendMode::Alpha],
+                    color_masks: vec![],
                     materials: vec![],
-                    push_constants: None,
+                    push_constants: vec![],
                 }
             }
 
@@ -459,7 +479,7 @@ fn create_graph(ctx: &mut Context, mat: material::MaterialHandle) -> graph::Grap
                 dispatcher.clear_image(canvas, ImageClearValue::Color([0.0, 0.0, 0.0, 0.0]));
 
                 dispatcher.with_config((), |cmd| {
-                    cmd.bind_vertex_buffers(&[(quad.vtx_pos, 0), (quad.vtx_uv, 0)]);
+                    cmd.bind_vertex_buffers(0, &[(quad.vtx_pos, 0), (quad.vtx_uv, 0)]);
                     cmd.draw(0..4, 0..1);
                 })?;
 
@@ -0,0 +1,276 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Derive macros for nitrogen.
+//!
+//! The `Vertex` derive implements `nitrogen::vertex_attrib::Vertex` for a `#[repr(C)]` struct.
+//! Every field needs a `#[location(n)]` attribute which is the location used to identify the
+//! element in shader programs.
+//!
+//! ```ignore
+//! #[derive(Vertex)]
+//! #[repr(C)]
+//! struct QuadVertex {
+//!     #[location(0)]
+//!     pos: [f32; 2],
+//!     #[location(1)]
+//!     uv: [f32; 2],
+//! }
+//! ```
+//!
+//! Supported field types are `f32`, `u32`, `i32` and arrays of them with up to 4 elements.
+
+#![recursion_limit = "128"]
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::quote;
+use syn::spanned::Spanned;
+use syn::{parse_macro_input, Data, DeriveInput, Fields, Lit, Meta, NestedMeta, Type};
+
+/// Derive `nitrogen::vertex_attrib::Vertex` for a `#[repr(C)]` struct.
+#[proc_macro_derive(Vertex, attributes(location))]
+pub fn derive_vertex(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    match vertex_impl(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// Scalar type of a vertex element.
+#[derive(Copy, Clone)]
+enum Scalar {
+    F32,
+    U32,
+    I32,
+}
+
+/// A vertex element type the derive knows the layout and format of.
+struct ElementType {
+    scalar: Scalar,
+    components: usize,
+}
+
+impl ElementType {
+    fn size(&self) -> usize {
+        // all supported scalars are 4 bytes in size, so there is never any padding between
+        // fields in a `#[repr(C)]` struct.
+        4 * self.components
+    }
+
+    fn format(&self) -> &'static str {
+        match (self.scalar, self.components) {
+            (Scalar::F32, 1) => "R32Sfloat",
+            (Scalar::F32, 2) => "Rg32Sfloat",
+            (Scalar::F32, 3) => "Rgb32Sfloat",
+            (Scalar::F32, 4) => "Rgba32Sfloat",
+            (Scalar::U32, 1) => "R32Uint",
+            (Scalar::U32, 2) => "Rg32Uint",
+            (Scalar::U32, 3) => "Rgb32Uint",
+            (Scalar::U32, 4) => "Rgba32Uint",
+            (Scalar::I32, 1) => "R32Sint",
+            (Scalar::I32, 2) => "Rg32Sint",
+            (Scalar::I32, 3) => "Rgb32Sint",
+            (Scalar::I32, 4) => "Rgba32Sint",
+            _ => unreachable!(),
+        }
+    }
+}
+
+fn vertex_impl(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    if !is_repr_c(input) {
+        return Err(syn::Error::new_spanned(
+            &input.ident,
+            "`Vertex` can only be derived for `#[repr(C)]` structs",
+        ));
+    }
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    &input.ident,
+                    "`Vertex` can only be derived for structs with named fields",
+                ));
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "`Vertex` can only be derived for structs",
+            ));
+        }
+    };
+
+    let mut offset = 0;
+    let mut elements = Vec::with_capacity(fields.len());
+
+    for field in fields {
+        let location = field_location(field)?;
+        let ty = element_type(&field.ty)?;
+
+        let format = syn::Ident::new(ty.format(), Span::call_site());
+        let elem_offset = offset as u32;
+
+        elements.push(quote! {
+            ::nitrogen::vertex_attrib::VertexAttribBufferElementInfo {
+                location: #location,
+                format: ::nitrogen::gfx::format::Format::#format,
+                offset: #elem_offset,
+            }
+        });
+
+        offset += ty.size();
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::nitrogen::vertex_attrib::Vertex for #name #ty_generics #where_clause {
+            fn buffer_info(
+                index: u32,
+                rate: ::nitrogen::vertex_attrib::VertexInputRate,
+            ) -> ::nitrogen::vertex_attrib::VertexAttribBufferInfo {
+                ::nitrogen::vertex_attrib::VertexAttribBufferInfo {
+                    stride: ::std::mem::size_of::<Self>(),
+                    index,
+                    rate,
+                    elements: vec![#(#elements),*],
+                }
+            }
+        }
+    })
+}
+
+fn is_repr_c(input: &DeriveInput) -> bool {
+    input
+        .attrs
+        .iter()
+        .filter_map(|attr| attr.parse_meta().ok())
+        .any(|meta| match meta {
+            Meta::List(list) => {
+                list.ident == "repr"
+                    && list.nested.iter().any(|nested| match nested {
+                        NestedMeta::Meta(Meta::Word(ident)) => ident == "C",
+                        _ => false,
+                    })
+            }
+            _ => false,
+        })
+}
+
+fn field_location(field: &syn::Field) -> syn::Result<u32> {
+    let mut location = None;
+
+    for attr in &field.attrs {
+        let meta = match attr.parse_meta() {
+            Ok(meta) => meta,
+            Err(_) => continue,
+        };
+
+        if meta.name() != "location" {
+            continue;
+        }
+
+        let value = match &meta {
+            Meta::List(list) if list.nested.len() == 1 => match &list.nested[0] {
+                NestedMeta::Literal(Lit::Int(int)) => Some(int.value()),
+                _ => None,
+            },
+            _ => None,
+        };
+
+        match value {
+            Some(value) if value <= u64::from(u32::max_value()) => {
+                if location.is_some() {
+                    return Err(syn::Error::new_spanned(
+                        attr,
+                        "the location of a field can only be specified once",
+                    ));
+                }
+                location = Some(value as u32);
+            }
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    attr,
+                    "expected a location of the form `#[location(n)]`",
+                ));
+            }
+        }
+    }
+
+    location.ok_or_else(|| {
+        syn::Error::new(
+            field.span(),
+            "every field of a `Vertex` struct needs a `#[location(n)]` attribute",
+        )
+    })
+}
+
+fn element_type(ty: &Type) -> syn::Result<ElementType> {
+    let unsupported = || {
+        syn::Error::new_spanned(
+            ty,
+            "unsupported vertex element type, expected `f32`, `u32`, `i32` \
+             or an array of them with up to 4 elements",
+        )
+    };
+
+    match ty {
+        Type::Path(_) => {
+            let scalar = scalar_type(ty).ok_or_else(unsupported)?;
+
+            Ok(ElementType {
+                scalar,
+                components: 1,
+            })
+        }
+        Type::Array(array) => {
+            let scalar = scalar_type(&array.elem).ok_or_else(unsupported)?;
+
+            let components = match &array.len {
+                syn::Expr::Lit(syn::ExprLit {
+                    lit: Lit::Int(int), ..
+                }) => int.value() as usize,
+                _ => return Err(unsupported()),
+            };
+
+            if components == 0 || components > 4 {
+                return Err(unsupported());
+            }
+
+            Ok(ElementType { scalar, components })
+        }
+        _ => Err(unsupported()),
+    }
+}
+
+fn scalar_type(ty: &Type) -> Option<Scalar> {
+    let path = match ty {
+        Type::Path(path) if path.qself.is_none() => &path.path,
+        _ => return None,
+    };
+
+    if path.segments.len() != 1 || !path.segments[0].arguments.is_empty() {
+        return None;
+    }
+
+    let ident = &path.segments[0].ident;
+
+    if ident == "f32" {
+        Some(Scalar::F32)
+    } else if ident == "u32" {
+        Some(Scalar::U32)
+    } else if ident == "i32" {
+        Some(Scalar::I32)
+    } else {
+        None
+    }
+}
@@ -0,0 +1,11 @@
+use nitrogen::vertex_attrib::Vertex;
+
+#[derive(Vertex)]
+#[repr(C)]
+struct BadVertex {
+    #[location(0)]
+    pos: [f32; 2],
+    uv: [f32; 2],
+}
+
+fn main() {}
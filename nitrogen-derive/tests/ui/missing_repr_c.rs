@@ -0,0 +1,9 @@
+use nitrogen::vertex_attrib::Vertex;
+
+#[derive(Vertex)]
+struct BadVertex {
+    #[location(0)]
+    pos: [f32; 2],
+}
+
+fn main() {}
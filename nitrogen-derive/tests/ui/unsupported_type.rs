@@ -0,0 +1,10 @@
+use nitrogen::vertex_attrib::Vertex;
+
+#[derive(Vertex)]
+#[repr(C)]
+struct BadVertex {
+    #[location(0)]
+    pos: [f64; 2],
+}
+
+fn main() {}
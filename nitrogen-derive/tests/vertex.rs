@@ -0,0 +1,63 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use nitrogen::gfx::format::Format;
+use nitrogen::vertex_attrib::{Vertex, VertexInputRate};
+
+#[derive(Vertex)]
+#[repr(C)]
+#[allow(dead_code)]
+struct MixedVertex {
+    #[location(0)]
+    pos: [f32; 3],
+    #[location(3)]
+    id: u32,
+    #[location(1)]
+    color: [f32; 4],
+    #[location(2)]
+    offset: [i32; 2],
+}
+
+/// Offset of a field in bytes, computed from an actual value of the struct.
+fn field_offset<T, F>(value: &T, field: &F) -> u32 {
+    (field as *const F as usize - value as *const T as usize) as u32
+}
+
+#[test]
+fn layout_matches_repr_c() {
+    let v = MixedVertex {
+        pos: [0.0; 3],
+        id: 0,
+        color: [0.0; 4],
+        offset: [0; 2],
+    };
+
+    let info = MixedVertex::buffer_info(2, VertexInputRate::Instance { divisor: 1 });
+
+    assert_eq!(info.stride, std::mem::size_of::<MixedVertex>());
+    assert_eq!(info.index, 2);
+    assert_eq!(info.rate, VertexInputRate::Instance { divisor: 1 });
+
+    let elements = info
+        .elements
+        .iter()
+        .map(|elem| (elem.location, elem.format, elem.offset))
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        elements,
+        vec![
+            (0, Format::Rgb32Sfloat, field_offset(&v, &v.pos)),
+            (3, Format::R32Uint, field_offset(&v, &v.id)),
+            (1, Format::Rgba32Sfloat, field_offset(&v, &v.color)),
+            (2, Format::Rg32Sint, field_offset(&v, &v.offset)),
+        ]
+    );
+}
+
+#[test]
+fn compile_errors() {
+    let tests = trybuild::TestCases::new();
+    tests.compile_fail("tests/ui/*.rs");
+}
@@ -70,7 +70,15 @@ impl DeviceContext {
             let features = {
                 use gfx::Features;
 
-                Features::empty()
+                // optional features that can be used by pipeline state, only enabled if the
+                // device supports them.
+                let optional = Features::MULTI_VIEWPORTS
+                    | Features::NON_FILL_POLYGON_MODE
+                    | Features::DEPTH_CLAMP
+                    | Features::LINE_WIDTH
+                    | Features::TESSELLATION_SHADER;
+
+                adapter.physical_device.features() & optional
             };
 
             let mut gpu = adapter
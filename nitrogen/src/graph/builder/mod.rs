@@ -7,12 +7,20 @@
 pub mod resource_descriptor;
 
 pub use self::resource_descriptor::*;
-use crate::graph::pass::{ComputePass, ComputePipelineInfo, GraphicsPass, GraphicsPipelineInfo};
-use crate::graph::{ComputePassAccessor, GraphicPassAccessor, PassName, ResourceName};
+use crate::device::DeviceContext;
+use crate::graph::execution::{ensure_pipeline_compute, ensure_pipeline_graphics, PassResources};
+use crate::graph::pass::{
+    ComputePass, ComputePipelineInfo, GraphicsPass, GraphicsPipelineInfo, PassId,
+};
+use crate::graph::{
+    ComputePassAccessor, GraphicPassAccessor, PassName, PrepareError, ResourceName, Storages,
+};
 use crate::util::CowString;
 use std::cell::RefCell;
 
+use std::collections::hash_map::Entry;
 use std::collections::HashMap;
+use std::hash::Hash;
 use std::rc::Rc;
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
 pub(crate) enum PassType {
@@ -30,6 +38,97 @@ pub(crate) struct GraphicsPassContext<T: GraphicsPass> {
     pub(crate) pipeline_infos: RefCell<HashMap<T::Config, GraphicsPipelineInfo>>,
 }
 
+impl<T: ComputePass> ComputePassContext<T> {
+    /// Create the pipelines for all configurations listed by the pass.
+    ///
+    /// Returns the number of pipelines that were created.
+    pub(crate) unsafe fn prewarm(
+        &self,
+        device: &DeviceContext,
+        storages: &Storages,
+        pass_res: &mut PassResources,
+        pass: PassId,
+    ) -> Result<usize, PrepareError> {
+        prewarm_configs(
+            self.pass.configs(),
+            &self.pipeline_infos,
+            |config| self.pass.configure(config),
+            |info| ensure_pipeline_compute(device, storages, pass_res, pass, info),
+        )
+    }
+
+    /// Forget the pipeline descriptions of configurations whose pipelines got evicted.
+    pub(crate) fn retain_pipeline_infos(&self, pass_res: &PassResources, pass: PassId) {
+        let pipelines = pass_res.compute_pipelines.get(&pass);
+
+        self.pipeline_infos
+            .borrow_mut()
+            .retain(|_, info| pipelines.map_or(false, |pipes| pipes.contains_key(info)));
+    }
+}
+
+impl<T: GraphicsPass> GraphicsPassContext<T> {
+    /// Create the pipelines for all configurations listed by the pass.
+    ///
+    /// Returns the number of pipelines that were created.
+    pub(crate) unsafe fn prewarm(
+        &self,
+        device: &DeviceContext,
+        storages: &Storages,
+        pass_res: &mut PassResources,
+        pass: PassId,
+    ) -> Result<usize, PrepareError> {
+        prewarm_configs(
+            self.pass.configs(),
+            &self.pipeline_infos,
+            |config| self.pass.configure(config),
+            |info| ensure_pipeline_graphics(device, storages, pass_res, pass, info),
+        )
+    }
+
+    /// Forget the pipeline descriptions of configurations whose pipelines got evicted.
+    pub(crate) fn retain_pipeline_infos(&self, pass_res: &PassResources, pass: PassId) {
+        let pipelines = pass_res.graphic_pipelines.get(&pass);
+
+        self.pipeline_infos
+            .borrow_mut()
+            .retain(|_, info| pipelines.map_or(false, |pipes| pipes.contains_key(info)));
+    }
+}
+
+/// Make sure pipelines for all `configs` exist, using `ensure` to create the missing ones.
+///
+/// The pipeline descriptions of the configurations are cached in `infos`, so `configure` is only
+/// called for new configurations. Returns the number of pipelines that were created.
+fn prewarm_configs<C, I>(
+    configs: Vec<C>,
+    infos: &RefCell<HashMap<C, I>>,
+    configure: impl Fn(&C) -> I,
+    mut ensure: impl FnMut(&I) -> Result<bool, PrepareError>,
+) -> Result<usize, PrepareError>
+where
+    C: Hash + Eq,
+{
+    let mut infos = infos.borrow_mut();
+    let mut created = 0;
+
+    for config in configs {
+        let info = match infos.entry(config) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let info = configure(entry.key());
+                entry.insert(info)
+            }
+        };
+
+        if ensure(info)? {
+            created += 1;
+        }
+    }
+
+    Ok(created)
+}
+
 /// Name of a graph.
 pub type GraphName = CowString;
 
@@ -71,6 +170,8 @@ impl GraphBuilder {
             let pass_ref_prepare = Rc::new(RefCell::new(pass));
             let pass_ref_describe = pass_ref_prepare.clone();
             let pass_ref_execute = pass_ref_prepare.clone();
+            let pass_ref_prewarm = pass_ref_prepare.clone();
+            let pass_ref_retain = pass_ref_prepare.clone();
 
             ComputePassAccessor {
                 prepare: Box::new(move |store| {
@@ -88,6 +189,16 @@ impl GraphBuilder {
                         unsafe { pass.pass.execute(store, &mut dispatcher) }
                     }
                 }),
+                prewarm: Box::new(move |device, storages, pass_res, pass| {
+                    let pass_impl = pass_ref_prewarm.borrow();
+
+                    unsafe { pass_impl.prewarm(device, storages, pass_res, pass) }
+                }),
+                retain_pipeline_infos: Box::new(move |pass_res, pass| {
+                    pass_ref_retain
+                        .borrow()
+                        .retain_pipeline_infos(pass_res, pass);
+                }),
             }
         };
 
@@ -113,6 +224,8 @@ impl GraphBuilder {
             let pass_ref_prepare = Rc::new(RefCell::new(pass));
             let pass_ref_describe = pass_ref_prepare.clone();
             let pass_ref_execute = pass_ref_prepare.clone();
+            let pass_ref_prewarm = pass_ref_prepare.clone();
+            let pass_ref_retain = pass_ref_prepare.clone();
 
             GraphicPassAccessor {
                 prepare: Box::new(move |store| {
@@ -130,6 +243,16 @@ impl GraphBuilder {
                         unsafe { pass.pass.execute(store, &mut dispatcher) }
                     }
                 }),
+                prewarm: Box::new(move |device, storages, pass_res, pass| {
+                    let pass_impl = pass_ref_prewarm.borrow();
+
+                    unsafe { pass_impl.prewarm(device, storages, pass_res, pass) }
+                }),
+                retain_pipeline_infos: Box::new(move |pass_res, pass| {
+                    pass_ref_retain
+                        .borrow()
+                        .retain_pipeline_infos(pass_res, pass);
+                }),
             }
         };
 
@@ -6,6 +6,7 @@
 
 use crate::graph::ResourceName;
 
+use crate::buffer;
 use crate::image;
 
 use self::ResourceReadType as R;
@@ -190,6 +191,27 @@ impl ResourceDescriptor {
         ));
     }
 
+    /// State the dependence on a uniform-texel buffer that will be used for reading.
+    pub fn buffer_read_uniform_texel<T: Into<ResourceName>>(&mut self, name: T, binding: u8) {
+        self.resource_reads.push((
+            name.into(),
+            R::Buffer(BufferReadType::UniformTexel),
+            binding,
+            None,
+        ));
+    }
+
+    /// State the dependence on a buffer that will be used as the source of parameters for
+    /// indirect draw or dispatch commands.
+    pub fn buffer_read_indirect<T: Into<ResourceName>>(&mut self, name: T) {
+        self.resource_reads.push((
+            name.into(),
+            R::Buffer(BufferReadType::Indirect),
+            u8::max_value(),
+            None,
+        ));
+    }
+
     /// Create a new "virtual resource". Virtual resources do not contain any data, nor do they
     /// have a runtime representation.
     /// They are only used to explicitly state a dependence relationship between passes.
@@ -251,10 +273,12 @@ pub struct ImageCreateInfo {
 /// Information needed to create a buffer resource.
 #[derive(Debug, Clone, Hash)]
 pub struct BufferCreateInfo {
-    /// Size of the buffer in bytes.
-    pub size: u64,
+    /// Size mode used to determine the size of the buffer.
+    pub size_mode: buffer::BufferSizeMode,
     /// Storage type of the buffer memory.
     pub storage: BufferStorageType,
+    /// Format of the texels, needed if the buffer is used as a texel buffer.
+    pub texel_format: Option<image::ImageFormat>,
 }
 
 /// Types of memory that a buffer can be backed by.
@@ -300,6 +324,8 @@ pub enum BufferReadType {
     Uniform,
     /// Same as `Uniform` but uses an image as the backing storage.
     UniformTexel,
+    /// Source of parameters for indirect draw or dispatch commands.
+    Indirect,
 }
 
 /// Ways a resource can be used with write-access.
@@ -66,6 +66,22 @@ pub enum CompileError {
         /// Pass in which the resource has been moved before.
         prev_move: PassId,
     },
+    /// An image is sized relative to a resource that is not an image.
+    InvalidSizeReference {
+        /// Name of the image resource.
+        res: ResourceName,
+        /// Name of the resource used as the size reference.
+        referenced: ResourceName,
+        /// Pass in which the image is created.
+        pass: PassId,
+    },
+    /// The size of an image depends on itself through a chain of relative sizes.
+    CyclicSizeReference {
+        /// Name of the image resource.
+        res: ResourceName,
+        /// Pass in which the image is created.
+        pass: PassId,
+    },
 }
 
 impl CompileError {
@@ -121,6 +137,27 @@ impl CompileError {
                 pass_name,
                 prev_move_pass,)
             }
+            CompileError::InvalidSizeReference {
+                res,
+                referenced,
+                pass,
+            } => {
+                let pass_name = pass_names[pass.0].clone();
+
+                format!(
+                    "Resource \"{}\" in pass \"{}\" is sized relative to \"{}\", \
+                     which is not an image resource.",
+                    res, pass_name, referenced,
+                )
+            }
+            CompileError::CyclicSizeReference { res, pass } => {
+                let pass_name = pass_names[pass.0].clone();
+
+                format!(
+                    "The size of resource \"{}\" in pass \"{}\" depends on itself.",
+                    res, pass_name,
+                )
+            }
         }
     }
 }
@@ -183,6 +220,9 @@ pub(crate) fn compile_graph(
     // replace all resource names with IDs.
     let resolved = resolve_input(input, &mut errors);
 
+    // images sized relative to other images need valid references.
+    validate_size_references(&resolved, &mut errors);
+
     // replace target names with IDs
     let targets = builder
         .targets
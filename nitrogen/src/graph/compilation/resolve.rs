@@ -92,26 +92,59 @@ impl GraphWithNamesResolved {
         None
     }
 
-    pub(crate) fn is_resource_context_dependent(&self, id: ResourceId) -> bool {
+    /// Find the resource whose size an image resource is relative to.
+    pub(crate) fn size_dependency(&self, id: ResourceId) -> Option<ResourceId> {
         use crate::image;
 
-        let (_id, info) = if let Some((id, info)) = self.create_info(id) {
-            (id, info)
-        } else {
-            return false;
-        };
+        let (_id, info) = self.create_info(id)?;
 
         match info {
-            ResourceCreateInfo::Image(img_info) => match &img_info {
-                ImageInfo::BackbufferRead { .. } => false,
-                ImageInfo::Create(create) => match create.size_mode {
-                    image::ImageSizeMode::ContextRelative { .. } => true,
-                    image::ImageSizeMode::Absolute { .. } => false,
-                },
+            ResourceCreateInfo::Image(ImageInfo::Create(create)) => match &create.size_mode {
+                image::ImageSizeMode::RelativeTo { resource, .. } => {
+                    let id = self.name_lookup.get(resource)?;
+                    self.moved_from(*id)
+                }
+                _ => None,
             },
-            ResourceCreateInfo::Buffer(_buf) => false,
-            ResourceCreateInfo::Virtual => false,
+            _ => None,
+        }
+    }
+
+    pub(crate) fn is_resource_context_dependent(&self, id: ResourceId) -> bool {
+        use crate::image;
+
+        // Images can be sized relative to other images, so the whole chain of size dependencies
+        // has to be followed. Cycles are reported during compilation, here they just end the walk.
+        let mut visited = BTreeSet::new();
+        let mut next = Some(id);
+
+        while let Some(id) = next {
+            if !visited.insert(id) {
+                return false;
+            }
+
+            let (id, info) = if let Some((id, info)) = self.create_info(id) {
+                (id, info)
+            } else {
+                return false;
+            };
+
+            next = match info {
+                ResourceCreateInfo::Image(img_info) => match &img_info {
+                    ImageInfo::BackbufferRead { .. } => return false,
+                    ImageInfo::Create(create) => match create.size_mode {
+                        image::ImageSizeMode::ContextRelative { .. } => return true,
+                        image::ImageSizeMode::ReferenceRelative { .. } => return true,
+                        image::ImageSizeMode::Absolute { .. } => return false,
+                        image::ImageSizeMode::RelativeTo { .. } => self.size_dependency(id),
+                    },
+                },
+                ResourceCreateInfo::Buffer(buf) => return buf.size_mode.is_context_relative(),
+                ResourceCreateInfo::Virtual => return false,
+            };
         }
+
+        false
     }
 
     pub(crate) fn pass_dependency(&self, id: PassId) -> PassDependency {
@@ -395,3 +428,230 @@ pub(crate) fn resolve_input(
         pass_writes,
     }
 }
+
+/// Check that all images which are sized relative to other resources reference valid images
+/// and that there are no cycles in those references.
+pub(crate) fn validate_size_references(
+    resolved: &GraphWithNamesResolved,
+    errors: &mut Vec<CompileError>,
+) {
+    use crate::image::ImageSizeMode;
+
+    for (id, info) in &resolved.infos {
+        let pass = resolved.defines[id];
+
+        let resource = match info {
+            ResourceCreateInfo::Image(ImageInfo::Create(create)) => match &create.size_mode {
+                ImageSizeMode::RelativeTo { resource, .. } => resource,
+                _ => continue,
+            },
+            _ => continue,
+        };
+
+        let res_name = resolved
+            .name_lookup
+            .iter()
+            .find(|(_, res)| *res == id)
+            .map(|(name, _)| name.clone())
+            .unwrap_or_else(|| resource.clone());
+
+        let referenced = match resolved.name_lookup.get(resource) {
+            Some(id) => resolved.moved_from(*id),
+            None => {
+                errors.push(CompileError::ReferencedInvalidResource {
+                    res: resource.clone(),
+                    pass,
+                });
+                continue;
+            }
+        };
+
+        let is_image = referenced
+            .and_then(|id| resolved.infos.get(&id))
+            .map(|info| ResourceType::from(info) == ResourceType::Image)
+            .unwrap_or(false);
+
+        if !is_image {
+            errors.push(CompileError::InvalidSizeReference {
+                res: res_name,
+                referenced: resource.clone(),
+                pass,
+            });
+            continue;
+        }
+
+        // walk the chain of references, if we end up where we started there is a cycle.
+        let mut visited = BTreeSet::new();
+        let mut next = referenced;
+
+        while let Some(next_id) = next {
+            if next_id == *id {
+                errors.push(CompileError::CyclicSizeReference {
+                    res: res_name.clone(),
+                    pass,
+                });
+                break;
+            }
+
+            if !visited.insert(next_id) {
+                break;
+            }
+
+            next = resolved.size_dependency(next_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::ImageCreateInfo;
+    use crate::image::{ImageFormat, ImageSizeMode};
+
+    fn image(size_mode: ImageSizeMode) -> ResourceCreateInfo {
+        ResourceCreateInfo::Image(ImageInfo::Create(ImageCreateInfo {
+            format: ImageFormat::RgbaUnorm,
+            size_mode,
+        }))
+    }
+
+    fn relative_to(resource: &'static str) -> ResourceCreateInfo {
+        image(ImageSizeMode::RelativeTo {
+            resource: resource.into(),
+            width: 0.5,
+            height: 0.5,
+        })
+    }
+
+    fn resolve(
+        creates: Vec<(&'static str, ResourceCreateInfo)>,
+        moves: Vec<(&'static str, &'static str)>,
+    ) -> (GraphWithNamesResolved, Vec<CompileError>) {
+        let mut input = GraphInput::default();
+
+        input.pass_types.insert(PassId(0), PassType::Graphics);
+        input.resource_creates.insert(
+            PassId(0),
+            creates
+                .into_iter()
+                .map(|(name, info)| (name.into(), info))
+                .collect(),
+        );
+
+        input.pass_types.insert(PassId(1), PassType::Graphics);
+        input.resource_moves.insert(
+            PassId(1),
+            moves
+                .into_iter()
+                .map(|(new, old)| (new.into(), old.into()))
+                .collect(),
+        );
+
+        let mut errors = vec![];
+        let resolved = resolve_input(input, &mut errors);
+        validate_size_references(&resolved, &mut errors);
+
+        (resolved, errors)
+    }
+
+    fn id(resolved: &GraphWithNamesResolved, name: &'static str) -> ResourceId {
+        resolved.name_lookup[&ResourceName::from(name)]
+    }
+
+    #[test]
+    fn chained_relative_sizes() {
+        let (resolved, errors) = resolve(
+            vec![
+                (
+                    "base",
+                    image(ImageSizeMode::Absolute {
+                        width: 64,
+                        height: 32,
+                    }),
+                ),
+                ("half", relative_to("base")),
+                ("quarter", relative_to("half_moved")),
+                (
+                    "context",
+                    image(ImageSizeMode::ContextRelative {
+                        width: 1.0,
+                        height: 1.0,
+                    }),
+                ),
+                ("context_half", relative_to("context")),
+            ],
+            vec![("half_moved", "half")],
+        );
+
+        assert!(errors.is_empty());
+
+        let base = id(&resolved, "base");
+        let half = id(&resolved, "half");
+        let quarter = id(&resolved, "quarter");
+
+        // references through moves end up at the resource that was originally created.
+        assert_eq!(resolved.size_dependency(quarter), Some(half));
+        assert_eq!(resolved.size_dependency(half), Some(base));
+        assert_eq!(resolved.size_dependency(base), None);
+
+        assert!(!resolved.is_resource_context_dependent(quarter));
+        assert!(resolved.is_resource_context_dependent(id(&resolved, "context_half")));
+    }
+
+    #[test]
+    fn cyclic_size_references() {
+        let (resolved, errors) = resolve(
+            vec![
+                ("a", relative_to("b")),
+                ("b", relative_to("a")),
+                ("c", relative_to("c")),
+            ],
+            vec![],
+        );
+
+        let cyclic = errors
+            .iter()
+            .filter(|err| match err {
+                CompileError::CyclicSizeReference { .. } => true,
+                _ => false,
+            })
+            .count();
+
+        assert_eq!(errors.len(), 3);
+        assert_eq!(cyclic, 3);
+
+        // walking a cycle terminates.
+        assert!(!resolved.is_resource_context_dependent(id(&resolved, "a")));
+    }
+
+    #[test]
+    fn invalid_size_references() {
+        let (_, errors) = resolve(
+            vec![
+                ("missing", relative_to("does_not_exist")),
+                ("virtual", ResourceCreateInfo::Virtual),
+                ("not_an_image", relative_to("virtual")),
+            ],
+            vec![],
+        );
+
+        assert_eq!(errors.len(), 2);
+
+        match &errors[0] {
+            CompileError::ReferencedInvalidResource { res, .. } => {
+                assert_eq!(res, "does_not_exist")
+            }
+            err => panic!("unexpected error {:?}", err),
+        }
+
+        match &errors[1] {
+            CompileError::InvalidSizeReference {
+                res, referenced, ..
+            } => {
+                assert_eq!(res, "not_an_image");
+                assert_eq!(referenced, "virtual");
+            }
+            err => panic!("unexpected error {:?}", err),
+        }
+    }
+}
@@ -105,6 +105,9 @@ fn derive_pass(compiled: &CompiledGraph, pass: PassId, usages: &mut ResourceUsag
                     BufferReadType::UniformTexel => {
                         usage |= BUsage::UNIFORM_TEXEL;
                     }
+                    BufferReadType::Indirect => {
+                        usage |= BUsage::INDIRECT;
+                    }
                 }
 
                 usages.buffer.insert(origin, usage);
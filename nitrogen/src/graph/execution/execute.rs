@@ -7,7 +7,8 @@ use super::*;
 use crate::graph::resolve::GraphWithNamesResolved;
 
 use crate::graph::{
-    BufferWriteType, ImageReadType, ImageWriteType, ResourceReadType, ResourceWriteType,
+    BufferReadType, BufferWriteType, ImageReadType, ImageWriteType, ResourceReadType,
+    ResourceWriteType,
 };
 
 use gfx::Device;
@@ -220,7 +221,41 @@ unsafe fn write_pass_descriptor_set(
                         }
                     }
                 }
-                ResourceReadType::Buffer(_buf) => unimplemented!(),
+                ResourceReadType::Buffer(BufferReadType::Indirect) => {
+                    // indirect buffers are used by commands, not descriptor sets
+                    SmallVec::new()
+                }
+                ResourceReadType::Buffer(buf) => {
+                    let buf_handle = res.buffers[rid];
+                    let buffer = buffer_storage.raw(buf_handle).unwrap();
+
+                    let descriptor = match buf {
+                        BufferReadType::Storage | BufferReadType::Uniform => {
+                            gfx::pso::Descriptor::Buffer(buffer.buffer.raw(), None..None)
+                        }
+                        BufferReadType::UniformTexel => {
+                            let view = buffer_storage.view_raw(res.buffer_views[rid]).unwrap();
+                            gfx::pso::Descriptor::UniformTexelBuffer(&view.view)
+                        }
+                        BufferReadType::StorageTexel => {
+                            let view = buffer_storage.view_raw(res.buffer_views[rid]).unwrap();
+                            gfx::pso::Descriptor::StorageTexelBuffer(&view.view)
+                        }
+                        BufferReadType::Indirect => unreachable!(),
+                    };
+
+                    let desc = gfx::pso::DescriptorSetWrite {
+                        set,
+                        binding: u32::from(*binding),
+                        array_offset: 0,
+                        descriptors: std::iter::once(descriptor),
+                    };
+
+                    let mut res: SmallVec<[_; 2]> = SmallVec::new();
+                    res.push(desc);
+
+                    res
+                }
                 ResourceReadType::Virtual => {
                     // Nothing to do...
                     SmallVec::new()
@@ -249,7 +284,18 @@ unsafe fn write_pass_descriptor_set(
                                 None..None,
                             )),
                         }),
-                        _ => unimplemented!(),
+                        BufferWriteType::StorageTexel => {
+                            let view = buffer_storage.view_raw(res.buffer_views[rid]).unwrap();
+
+                            Some(gfx::pso::DescriptorSetWrite {
+                                set,
+                                binding: u32::from(*binding),
+                                array_offset: 0,
+                                descriptors: std::iter::once(
+                                    gfx::pso::Descriptor::StorageTexelBuffer(&view.view),
+                                ),
+                            })
+                        }
                     }
                 }
                 ResourceWriteType::Image(img) => {
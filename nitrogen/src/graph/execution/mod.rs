@@ -6,26 +6,36 @@ mod derive;
 mod execute;
 mod graph;
 mod prepare;
+mod validate;
 
 pub(crate) use self::derive::*;
 pub(crate) use self::execute::*;
 pub(crate) use self::graph::*;
 pub(crate) use self::prepare::*;
+pub(crate) use self::validate::validate_pipeline;
 
 pub use self::prepare::PrepareError;
+pub use self::validate::ShaderInterfaceError;
 
 use super::{PassId, ResourceId, Storages};
 use crate::resources::{
-    buffer::BufferHandle, image::ImageHandle, pipeline::PipelineHandle,
-    render_pass::RenderPassHandle, sampler::SamplerHandle,
+    buffer::{BufferHandle, BufferViewHandle},
+    image::ImageHandle,
+    pipeline::PipelineHandle,
+    render_pass::RenderPassHandle,
+    sampler::SamplerHandle,
+    shader::{ShaderHandle, ShaderStorage},
 };
 use crate::types;
 
+use smallvec::SmallVec;
+
 use crate::submit_group::ResourceList;
 
 use std::collections::{BTreeMap, HashMap, HashSet};
 
 use crate::graph::pass::dispatcher::ResourceRefError;
+use crate::graph::pass::PushConstantError;
 use crate::graph::pass::{ComputePipelineInfo, GraphicsPipelineInfo};
 use crate::graph::ResourceName;
 use crate::resources::material::MaterialInstanceHandle;
@@ -46,6 +56,9 @@ pub enum GraphExecError {
 
     #[display(fmt = "Attempted to use a graph resource in an invalid way: {:?}", _0)]
     ResourceRefError(ResourceRefError),
+
+    #[display(fmt = "Invalid push constant upload: {}", _0)]
+    PushConstantError(PushConstantError),
 }
 
 impl std::error::Error for GraphExecError {}
@@ -59,6 +72,89 @@ pub(crate) struct ResourceUsages {
 #[derive(Debug)]
 pub(crate) struct PipelineResources {
     pub(crate) pipeline_handle: PipelineHandle,
+
+    /// shaders used by the pipeline and their versions at creation time.
+    pub(crate) shaders: SmallVec<[(ShaderHandle, u64); 2]>,
+
+    /// number of the last graph execution that used the pipeline.
+    pub(crate) last_used: u64,
+}
+
+impl PipelineResources {
+    pub(crate) fn new<I>(
+        pipeline_handle: PipelineHandle,
+        shader_storage: &ShaderStorage,
+        shaders: I,
+    ) -> Self
+    where
+        I: IntoIterator<Item = ShaderHandle>,
+    {
+        let mut tracked = SmallVec::<[(ShaderHandle, u64); 2]>::new();
+
+        for handle in shaders {
+            if tracked.iter().any(|(tracked, _)| *tracked == handle) {
+                continue;
+            }
+
+            if let Some(version) = shader_storage.version(handle) {
+                tracked.push((handle, version));
+            }
+        }
+
+        PipelineResources {
+            pipeline_handle,
+            shaders: tracked,
+            last_used: 0,
+        }
+    }
+
+    /// Check if any of the shaders was replaced since the pipeline was created.
+    pub(crate) fn is_outdated(&self, shader_storage: &ShaderStorage) -> bool {
+        self.shaders.iter().any(|(handle, version)| {
+            // destroyed shaders don't affect pipelines that were already created.
+            shader_storage
+                .version(*handle)
+                .map(|current| current != *version)
+                .unwrap_or(false)
+        })
+    }
+}
+
+/// Statistics about the creation of pipelines in a graph.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PipelineStats {
+    /// Number of pipelines created ahead of time from the configurations listed by passes.
+    pub prewarmed: usize,
+
+    /// Number of pipeline lookups during graph execution which found an existing pipeline.
+    pub hits: usize,
+
+    /// Number of pipelines created during graph execution.
+    ///
+    /// A non-zero number means some configurations used by passes are missing in their
+    /// `configs()` list, or that pipelines got evicted and had to be recreated.
+    pub misses: usize,
+
+    /// Number of pipelines destroyed because they exceeded the [`PipelineBudget`].
+    ///
+    /// [`PipelineBudget`]: ./struct.PipelineBudget.html
+    pub evictions: usize,
+}
+
+/// Limits for the number of pipelines kept alive by the passes of a graph.
+///
+/// Passes which use many different configurations, for example derived from quality settings,
+/// would otherwise keep all their pipelines until the graph is destroyed. Evicted pipelines get
+/// recreated when they are used again.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PipelineBudget {
+    /// Maximum number of pipelines per pass, the least recently used pipelines get evicted first.
+    pub max_per_pass: Option<usize>,
+
+    /// Evict pipelines which were not used in this many graph executions.
+    ///
+    /// A value of `0` evicts all pipelines before every execution.
+    pub max_unused_executions: Option<u64>,
 }
 
 #[derive(Debug, Default)]
@@ -68,9 +164,81 @@ pub(crate) struct PassResources {
     pub(crate) pass_material: HashMap<PassId, crate::material::MaterialHandle>,
     pub(crate) compute_pipelines: HashMap<PassId, HashMap<ComputePipelineInfo, PipelineResources>>,
     pub(crate) graphic_pipelines: HashMap<PassId, HashMap<GraphicsPipelineInfo, PipelineResources>>,
+
+    /// number of the current (or last) graph execution.
+    pub(crate) executions: u64,
+
+    pub(crate) pipeline_stats: PipelineStats,
 }
 
 impl PassResources {
+    /// Destroy all pipelines which use replaced shaders.
+    ///
+    /// The pipelines get recreated the next time they are used.
+    pub(crate) fn release_outdated_pipelines(
+        &mut self,
+        res_list: &mut ResourceList,
+        storages: &Storages,
+    ) {
+        let shader_storage = storages.shader.borrow();
+        let mut pipeline_storage = storages.pipeline.borrow_mut();
+
+        let mut outdated = SmallVec::<[PipelineHandle; 4]>::new();
+
+        for pipes in self.compute_pipelines.values_mut() {
+            pipes.retain(|_, res| {
+                if res.is_outdated(&*shader_storage) {
+                    outdated.push(res.pipeline_handle);
+                    false
+                } else {
+                    true
+                }
+            });
+        }
+
+        for pipes in self.graphic_pipelines.values_mut() {
+            pipes.retain(|_, res| {
+                if res.is_outdated(&*shader_storage) {
+                    outdated.push(res.pipeline_handle);
+                    false
+                } else {
+                    true
+                }
+            });
+        }
+
+        pipeline_storage.destroy(res_list, outdated);
+    }
+
+    /// Destroy the pipelines which exceed the budget.
+    ///
+    /// Returns the number of evicted pipelines.
+    pub(crate) fn evict_pipelines(
+        &mut self,
+        res_list: &mut ResourceList,
+        storages: &Storages,
+        budget: &PipelineBudget,
+    ) -> usize {
+        let mut evicted = SmallVec::<[PipelineHandle; 4]>::new();
+
+        for pipes in self.compute_pipelines.values_mut() {
+            evict_pipelines(pipes, self.executions, budget, &mut evicted);
+        }
+
+        for pipes in self.graphic_pipelines.values_mut() {
+            evict_pipelines(pipes, self.executions, budget, &mut evicted);
+        }
+
+        let count = evicted.len();
+
+        // destruction is delayed by the resource list, so pipelines still in flight stay valid.
+        storages.pipeline.borrow_mut().destroy(res_list, evicted);
+
+        self.pipeline_stats.evictions += count;
+
+        count
+    }
+
     pub(crate) fn release(self, res_list: &mut ResourceList, storages: &mut Storages) {
         storages
             .render_pass
@@ -93,6 +261,47 @@ impl PassResources {
     }
 }
 
+fn evict_pipelines<K>(
+    pipes: &mut HashMap<K, PipelineResources>,
+    executions: u64,
+    budget: &PipelineBudget,
+    evicted: &mut SmallVec<[PipelineHandle; 4]>,
+) where
+    K: Clone + Eq + std::hash::Hash,
+{
+    if let Some(max_unused) = budget.max_unused_executions {
+        pipes.retain(|_, res| {
+            if executions.saturating_sub(res.last_used) >= max_unused {
+                evicted.push(res.pipeline_handle);
+                false
+            } else {
+                true
+            }
+        });
+    }
+
+    if let Some(max) = budget.max_per_pass {
+        if pipes.len() <= max {
+            return;
+        }
+
+        let excess = pipes.len() - max;
+
+        let mut by_age = pipes
+            .iter()
+            .map(|(key, res)| (res.last_used, key.clone()))
+            .collect::<Vec<_>>();
+
+        by_age.sort_by_key(|(last_used, _)| *last_used);
+
+        for (_, key) in by_age.into_iter().take(excess) {
+            if let Some(res) = pipes.remove(&key) {
+                evicted.push(res.pipeline_handle);
+            }
+        }
+    }
+}
+
 #[derive(Debug, Default)]
 pub(crate) struct GraphResources {
     pub(crate) exec_context: Option<super::ExecutionContext>,
@@ -105,6 +314,8 @@ pub(crate) struct GraphResources {
     pub(crate) images: HashMap<ResourceId, ImageHandle>,
     samplers: HashMap<ResourceId, SamplerHandle>,
     pub(crate) buffers: HashMap<ResourceId, BufferHandle>,
+    /// Views of texel buffers, destroyed together with their buffers.
+    pub(crate) buffer_views: HashMap<ResourceId, BufferViewHandle>,
 }
 
 impl GraphResources {
@@ -192,3 +403,62 @@ impl Backbuffer {
         Some(map)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::storage::Handle;
+
+    fn pipelines(last_used: &[u64]) -> HashMap<usize, PipelineResources> {
+        last_used
+            .iter()
+            .enumerate()
+            .map(|(i, last_used)| {
+                let res = PipelineResources {
+                    pipeline_handle: Handle::new(i, 0),
+                    shaders: SmallVec::new(),
+                    last_used: *last_used,
+                };
+                (i, res)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn evict_unused() {
+        let mut pipes = pipelines(&[2, 7, 9, 10]);
+        let mut evicted = SmallVec::new();
+
+        let budget = PipelineBudget {
+            max_per_pass: None,
+            max_unused_executions: Some(3),
+        };
+
+        evict_pipelines(&mut pipes, 10, &budget, &mut evicted);
+
+        assert_eq!(evicted.len(), 2);
+        assert!(pipes.contains_key(&2));
+        assert!(pipes.contains_key(&3));
+    }
+
+    #[test]
+    fn evict_least_recently_used() {
+        let mut pipes = pipelines(&[5, 1, 4, 3]);
+        let mut evicted = SmallVec::new();
+
+        let budget = PipelineBudget {
+            max_per_pass: Some(2),
+            max_unused_executions: None,
+        };
+
+        evict_pipelines(&mut pipes, 5, &budget, &mut evicted);
+
+        assert_eq!(evicted.len(), 2);
+        assert!(pipes.contains_key(&0));
+        assert!(pipes.contains_key(&2));
+
+        // within budget, nothing happens.
+        evict_pipelines(&mut pipes, 5, &budget, &mut evicted);
+        assert_eq!(evicted.len(), 2);
+    }
+}
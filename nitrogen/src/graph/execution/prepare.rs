@@ -9,8 +9,8 @@ use gfx;
 
 use crate::graph::{
     BufferReadType, BufferStorageType, BufferWriteType, ExecutionContext, Graph,
-    GraphWithNamesResolved, ImageInfo, ImageReadType, ImageWriteType, ResourceCreateInfo,
-    ResourceReadType, ResourceWriteType,
+    GraphWithNamesResolved, ImageInfo, ImageReadType, ImageWriteType, Primitive,
+    ResourceCreateInfo, ResourceReadType, ResourceWriteType,
 };
 
 use crate::resources::{image, sampler};
@@ -22,12 +22,15 @@ use smallvec::SmallVec;
 
 use crate::graph::builder::PassType;
 use crate::graph::compilation::CompiledGraph;
-use crate::graph::ResourceName;
+use crate::graph::pass::Specialization;
+use crate::graph::{ReferenceName, ResourceName};
 use crate::resources::buffer::BufferError;
 use crate::resources::image::ImageError;
-use crate::resources::material::MaterialStorage;
+use crate::resources::material::{MaterialParameter, MaterialStorage};
 use crate::resources::pipeline::PipelineError;
 use crate::resources::render_pass::RenderPassError;
+use crate::resources::shader::{ShaderHandle, ShaderStages, ShaderStorage};
+use crate::resources::shader_variant::ShaderVariantError;
 use std::collections::BTreeMap;
 
 /// Errors that can occur when trying to prepare resources for a graph execution.
@@ -43,12 +46,30 @@ pub enum PrepareError {
     #[display(fmt = "Pipeline could not be created because a mandatory shader handle is invalid")]
     InvalidShaderHandle,
 
+    #[display(fmt = "Shader variant could not be resolved: {}", _0)]
+    InvalidShaderVariant(ShaderVariantError),
+
     #[display(fmt = "Resource {:?} is referenced which is invalid", _0)]
     InvalidResource(ResourceId),
 
     #[display(fmt = "Backbuffer resource \"{}\" does not exist", _0)]
     InvalidBackbufferResource(ResourceName),
 
+    #[display(fmt = "Reference size \"{}\" is not set in the execution context", _0)]
+    InvalidReferenceSize(ReferenceName),
+
+    #[display(fmt = "Tessellation shaders have to be used together with a patch list primitive")]
+    InvalidTessellationSetup,
+
+    #[display(fmt = "Push constant range {:?} is not aligned to 4 bytes", _0)]
+    InvalidPushConstantRange(std::ops::Range<u32>),
+
+    #[display(
+        fmt = "Buffer {:?} is used as a texel buffer but has no texel format",
+        _0
+    )]
+    MissingTexelFormat(ResourceId),
+
     #[display(fmt = "Image {:?} was not created yet. Bug?", _0)]
     InvalidImageResource(ResourceId),
 
@@ -75,6 +96,9 @@ pub enum PrepareError {
 
     #[display(fmt = "Error creating a buffer: {}", _0)]
     BufferError(BufferError),
+
+    #[display(fmt = "Pipeline does not match its shaders: {}", _0)]
+    ShaderInterfaceError(ShaderInterfaceError),
 }
 
 impl std::error::Error for PrepareError {}
@@ -130,7 +154,8 @@ pub(crate) unsafe fn prepare_resources(
 
             if create {
                 create_resource(
-                    device, storages, res_list, usages, res, backbuffer, *res_id, info, context,
+                    device, storages, res_list, resolved, usages, res, backbuffer, *res_id, info,
+                    context,
                 )?;
             }
         }
@@ -271,7 +296,7 @@ unsafe fn create_render_pass(
             .filter_map(|(res, _ty, binding)| {
                 let (_origin, info) = resolved_graph.create_info(*res)?;
 
-                let format = match info {
+                let format: gfx::format::Format = match info {
                     ResourceCreateInfo::Image(ImageInfo::Create(img)) => img.format.into(),
                     ResourceCreateInfo::Image(ImageInfo::BackbufferRead { format, .. }) => *format,
                     _ => unreachable!(),
@@ -288,7 +313,13 @@ unsafe fn create_render_pass(
                         store: gfx::pass::AttachmentStoreOp::Store,
                     };
 
-                    let stencil = gfx::pass::AttachmentOps::DONT_CARE;
+                    // stencil values have to survive the pass, otherwise masks written in one
+                    // pass can't be tested against in later ones.
+                    let stencil = if format.is_stencil() {
+                        ops
+                    } else {
+                        gfx::pass::AttachmentOps::DONT_CARE
+                    };
 
                     (ops, stencil)
                 };
@@ -318,7 +349,7 @@ unsafe fn create_render_pass(
 
                         let (_origin, info) = resolved_graph.create_info(*res).unwrap();
 
-                        let format = match info {
+                        let format: gfx::format::Format = match info {
                             ResourceCreateInfo::Image(ImageInfo::Create(img)) => img.format.into(),
                             ResourceCreateInfo::Image(ImageInfo::BackbufferRead {
                                 format, ..
@@ -326,16 +357,24 @@ unsafe fn create_render_pass(
                             _ => unreachable!(),
                         };
 
+                        let ops = gfx::pass::AttachmentOps {
+                            load: gfx::pass::AttachmentLoadOp::Load,
+                            store: gfx::pass::AttachmentStoreOp::DontCare,
+                        };
+
+                        let stencil = if format.is_stencil() {
+                            ops
+                        } else {
+                            gfx::pass::AttachmentOps::DONT_CARE
+                        };
+
                         (
                             u8::max_value(),
                             gfx::pass::Attachment {
                                 format: Some(format),
                                 samples: 1,
-                                ops: gfx::pass::AttachmentOps {
-                                    load: gfx::pass::AttachmentLoadOp::Load,
-                                    store: gfx::pass::AttachmentStoreOp::DontCare,
-                                },
-                                stencil_ops: gfx::pass::AttachmentOps::DONT_CARE,
+                                ops,
+                                stencil_ops: stencil,
                                 layouts: gfx::image::Layout::General..gfx::image::Layout::General,
                             },
                         )
@@ -540,17 +579,109 @@ unsafe fn create_framebuffer(
     Ok((framebuffer, extent))
 }
 
-pub(crate) unsafe fn create_pipeline_compute(
+fn validate_push_constant_range(range: &std::ops::Range<u32>) -> Result<(), PrepareError> {
+    if range.start % 4 != 0 || range.end % 4 != 0 || range.start >= range.end {
+        Err(PrepareError::InvalidPushConstantRange(range.clone()))
+    } else {
+        Ok(())
+    }
+}
+
+/// Make sure a pipeline for `info` exists in the resources of a compute pass.
+///
+/// Returns `true` if the pipeline had to be created.
+pub(crate) unsafe fn ensure_pipeline_compute(
+    device: &DeviceContext,
+    storages: &Storages,
+    pass_res: &mut PassResources,
+    pass: PassId,
+    info: &ComputePipelineInfo,
+) -> Result<bool, PrepareError> {
+    let pass_mat = pass_res.pass_material.get(&pass).cloned();
+    let executions = pass_res.executions;
+    let pipelines = pass_res.compute_pipelines.entry(pass).or_default();
+
+    if let Some(res) = pipelines.get_mut(info) {
+        res.last_used = executions;
+        return Ok(false);
+    }
+
+    let mut pipe_res = create_pipeline_compute(device, storages, pass, pass_mat, info)?;
+    pipe_res.last_used = executions;
+    pipelines.insert(info.clone(), pipe_res);
+
+    Ok(true)
+}
+
+/// Make sure a pipeline for `info` exists in the resources of a graphics pass.
+///
+/// Returns `true` if the pipeline had to be created.
+pub(crate) unsafe fn ensure_pipeline_graphics(
+    device: &DeviceContext,
+    storages: &Storages,
+    pass_res: &mut PassResources,
+    pass: PassId,
+    info: &GraphicsPipelineInfo,
+) -> Result<bool, PrepareError> {
+    let render_pass = *pass_res
+        .render_passes
+        .get(&pass)
+        .ok_or(PrepareError::InvalidRenderPass)?;
+
+    let pass_mat = pass_res.pass_material.get(&pass).cloned();
+    let executions = pass_res.executions;
+    let pipelines = pass_res.graphic_pipelines.entry(pass).or_default();
+
+    if let Some(res) = pipelines.get_mut(info) {
+        res.last_used = executions;
+        return Ok(false);
+    }
+
+    let mut pipe_res =
+        create_pipeline_graphics(device, storages, pass, pass_mat, info, render_pass)?;
+    pipe_res.last_used = executions;
+    pipelines.insert(info.clone(), pipe_res);
+
+    Ok(true)
+}
+
+unsafe fn create_pipeline_compute(
     device: &DeviceContext,
     storages: &Storages,
     _pass: PassId,
     pass_material: Option<MaterialHandle>,
     info: &ComputePipelineInfo,
-) -> Result<PipelineHandle, PrepareError> {
+) -> Result<PipelineResources, PrepareError> {
     let material_storage = storages.material.borrow();
-    let shader_storage = storages.shader.borrow();
+    let mut shader_storage = storages.shader.borrow_mut();
     let mut pipeline_storage = storages.pipeline.borrow_mut();
 
+    let shader_handle = resolve_shader(&mut *shader_storage, &info.shader)?;
+
+    // replacing the shader a variant was compiled from outdates the pipeline as well.
+    let dependencies = [shader_handle, info.shader.handle.into()];
+
+    let key = crate::pipeline::ComputePipelineKey {
+        info: info.clone(),
+        pass_material: pass_material_parameters(&*material_storage, pass_material),
+        shader_versions: shader_versions(&*shader_storage, &dependencies)?,
+    };
+
+    // another pass or graph might have created the same pipeline already.
+    if let Some(pipeline_handle) = pipeline_storage.acquire_compute(&key) {
+        return Ok(PipelineResources::new(
+            pipeline_handle,
+            &*shader_storage,
+            dependencies.iter().cloned(),
+        ));
+    }
+
+    shader_storage
+        .prepare_module(device, shader_handle)
+        .map_err(PipelineError::from)?;
+
+    let shader_storage = &*shader_storage;
+
     let layouts = create_pipeline_base(&*material_storage, pass_material, &info.materials[..]);
 
     let layouts = layouts
@@ -560,41 +691,104 @@ pub(crate) unsafe fn create_pipeline_compute(
 
     let mut push_constants = SmallVec::<[_; 1]>::new();
     if let Some(range) = &info.push_constant_range {
-        push_constants.push((range.start / 4)..(range.end / 4));
+        validate_push_constant_range(range)?;
+        push_constants.push((gfx::pso::ShaderStageFlags::COMPUTE, range.clone()));
     }
 
-    let shader = shader_storage
-        .raw_compute(info.shader.handle)
-        .ok_or(PrepareError::InvalidShaderHandle)?;
+    let shader_info = shader_info(shader_storage, shader_handle, &info.shader.specialization)?;
+
+    validate_pipeline(
+        &*material_storage,
+        pass_material,
+        &info.materials[..],
+        &[(ShaderStages::COMPUTE, &shader_info)],
+        push_constants.as_slice(),
+        None,
+    )?;
 
     let create_info = crate::pipeline::ComputePipelineCreateInfo {
-        shader: crate::pipeline::ShaderInfo {
-            content: shader.spirv_content.as_slice(),
-            entry: &shader.entry_point,
-            specialization: &info.shader.specialization,
-        },
+        shader: shader_info,
         descriptor_set_layout: &layouts[..],
         push_constants: push_constants.as_slice(),
     };
 
-    let pipeline_handle = pipeline_storage.create_compute_pipeline(device, create_info)?;
+    let pipeline_handle = pipeline_storage.create_compute_pipeline(device, create_info, key)?;
 
-    Ok(pipeline_handle)
+    Ok(PipelineResources::new(
+        pipeline_handle,
+        shader_storage,
+        dependencies.iter().cloned(),
+    ))
 }
 
-pub(crate) unsafe fn create_pipeline_graphics(
+unsafe fn create_pipeline_graphics(
     device: &DeviceContext,
     storages: &Storages,
     _pass: PassId,
     pass_material: Option<MaterialHandle>,
     info: &GraphicsPipelineInfo,
     render_pass: RenderPassHandle,
-) -> Result<PipelineHandle, PrepareError> {
+) -> Result<PipelineResources, PrepareError> {
     use crate::pipeline;
 
     let material_storage = storages.material.borrow();
-    let shader_storage = storages.shader.borrow();
+    let mut shader_storage = storages.shader.borrow_mut();
     let mut pipeline_storage = storages.pipeline.borrow_mut();
+    let render_pass_storage = storages.render_pass.borrow();
+
+    let shaders = &info.shaders;
+
+    // shader variants with defines resolve to separately compiled shaders.
+    let vertex = resolve_shader(&mut *shader_storage, &shaders.vertex)?;
+    let fragment = resolve_optional_shader(&mut *shader_storage, &shaders.fragment)?;
+    let geometry = resolve_optional_shader(&mut *shader_storage, &shaders.geometry)?;
+    let tess_control = resolve_optional_shader(&mut *shader_storage, &shaders.tess_control)?;
+    let tess_evaluation = resolve_optional_shader(&mut *shader_storage, &shaders.tess_evaluation)?;
+
+    let used_shaders: SmallVec<[_; 5]> = std::iter::once(vertex)
+        .chain(fragment)
+        .chain(geometry)
+        .chain(tess_control)
+        .chain(tess_evaluation)
+        .collect();
+
+    // replacing the shader a variant was compiled from outdates the pipeline as well.
+    let dependencies: SmallVec<[ShaderHandle; 10]> = used_shaders
+        .iter()
+        .cloned()
+        .chain(std::iter::once(shaders.vertex.handle.into()))
+        .chain(shaders.fragment.as_ref().map(|s| s.handle.into()))
+        .chain(shaders.geometry.as_ref().map(|s| s.handle.into()))
+        .chain(shaders.tess_control.as_ref().map(|s| s.handle.into()))
+        .chain(shaders.tess_evaluation.as_ref().map(|s| s.handle.into()))
+        .collect();
+
+    let key = pipeline::GraphicsPipelineKey {
+        info: info.clone(),
+        pass_material: pass_material_parameters(&*material_storage, pass_material),
+        shader_versions: shader_versions(&*shader_storage, &dependencies)?,
+        render_pass: render_pass_storage
+            .compat(render_pass)
+            .ok_or(PrepareError::InvalidRenderPass)?
+            .clone(),
+    };
+
+    // another pass or graph might have created the same pipeline already.
+    if let Some(pipeline_handle) = pipeline_storage.acquire_graphics(&key) {
+        return Ok(PipelineResources::new(
+            pipeline_handle,
+            &*shader_storage,
+            dependencies,
+        ));
+    }
+
+    for handle in &used_shaders {
+        shader_storage
+            .prepare_module(device, *handle)
+            .map_err(PipelineError::from)?;
+    }
+
+    let shader_storage = &*shader_storage;
 
     let layouts = create_pipeline_base(&*material_storage, pass_material, &info.materials[..]);
 
@@ -603,52 +797,66 @@ pub(crate) unsafe fn create_pipeline_graphics(
         .map(|(_, data)| data)
         .collect::<Vec<_>>();
 
-    let mut push_constants = SmallVec::<[_; 1]>::new();
-    if let Some(range) = &info.push_constants {
-        push_constants.push((range.start / 4)..(range.end / 4));
+    let mut push_constants = SmallVec::<[_; 2]>::new();
+    for push_range in &info.push_constants {
+        validate_push_constant_range(&push_range.range)?;
+        push_constants.push((push_range.stages.into(), push_range.range.clone()));
     }
 
-    let vertex_shader = {
-        let raw = shader_storage
-            .raw_vertex(info.shaders.vertex.handle)
-            .ok_or(PrepareError::InvalidShaderHandle)?;
+    let vertex_shader = shader_info(shader_storage, vertex, &shaders.vertex.specialization)?;
+    let fragment_shader = optional_shader_info(shader_storage, fragment, &shaders.fragment)?;
+    let geometry_shader = optional_shader_info(shader_storage, geometry, &shaders.geometry)?;
+    let tess_control_shader =
+        optional_shader_info(shader_storage, tess_control, &shaders.tess_control)?;
+    let tess_evaluation_shader =
+        optional_shader_info(shader_storage, tess_evaluation, &shaders.tess_evaluation)?;
+
+    // tessellation needs both shader stages and patches as input, patches can't be used
+    // without tessellation.
+    {
+        let is_patch_list = match info.primitive {
+            Primitive::PatchList(_) => true,
+            _ => false,
+        };
 
-        pipeline::ShaderInfo {
-            content: raw.spirv_content.as_slice(),
-            entry: &raw.entry_point,
-            specialization: &info.shaders.vertex.specialization,
+        let valid = match (&tess_control_shader, &tess_evaluation_shader) {
+            (Some(_), Some(_)) => is_patch_list,
+            (None, None) => !is_patch_list,
+            _ => false,
+        };
+
+        if !valid {
+            return Err(PrepareError::InvalidTessellationSetup);
         }
-    };
+    }
 
-    let fragment_shader = if let Some(sh) = &info.shaders.fragment {
-        let raw = shader_storage
-            .raw_fragment(sh.handle)
-            .ok_or(PrepareError::InvalidShaderHandle)?;
+    {
+        let mut shaders = SmallVec::<[_; 5]>::new();
 
-        let info = pipeline::ShaderInfo {
-            content: raw.spirv_content.as_slice(),
-            entry: &raw.entry_point,
-            specialization: &sh.specialization,
-        };
-        Some(info)
-    } else {
-        None
-    };
+        shaders.push((ShaderStages::VERTEX, &vertex_shader));
 
-    let geometry_shader = if let Some(sh) = &info.shaders.geometry {
-        let raw = shader_storage
-            .raw_geometry(sh.handle)
-            .ok_or(PrepareError::InvalidShaderHandle)?;
+        let optional_shaders = [
+            (ShaderStages::TESS_CONTROL, &tess_control_shader),
+            (ShaderStages::TESS_EVALUATION, &tess_evaluation_shader),
+            (ShaderStages::GEOMETRY, &geometry_shader),
+            (ShaderStages::FRAGMENT, &fragment_shader),
+        ];
 
-        let info = pipeline::ShaderInfo {
-            content: raw.spirv_content.as_slice(),
-            entry: &raw.entry_point,
-            specialization: &sh.specialization,
-        };
-        Some(info)
-    } else {
-        None
-    };
+        for (stage, shader) in &optional_shaders {
+            if let Some(shader) = shader {
+                shaders.push((*stage, shader));
+            }
+        }
+
+        validate_pipeline(
+            &*material_storage,
+            pass_material,
+            &info.materials[..],
+            shaders.as_slice(),
+            push_constants.as_slice(),
+            info.vertex_attrib.as_ref(),
+        )?;
+    }
 
     let vertex_attrib_res = info
         .vertex_attrib
@@ -658,23 +866,33 @@ pub(crate) unsafe fn create_pipeline_graphics(
     let create_info = pipeline::GraphicsPipelineCreateInfo {
         vertex_attribs: vertex_attrib_res,
         primitive: info.primitive,
+        rasterizer: info.rasterizer,
         shader_vertex: vertex_shader,
         shader_fragment: fragment_shader,
         shader_geometry: geometry_shader,
+        shader_tess_control: tess_control_shader,
+        shader_tess_evaluation: tess_evaluation_shader,
         descriptor_set_layout: &layouts[..],
         push_constants: push_constants.as_slice(),
         blend_modes: &info.blend_modes[..],
+        color_masks: &info.color_masks[..],
         depth_mode: info.depth_mode,
+        stencil_mode: info.stencil_mode,
     };
 
     let pipeline_handle = pipeline_storage.create_graphics_pipeline(
         device,
-        &*storages.render_pass.borrow(),
+        &*render_pass_storage,
         render_pass,
         create_info,
+        key,
     )?;
 
-    Ok(pipeline_handle)
+    Ok(PipelineResources::new(
+        pipeline_handle,
+        shader_storage,
+        dependencies,
+    ))
 }
 
 // this attribute is here because clippy keeps complaining, but there is no good way
@@ -684,6 +902,7 @@ unsafe fn create_resource(
     device: &DeviceContext,
     storages: &Storages,
     res_list: &mut ResourceList,
+    resolved: &GraphWithNamesResolved,
     usages: &ResourceUsages,
     res: &mut GraphResources,
     backbuffer: &mut Backbuffer,
@@ -721,7 +940,7 @@ unsafe fn create_resource(
         ResourceCreateInfo::Image(ImageInfo::Create(img)) => {
             // find out the size and kind of the image
 
-            let raw_dim = img.size_mode.absolute(context.reference_size);
+            let raw_dim = image_size(resolved, backbuffer, &*image_storage, context, id)?;
             let dim = image::ImageDimension::D2 {
                 x: raw_dim.0,
                 y: raw_dim.1,
@@ -788,10 +1007,12 @@ unsafe fn create_resource(
         ResourceCreateInfo::Buffer(buf) => {
             let usage = usages.buffer[&id];
 
+            let size = buf.size_mode.absolute(context.reference_size)?;
+
             let buffer = match buf.storage {
                 BufferStorageType::DeviceLocal => {
                     let create_info = crate::buffer::DeviceLocalCreateInfo {
-                        size: buf.size,
+                        size,
                         is_transient: false,
                         usage,
                     };
@@ -800,7 +1021,7 @@ unsafe fn create_resource(
                 }
                 BufferStorageType::HostVisible => {
                     let create_info = crate::buffer::CpuVisibleCreateInfo {
-                        size: buf.size,
+                        size,
                         is_transient: false,
                         usage,
                     };
@@ -811,10 +1032,32 @@ unsafe fn create_resource(
 
             let old_buf = res.buffers.insert(id, buffer);
 
+            // views of the old buffer are destroyed together with it
             if let Some(old_buf) = old_buf {
                 buffer_storage.destroy(res_list, &[old_buf]);
             }
 
+            if usage
+                .intersects(gfx::buffer::Usage::UNIFORM_TEXEL | gfx::buffer::Usage::STORAGE_TEXEL)
+            {
+                let format = buf
+                    .texel_format
+                    .ok_or_else(|| PrepareError::MissingTexelFormat(id))?;
+
+                let view = buffer_storage.view_create(
+                    device,
+                    crate::buffer::BufferViewCreateInfo {
+                        buffer,
+                        format,
+                        range: None..None,
+                    },
+                )?;
+
+                res.buffer_views.insert(id, view);
+            } else {
+                res.buffer_views.remove(&id);
+            }
+
             Ok(())
         }
         ResourceCreateInfo::Virtual => {
@@ -824,6 +1067,143 @@ unsafe fn create_resource(
     }
 }
 
+/// Compute the size of an image resource.
+///
+/// Images can be sized relative to other images, in which case the size of the referenced image
+/// is computed first. Cycles are rejected during graph compilation.
+fn image_size(
+    resolved: &GraphWithNamesResolved,
+    backbuffer: &Backbuffer,
+    image_storage: &image::ImageStorage,
+    context: &ExecutionContext,
+    id: ResourceId,
+) -> Result<(u32, u32), PrepareError> {
+    use crate::image::ImageSizeMode;
+
+    let (id, info) = resolved
+        .create_info(id)
+        .ok_or_else(|| PrepareError::InvalidResource(id))?;
+
+    match info {
+        ResourceCreateInfo::Image(ImageInfo::BackbufferRead { name, .. }) => {
+            let handle = backbuffer
+                .images
+                .get(name)
+                .ok_or_else(|| PrepareError::InvalidBackbufferResource(name.clone()))?;
+
+            let image = image_storage
+                .raw(*handle)
+                .ok_or_else(|| PrepareError::InvalidImageHandle(*handle))?;
+
+            let (x, y, _) = image.dimension.as_triple(1);
+
+            Ok((x, y))
+        }
+        ResourceCreateInfo::Image(ImageInfo::Create(img)) => {
+            let reference = match &img.size_mode {
+                ImageSizeMode::ContextRelative { .. } => context.reference_size,
+                ImageSizeMode::ReferenceRelative { reference, .. } => *context
+                    .reference_sizes
+                    .get(reference)
+                    .ok_or_else(|| PrepareError::InvalidReferenceSize(reference.clone()))?,
+                ImageSizeMode::RelativeTo { .. } => {
+                    let dependency = resolved
+                        .size_dependency(id)
+                        .ok_or_else(|| PrepareError::InvalidResource(id))?;
+
+                    image_size(resolved, backbuffer, image_storage, context, dependency)?
+                }
+                ImageSizeMode::Absolute { .. } => (0, 0),
+            };
+
+            Ok(img.size_mode.absolute(reference))
+        }
+        _ => Err(PrepareError::InvalidResource(id)),
+    }
+}
+
+fn resolve_shader<H>(
+    shader_storage: &mut ShaderStorage,
+    shader: &crate::graph::Shader<H>,
+) -> Result<ShaderHandle, PrepareError>
+where
+    H: Copy + Into<ShaderHandle>,
+{
+    let handle = shader_storage.resolve_variant(shader.handle.into(), &shader.specialization)?;
+    Ok(handle)
+}
+
+fn resolve_optional_shader<H>(
+    shader_storage: &mut ShaderStorage,
+    shader: &Option<crate::graph::Shader<H>>,
+) -> Result<Option<ShaderHandle>, PrepareError>
+where
+    H: Copy + Into<ShaderHandle>,
+{
+    match shader {
+        Some(shader) => Ok(Some(resolve_shader(shader_storage, shader)?)),
+        None => Ok(None),
+    }
+}
+
+/// Versions of the shaders a pipeline depends on.
+///
+/// Variants are recompiled with a fresh version after the shader they were compiled from is
+/// replaced, so the versions of those base shaders have to be part of pipeline keys too.
+fn shader_versions(
+    shader_storage: &ShaderStorage,
+    dependencies: &[ShaderHandle],
+) -> Result<crate::pipeline::ShaderVersions, PrepareError> {
+    dependencies
+        .iter()
+        .map(|handle| shader_storage.version(*handle))
+        .collect::<Option<_>>()
+        .ok_or(PrepareError::InvalidShaderHandle)
+}
+
+fn shader_info<'a>(
+    shader_storage: &'a ShaderStorage,
+    handle: ShaderHandle,
+    specialization: &'a [Specialization],
+) -> Result<crate::pipeline::ShaderInfo<'a>, PrepareError> {
+    let shader = shader_storage
+        .raw_any(handle)
+        .ok_or(PrepareError::InvalidShaderHandle)?;
+
+    let module = shader.module.ok_or(PrepareError::InvalidShaderHandle)?;
+
+    Ok(crate::pipeline::ShaderInfo {
+        interface: shader.interface,
+        module,
+        entry: shader.entry_point,
+        specialization,
+    })
+}
+
+fn optional_shader_info<'a, H>(
+    shader_storage: &'a ShaderStorage,
+    handle: Option<ShaderHandle>,
+    shader: &'a Option<crate::graph::Shader<H>>,
+) -> Result<Option<crate::pipeline::ShaderInfo<'a>>, PrepareError> {
+    match (handle, shader) {
+        (Some(handle), Some(shader)) => Ok(Some(shader_info(
+            shader_storage,
+            handle,
+            &shader.specialization,
+        )?)),
+        _ => Ok(None),
+    }
+}
+
+/// Parameters of the pass material, pipelines with equal parameters have compatible layouts.
+fn pass_material_parameters(
+    material_storage: &MaterialStorage,
+    pass_material: Option<MaterialHandle>,
+) -> Option<Vec<MaterialParameter>> {
+    let material = material_storage.raw(pass_material?)?;
+    Some(material.parameters().to_vec())
+}
+
 unsafe fn create_pipeline_base<'a>(
     material_storage: &'a MaterialStorage,
     pass_material: Option<MaterialHandle>,
@@ -862,72 +1242,62 @@ pub(crate) unsafe fn create_pass_material(
 ) -> Result<Option<MaterialHandle>, PrepareError> {
     use gfx::Device;
 
-    let (core_desc, core_range) = {
+    let core_desc = {
         let reads = graph.pass_reads[&pass]
             .iter()
             .filter(|(_id, _ty, _, _)| match _ty {
                 ResourceReadType::Virtual => false,
                 ResourceReadType::Image(ImageReadType::DepthStencil) => false,
                 ResourceReadType::Image(_) => true,
+                ResourceReadType::Buffer(BufferReadType::Indirect) => false,
                 ResourceReadType::Buffer(_) => true,
             });
 
         let samplers = reads.clone().filter(|(_, _, _, sampler)| sampler.is_some());
 
         let sampler_descriptors =
-            samplers
-                .clone()
-                .map(|(_, _, _, binding)| gfx::pso::DescriptorSetLayoutBinding {
-                    binding: u32::from(binding.unwrap()),
-                    ty: gfx::pso::DescriptorType::Sampler,
-                    count: 1,
-                    stage_flags: gfx::pso::ShaderStageFlags::ALL,
-                    immutable_samplers: false,
-                });
+            samplers.map(|(_, _, _, binding)| gfx::pso::DescriptorSetLayoutBinding {
+                binding: u32::from(binding.unwrap()),
+                ty: gfx::pso::DescriptorType::Sampler,
+                count: 1,
+                stage_flags: gfx::pso::ShaderStageFlags::ALL,
+                immutable_samplers: false,
+            });
 
         // writing to resources that are not color or depth images happens via descriptors as well
         let writes = graph.pass_writes[&pass]
             .iter()
-            .filter(|(_res, ty, _binding)| {
-                match ty {
-                    ResourceWriteType::Buffer(buf) => {
-                        match buf {
-                            BufferWriteType::Storage => true,
-                            // TODO storage texel
-                            _ => false,
-                        }
-                    }
-                    ResourceWriteType::Image(img) => match img {
-                        ImageWriteType::Storage => true,
-                        _ => false,
-                    },
-                }
+            .filter(|(_res, ty, _binding)| match ty {
+                ResourceWriteType::Buffer(_) => true,
+                ResourceWriteType::Image(img) => match img {
+                    ImageWriteType::Storage => true,
+                    _ => false,
+                },
             });
 
         let write_descriptors =
-            writes
-                .clone()
-                .map(|(_res, ty, binding)| gfx::pso::DescriptorSetLayoutBinding {
-                    binding: u32::from(*binding),
-                    ty: match ty {
-                        ResourceWriteType::Image(img) => match img {
-                            ImageWriteType::Storage => gfx::pso::DescriptorType::StorageImage,
-                            _ => unreachable!(),
-                        },
-                        ResourceWriteType::Buffer(buf) => match buf {
-                            BufferWriteType::Storage => gfx::pso::DescriptorType::StorageBuffer,
-                            _ => unimplemented!(),
-                        },
+            writes.map(|(_res, ty, binding)| gfx::pso::DescriptorSetLayoutBinding {
+                binding: u32::from(*binding),
+                ty: match ty {
+                    ResourceWriteType::Image(img) => match img {
+                        ImageWriteType::Storage => gfx::pso::DescriptorType::StorageImage,
+                        _ => unreachable!(),
                     },
-                    count: 1,
-                    stage_flags: gfx::pso::ShaderStageFlags::ALL,
-                    immutable_samplers: false,
-                });
+                    ResourceWriteType::Buffer(buf) => match buf {
+                        BufferWriteType::Storage => gfx::pso::DescriptorType::StorageBuffer,
+                        BufferWriteType::StorageTexel => {
+                            gfx::pso::DescriptorType::StorageTexelBuffer
+                        }
+                    },
+                },
+                count: 1,
+                stage_flags: gfx::pso::ShaderStageFlags::ALL,
+                immutable_samplers: false,
+            });
 
         let descriptors = reads
-            .clone()
-            .map(|(_res, ty, binding, _)| {
-                gfx::pso::DescriptorSetLayoutBinding {
+            .map(
+                |(_res, ty, binding, _)| gfx::pso::DescriptorSetLayoutBinding {
                     binding: u32::from(*binding),
                     ty: match ty {
                         ResourceReadType::Image(img) => match img {
@@ -935,86 +1305,204 @@ pub(crate) unsafe fn create_pass_material(
                             ImageReadType::Storage => gfx::pso::DescriptorType::StorageImage,
                             ImageReadType::DepthStencil => unreachable!(),
                         },
-                        ResourceReadType::Buffer(buf) => {
-                            match buf {
-                                BufferReadType::Uniform => gfx::pso::DescriptorType::UniformBuffer,
-                                BufferReadType::UniformTexel => {
-                                    // TODO test this
-                                    // does this need samplers? I think so. Let's find out!
-                                    gfx::pso::DescriptorType::UniformTexelBuffer
-                                }
-                                BufferReadType::Storage => gfx::pso::DescriptorType::StorageBuffer,
-                                BufferReadType::StorageTexel => {
-                                    //  TODO test this
-                                    // does this need samplers? I think so. Let's find out!
-                                    gfx::pso::DescriptorType::StorageTexelBuffer
-                                }
+                        ResourceReadType::Buffer(buf) => match buf {
+                            BufferReadType::Uniform => gfx::pso::DescriptorType::UniformBuffer,
+                            BufferReadType::UniformTexel => {
+                                gfx::pso::DescriptorType::UniformTexelBuffer
                             }
-                        }
+                            BufferReadType::Storage => gfx::pso::DescriptorType::StorageBuffer,
+                            BufferReadType::StorageTexel => {
+                                gfx::pso::DescriptorType::StorageTexelBuffer
+                            }
+                            BufferReadType::Indirect => unreachable!(),
+                        },
                         ResourceReadType::Virtual => unreachable!(),
                     },
                     count: 1,
                     stage_flags: gfx::pso::ShaderStageFlags::ALL,
                     immutable_samplers: false,
-                }
-            })
+                },
+            )
             .chain(sampler_descriptors)
             .chain(write_descriptors);
 
-        let range = reads
-            .map(|(_, ty, _, _)| {
-                gfx::pso::DescriptorRangeDesc {
-                    ty: match ty {
-                        ResourceReadType::Image(img) => match img {
-                            ImageReadType::Color => gfx::pso::DescriptorType::SampledImage,
-                            ImageReadType::Storage => gfx::pso::DescriptorType::StorageImage,
-                            ImageReadType::DepthStencil => unreachable!(),
-                        },
-                        ResourceReadType::Buffer(buf) => {
-                            match buf {
-                                BufferReadType::Uniform => gfx::pso::DescriptorType::UniformBuffer,
-                                BufferReadType::UniformTexel => {
-                                    // TODO test this
-                                    // does this need samplers? I think so. Let's find out!
-                                    gfx::pso::DescriptorType::UniformTexelBuffer
-                                }
-                                BufferReadType::Storage => gfx::pso::DescriptorType::StorageBuffer,
-                                BufferReadType::StorageTexel => {
-                                    //  TODO test this
-                                    // does this need samplers? I think so. Let's find out!
-                                    gfx::pso::DescriptorType::StorageTexelBuffer
-                                }
-                            }
-                        }
-                        ResourceReadType::Virtual => unreachable!(),
-                    },
-                    count: 1,
-                }
-            })
-            .chain(samplers.map(|_| gfx::pso::DescriptorRangeDesc {
-                ty: gfx::pso::DescriptorType::Sampler,
-                count: 1,
-            }))
-            .chain(writes.map(|(_, ty, _)| gfx::pso::DescriptorRangeDesc {
-                ty: match ty {
-                    ResourceWriteType::Image(img) => match img {
-                        ImageWriteType::Storage => gfx::pso::DescriptorType::StorageImage,
-                        _ => unreachable!(),
-                    },
-                    ResourceWriteType::Buffer(buf) => match buf {
-                        BufferWriteType::Storage => gfx::pso::DescriptorType::StorageBuffer,
-                        _ => unimplemented!(),
-                    },
-                },
-                count: 1,
-            }));
-
-        (descriptors, range)
+        descriptors.collect::<SmallVec<[_; 16]>>()
     };
 
-    let pass_set_layout = device.device.create_descriptor_set_layout(core_desc, &[])?;
+    let pass_set_layout = device
+        .device
+        .create_descriptor_set_layout(core_desc.iter(), &[])?;
+
+    let params = core_desc.iter().map(|desc| (desc.binding, desc.ty));
 
-    let mat = material_storage.create_raw(device, pass_set_layout, core_range, 16);
+    let mat = material_storage.create_raw(device, pass_set_layout, params, 16);
 
     Ok(mat)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::compilation::input::GraphInput;
+    use crate::graph::compilation::resolve::resolve_input;
+    use crate::graph::{ComputePipelineInfo, ImageCreateInfo, PassId};
+    use crate::image::{ImageFormat, ImageSizeMode};
+    use crate::pipeline::ComputePipelineKey;
+    use crate::resources::shader::ShaderInfo;
+
+    fn image(size_mode: ImageSizeMode) -> ResourceCreateInfo {
+        ResourceCreateInfo::Image(ImageInfo::Create(ImageCreateInfo {
+            format: ImageFormat::RgbaUnorm,
+            size_mode,
+        }))
+    }
+
+    #[test]
+    fn relative_image_sizes() {
+        let mut input = GraphInput::default();
+
+        input.pass_types.insert(PassId(0), PassType::Graphics);
+        input.resource_creates.insert(
+            PassId(0),
+            vec![
+                (
+                    "context".into(),
+                    image(ImageSizeMode::ContextRelative {
+                        width: 1.0,
+                        height: 0.5,
+                    }),
+                ),
+                (
+                    "half".into(),
+                    image(ImageSizeMode::RelativeTo {
+                        resource: "context".into(),
+                        width: 0.5,
+                        height: 0.5,
+                    }),
+                ),
+                (
+                    "quarter".into(),
+                    image(ImageSizeMode::RelativeTo {
+                        resource: "half_moved".into(),
+                        width: 0.5,
+                        height: 0.5,
+                    }),
+                ),
+                (
+                    "shadow".into(),
+                    image(ImageSizeMode::ReferenceRelative {
+                        reference: "shadow".into(),
+                        width: 2.0,
+                        height: 2.0,
+                    }),
+                ),
+            ],
+        );
+        input.pass_types.insert(PassId(1), PassType::Graphics);
+        input
+            .resource_moves
+            .insert(PassId(1), vec![("half_moved".into(), "half".into())]);
+
+        let mut errors = vec![];
+        let resolved = resolve_input(input, &mut errors);
+        assert!(errors.is_empty());
+
+        let backbuffer = Backbuffer::new();
+        let images = image::ImageStorage::new();
+        let context = ExecutionContext {
+            reference_size: (400, 300),
+            reference_sizes: BTreeMap::new(),
+        };
+
+        let size = |name: &'static str| {
+            let id = resolved.name_lookup[&ResourceName::from(name)];
+            image_size(&resolved, &backbuffer, &images, &context, id)
+        };
+
+        assert_eq!(size("context").unwrap(), (400, 150));
+        assert_eq!(size("half").unwrap(), (200, 75));
+        assert_eq!(size("half_moved").unwrap(), (200, 75));
+        assert_eq!(size("quarter").unwrap(), (100, 37));
+
+        match size("shadow") {
+            Err(PrepareError::InvalidReferenceSize(name)) => assert_eq!(name, "shadow"),
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+
+    /// A module with a single compute shader entry point named "main".
+    fn compute_module() -> Vec<u8> {
+        let words: [u32; 16] = [
+            // header
+            0x0723_0203,
+            0x0001_0000,
+            0,
+            4,
+            0,
+            // OpEntryPoint GLCompute %1 "main"
+            5 << 16 | 15,
+            5,
+            1,
+            u32::from_le_bytes(*b"main"),
+            0,
+            // %1 = OpFunction %2 None %3
+            5 << 16 | 54,
+            2,
+            1,
+            0,
+            3,
+            // OpFunctionEnd
+            1 << 16 | 56,
+        ];
+
+        words
+            .iter()
+            .flat_map(|word| word.to_le_bytes().to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn replaced_base_shaders_change_keys() {
+        let module = compute_module();
+
+        let info = || ShaderInfo {
+            spirv_content: &module,
+            entry_point: "main".into(),
+        };
+
+        let mut shaders = ShaderStorage::new();
+
+        let base = shaders.create_compute_shader(info()).unwrap();
+        // stands in for a variant compiled from the base shader
+        let variant = shaders.create_compute_shader(info()).unwrap();
+
+        let pipeline_info = ComputePipelineInfo {
+            materials: vec![],
+            push_constant_range: None,
+            shader: crate::graph::Shader {
+                handle: base,
+                specialization: vec![],
+            },
+        };
+
+        let key = |shaders: &ShaderStorage, variant: ShaderHandle| {
+            let dependencies = [variant, base.into()];
+
+            ComputePipelineKey {
+                info: pipeline_info.clone(),
+                pass_material: None,
+                shader_versions: shader_versions(shaders, &dependencies).unwrap(),
+            }
+        };
+
+        let before = key(&shaders, variant.into());
+
+        // replacing the base destroys its variants, a recompiled variant starts at version 0.
+        shaders.replace(base.into(), info()).unwrap();
+        shaders.destroy_compute_shader(variant);
+        let variant = shaders.create_compute_shader(info()).unwrap();
+
+        assert_eq!(shaders.version(variant.into()), Some(0));
+        assert!(key(&shaders, variant.into()) != before);
+    }
+}
@@ -0,0 +1,318 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Validation of pipelines against the interfaces of their shader programs.
+
+use super::PrepareError;
+
+use crate::resources::material::{MaterialHandle, MaterialParameterType, MaterialStorage};
+use crate::resources::pipeline::ShaderInfo;
+use crate::resources::shader::ShaderStages;
+use crate::resources::spirv::{DescriptorType, InputVariable, ScalarType};
+use crate::vertex_attrib::VertexAttrib;
+
+use derive_more::Display;
+use smallvec::SmallVec;
+
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+/// Errors that occur when a pipeline does not match the interface of its shader programs.
+#[derive(Debug, Display)]
+pub enum ShaderInterfaceError {
+    /// The shader uses a binding no material or pass resource provides.
+    #[display(
+        fmt = "{:?} shader uses binding {} of set {} which is not provided",
+        stage,
+        binding,
+        set
+    )]
+    MissingDescriptor {
+        /// Stage of the shader.
+        stage: ShaderStages,
+        /// Descriptor set of the binding.
+        set: u32,
+        /// Binding inside the set.
+        binding: u32,
+    },
+
+    /// The shader expects a different type of descriptor than provided.
+    #[display(
+        fmt = "{:?} shader expects {:?} at binding {} of set {}, but {:?} is provided",
+        stage,
+        expected,
+        binding,
+        set,
+        provided
+    )]
+    DescriptorTypeMismatch {
+        /// Stage of the shader.
+        stage: ShaderStages,
+        /// Descriptor set of the binding.
+        set: u32,
+        /// Binding inside the set.
+        binding: u32,
+        /// Descriptor type used in the shader.
+        expected: DescriptorType,
+        /// Parameter type provided for the binding.
+        provided: MaterialParameterType,
+    },
+
+    /// The shader expects more descriptors in a binding than provided.
+    #[display(
+        fmt = "{:?} shader expects {} descriptors at binding {} of set {}, but {} are provided",
+        stage,
+        expected,
+        binding,
+        set,
+        provided
+    )]
+    DescriptorCountMismatch {
+        /// Stage of the shader.
+        stage: ShaderStages,
+        /// Descriptor set of the binding.
+        set: u32,
+        /// Binding inside the set.
+        binding: u32,
+        /// Number of descriptors used in the shader.
+        expected: u32,
+        /// Number of descriptors provided.
+        provided: u32,
+    },
+
+    /// The shader uses a descriptor which is not visible to its stage.
+    #[display(
+        fmt = "{:?} shader uses binding {} of set {} which is not visible to the stage",
+        stage,
+        binding,
+        set
+    )]
+    DescriptorNotVisible {
+        /// Stage of the shader.
+        stage: ShaderStages,
+        /// Descriptor set of the binding.
+        set: u32,
+        /// Binding inside the set.
+        binding: u32,
+    },
+
+    /// The push-constant block of the shader is not covered by the declared ranges.
+    #[display(
+        fmt = "{:?} shader uses push constants {:?} which are not declared for the stage",
+        stage,
+        range
+    )]
+    PushConstantsNotDeclared {
+        /// Stage of the shader.
+        stage: ShaderStages,
+        /// Range of the push-constant block in the shader.
+        range: Range<u32>,
+    },
+
+    /// A vertex shader input is not provided by the vertex attributes.
+    #[display(
+        fmt = "Vertex shader input at location {} is not provided by the vertex attributes",
+        location
+    )]
+    MissingVertexInput {
+        /// Location of the input.
+        location: u32,
+    },
+
+    /// A vertex attribute format does not match the type of the vertex shader input.
+    #[display(
+        fmt = "Vertex shader input at location {} expects {:?} data, but the attribute has format {:?}",
+        location,
+        expected,
+        format
+    )]
+    VertexInputTypeMismatch {
+        /// Location of the input.
+        location: u32,
+        /// Component type used in the shader.
+        expected: ScalarType,
+        /// Format of the vertex attribute.
+        format: gfx::format::Format,
+    },
+}
+
+impl std::error::Error for ShaderInterfaceError {}
+
+/// Check that the materials, push-constant ranges and vertex attributes of a pipeline match
+/// the interfaces of its shaders.
+///
+/// The pass material (if any) is bound to set 0, other materials can override it like during
+/// pipeline creation.
+pub(crate) fn validate_pipeline(
+    material_storage: &MaterialStorage,
+    pass_material: Option<MaterialHandle>,
+    materials: &[(usize, MaterialHandle)],
+    shaders: &[(ShaderStages, &ShaderInfo)],
+    push_constants: &[(gfx::pso::ShaderStageFlags, Range<u32>)],
+    vertex_attrib: Option<&VertexAttrib>,
+) -> Result<(), PrepareError> {
+    let mut sets = BTreeMap::new();
+
+    if let Some(mat) = pass_material.and_then(|mat| material_storage.raw(mat)) {
+        sets.insert(0, mat.parameters());
+    }
+
+    for (set, material) in materials {
+        if let Some(mat) = material_storage.raw(*material) {
+            sets.insert(*set as u32, mat.parameters());
+        }
+    }
+
+    for (stage, shader) in shaders {
+        let stage = *stage;
+
+        let entry = shader.interface;
+
+        for desc in &entry.descriptors {
+            let param = sets.get(&desc.set).and_then(|params| {
+                params
+                    .iter()
+                    .find(|param| param.binding == desc.binding)
+                    .copied()
+            });
+
+            let param = param.ok_or(ShaderInterfaceError::MissingDescriptor {
+                stage,
+                set: desc.set,
+                binding: desc.binding,
+            })?;
+
+            if !is_compatible(desc.ty, param.ty) {
+                return Err(ShaderInterfaceError::DescriptorTypeMismatch {
+                    stage,
+                    set: desc.set,
+                    binding: desc.binding,
+                    expected: desc.ty,
+                    provided: param.ty,
+                }
+                .into());
+            }
+
+            if !param.stages.contains(stage) {
+                return Err(ShaderInterfaceError::DescriptorNotVisible {
+                    stage,
+                    set: desc.set,
+                    binding: desc.binding,
+                }
+                .into());
+            }
+
+            // runtime arrays have a count of 0 and accept any array size
+            if desc.count > param.count {
+                return Err(ShaderInterfaceError::DescriptorCountMismatch {
+                    stage,
+                    set: desc.set,
+                    binding: desc.binding,
+                    expected: desc.count,
+                    provided: param.count,
+                }
+                .into());
+            }
+        }
+
+        if let Some(range) = &entry.push_constants {
+            let flags = gfx::pso::ShaderStageFlags::from(stage);
+
+            let declared = push_constants
+                .iter()
+                .filter(|(stages, _)| stages.contains(flags))
+                .map(|(_, range)| range.clone());
+
+            if !is_covered(range, declared) {
+                return Err(ShaderInterfaceError::PushConstantsNotDeclared {
+                    stage,
+                    range: range.clone(),
+                }
+                .into());
+            }
+        }
+
+        if stage == ShaderStages::VERTEX {
+            validate_vertex_inputs(&entry.inputs, vertex_attrib)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn is_compatible(shader: DescriptorType, param: MaterialParameterType) -> bool {
+    use self::DescriptorType as D;
+    use self::MaterialParameterType as P;
+
+    match (shader, param) {
+        (D::Sampler, P::Sampler)
+        | (D::CombinedImageSampler, P::CombinedImageSampler)
+        | (D::SampledImage, P::SampledImage)
+        | (D::StorageImage, P::StorageImage)
+        | (D::UniformTexelBuffer, P::UniformTexelBuffer)
+        | (D::StorageTexelBuffer, P::StorageTexelBuffer)
+        | (D::UniformBuffer, P::UniformBuffer)
+        | (D::UniformBuffer, P::UniformBufferDynamic)
+        | (D::StorageBuffer, P::StorageBuffer)
+        | (D::StorageBuffer, P::StorageBufferDynamic) => true,
+        _ => false,
+    }
+}
+
+/// Check if `range` is fully covered by the union of the `declared` ranges.
+fn is_covered<I>(range: &Range<u32>, declared: I) -> bool
+where
+    I: Iterator<Item = Range<u32>>,
+{
+    let mut declared = declared.collect::<SmallVec<[_; 4]>>();
+    declared.sort_by_key(|declared| declared.start);
+
+    let mut covered = range.start;
+
+    for declared in declared {
+        if declared.start > covered {
+            break;
+        }
+
+        covered = covered.max(declared.end);
+    }
+
+    covered >= range.end
+}
+
+fn validate_vertex_inputs(
+    inputs: &[InputVariable],
+    vertex_attrib: Option<&VertexAttrib>,
+) -> Result<(), ShaderInterfaceError> {
+    use gfx::format::ChannelType;
+
+    // reflection guarantees that the locations of an input don't overflow. The first missing
+    // location ends the loop, so large arrays are not walked entirely.
+    for input in inputs {
+        for location in input.location..input.location + input.locations {
+            let element = vertex_attrib
+                .into_iter()
+                .flat_map(|attrib| attrib.buffer_infos.iter())
+                .flat_map(|buffer| buffer.elements.iter())
+                .find(|elem| elem.location == location)
+                .ok_or(ShaderInterfaceError::MissingVertexInput { location })?;
+
+            let provided = match element.format.base_format().1 {
+                ChannelType::Uint => ScalarType::Uint,
+                ChannelType::Sint => ScalarType::Int,
+                _ => ScalarType::Float,
+            };
+
+            if provided != input.ty {
+                return Err(ShaderInterfaceError::VertexInputTypeMismatch {
+                    location,
+                    expected: input.ty,
+                    format: element.format,
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -32,7 +32,10 @@ pub(crate) use self::execution::*;
 
 pub use self::execution::Backbuffer;
 pub use self::execution::GraphExecError;
+pub use self::execution::PipelineBudget;
+pub use self::execution::PipelineStats;
 pub use self::execution::PrepareError;
+pub use self::execution::ShaderInterfaceError;
 
 pub use self::compilation::CompileError;
 
@@ -64,6 +67,11 @@ pub type PassName = CowString;
 /// Type used to name resources.
 pub type ResourceName = CowString;
 
+/// Type used to name reference sizes of an [`ExecutionContext`].
+///
+/// [`ExecutionContext`]: ./struct.ExecutionContext.html
+pub type ReferenceName = CowString;
+
 // The ComputePass (and GraphicsPass) traits now have associated types, which means it's not
 // possible anymore to use `dyn ComputePass` to store the passes (since they can't be named).
 //
@@ -75,6 +83,8 @@ pub(crate) struct ComputePassAccessor {
     pub(crate) prepare: Box<dyn Fn(&mut Store)>,
     pub(crate) describe: Box<dyn Fn(&mut ResourceDescriptor)>,
     pub(crate) execute: Box<dyn Fn(&Store, RawComputeDispatcher) -> Result<(), GraphExecError>>,
+    pub(crate) prewarm: Box<PrewarmFn>,
+    pub(crate) retain_pipeline_infos: Box<dyn Fn(&PassResources, PassId)>,
 }
 
 // Same explanation as `ComputePassAccessor`
@@ -82,8 +92,14 @@ pub(crate) struct GraphicPassAccessor {
     pub(crate) prepare: Box<dyn Fn(&mut Store)>,
     pub(crate) describe: Box<dyn Fn(&mut ResourceDescriptor)>,
     pub(crate) execute: Box<dyn Fn(&Store, RawGraphicsDispatcher) -> Result<(), GraphExecError>>,
+    pub(crate) prewarm: Box<PrewarmFn>,
+    pub(crate) retain_pipeline_infos: Box<dyn Fn(&PassResources, PassId)>,
 }
 
+// Creates the pipelines for the configurations a pass lists, returns the number of new pipelines.
+pub(crate) type PrewarmFn =
+    dyn Fn(&DeviceContext, &Storages, &mut PassResources, PassId) -> Result<usize, PrepareError>;
+
 /// Errors that can occur when dealing with graph preparation/execution.
 #[derive(Debug, From)]
 pub enum GraphError {
@@ -114,12 +130,14 @@ pub struct Graph {
 
 pub(crate) struct GraphStorage {
     pub(crate) storage: Storage<Graph>,
+    pub(crate) pipeline_budget: PipelineBudget,
 }
 
 impl GraphStorage {
     pub(crate) fn new() -> Self {
         GraphStorage {
             storage: Storage::new(),
+            pipeline_budget: PipelineBudget::default(),
         }
     }
 
@@ -127,6 +145,7 @@ impl GraphStorage {
         &mut self,
         device: &DeviceContext,
         storages: &mut Storages,
+        res_list: &mut ResourceList,
         builder: GraphBuilder,
     ) -> Result<GraphHandle, GraphError> {
         let name = builder.name.clone();
@@ -138,35 +157,20 @@ impl GraphStorage {
 
         let exec_graph = ExecutionGraph::new(&compiled);
 
-        let pass_resources = {
-            let mut res = PassResources::default();
-
-            for batch in &exec_graph.pass_execution {
-                for pass in &batch.passes {
-                    let mat = execution::create_pass_material(
-                        device,
-                        &mut *storages.material.borrow_mut(),
-                        &compiled.graph_resources,
-                        *pass,
-                    )?;
-
-                    if let Some(mat) = mat {
-                        res.pass_material.insert(*pass, mat);
-                    }
-
-                    // create base resources
-
-                    if compiled.compute_passes.contains_key(pass) {
-                        // nothing to do for compute passes. For now, at least.
-                    } else {
-                        // graphics
-                        prepare_graphics_pass_base(device, storages, &mut res, *pass, &compiled)?;
-                    }
-                }
-            }
+        let mut pass_resources = PassResources::default();
 
-            res
-        };
+        let prepared = prepare_pass_resources(
+            device,
+            storages,
+            &exec_graph,
+            &compiled,
+            &mut pass_resources,
+        );
+
+        if let Err(err) = prepared {
+            pass_resources.release(res_list, storages);
+            return Err(err.into());
+        }
 
         let res_usage = derive_resource_usage(&exec_graph, &compiled);
 
@@ -185,6 +189,34 @@ impl GraphStorage {
         Ok(self.storage.insert(graph))
     }
 
+    /// Create the pipelines for all configurations listed by the passes of a graph.
+    pub(crate) unsafe fn prewarm(
+        &mut self,
+        device: &DeviceContext,
+        storages: &Storages,
+        handle: GraphHandle,
+    ) -> Result<usize, GraphExecError> {
+        let graph = self
+            .storage
+            .get_mut(handle)
+            .ok_or(GraphExecError::InvalidGraph)?;
+
+        let created = prewarm_pipelines(
+            device,
+            storages,
+            &graph.compiled_graph,
+            &mut graph.pass_resources,
+        )?;
+
+        Ok(created)
+    }
+
+    pub(crate) fn pipeline_stats(&self, handle: GraphHandle) -> Option<PipelineStats> {
+        let graph = self.storage.get(handle)?;
+
+        Some(graph.pass_resources.pipeline_stats)
+    }
+
     pub(crate) fn destroy(
         &mut self,
         res_list: &mut ResourceList,
@@ -331,6 +363,23 @@ impl GraphStorage {
             }
         }
 
+        // pipelines using replaced shaders get recreated when they are used next.
+        graph
+            .pass_resources
+            .release_outdated_pipelines(sync.res_list, storages);
+
+        // pipelines which were not used for a while get destroyed to stay within the budget.
+        let evicted =
+            graph
+                .pass_resources
+                .evict_pipelines(sync.res_list, storages, &self.pipeline_budget);
+
+        if evicted > 0 {
+            forget_evicted_pipeline_infos(&graph.compiled_graph, &graph.pass_resources);
+        }
+
+        graph.pass_resources.executions += 1;
+
         execution::execute(
             device,
             sync,
@@ -360,7 +409,7 @@ impl GraphStorage {
 }
 
 /// Reference data used during graph executions.
-#[derive(Debug, Clone, Ord, PartialOrd, PartialEq, Eq)]
+#[derive(Debug, Clone, Default, Ord, PartialOrd, PartialEq, Eq)]
 pub struct ExecutionContext {
     /// Reference size of the current execution.
     ///
@@ -368,4 +417,81 @@ pub struct ExecutionContext {
     ///
     /// [`ImageSizeMode::ContextRelative`]: ../resources/image/enum.ImageSizeMode.html#variant.ContextRelative
     pub reference_size: (u32, u32),
+
+    /// Additional named reference sizes, for example `"screen"` or `"shadow"`.
+    ///
+    /// These values are used as the reference when [`ImageSizeMode::ReferenceRelative`] is used.
+    ///
+    /// [`ImageSizeMode::ReferenceRelative`]: ../resources/image/enum.ImageSizeMode.html#variant.ReferenceRelative
+    pub reference_sizes: BTreeMap<ReferenceName, (u32, u32)>,
+}
+
+/// Create the pass materials, render passes and initial pipelines of a graph.
+///
+/// On error the resources created so far are left in `pass_res`, so they can be released.
+unsafe fn prepare_pass_resources(
+    device: &DeviceContext,
+    storages: &Storages,
+    exec_graph: &ExecutionGraph,
+    compiled: &CompiledGraph,
+    pass_res: &mut PassResources,
+) -> Result<(), PrepareError> {
+    for batch in &exec_graph.pass_execution {
+        for pass in &batch.passes {
+            let mat = execution::create_pass_material(
+                device,
+                &mut *storages.material.borrow_mut(),
+                &compiled.graph_resources,
+                *pass,
+            )?;
+
+            if let Some(mat) = mat {
+                pass_res.pass_material.insert(*pass, mat);
+            }
+
+            // create base resources
+
+            if compiled.compute_passes.contains_key(pass) {
+                // nothing to do for compute passes. For now, at least.
+            } else {
+                // graphics
+                prepare_graphics_pass_base(device, storages, pass_res, *pass, compiled)?;
+            }
+        }
+    }
+
+    prewarm_pipelines(device, storages, compiled, pass_res)?;
+
+    Ok(())
+}
+
+unsafe fn prewarm_pipelines(
+    device: &DeviceContext,
+    storages: &Storages,
+    compiled: &CompiledGraph,
+    pass_res: &mut PassResources,
+) -> Result<usize, PrepareError> {
+    let mut created = 0;
+
+    for (pass, accessor) in &compiled.compute_passes {
+        created += (accessor.prewarm)(device, storages, pass_res, *pass)?;
+    }
+
+    for (pass, accessor) in &compiled.graphic_passes {
+        created += (accessor.prewarm)(device, storages, pass_res, *pass)?;
+    }
+
+    pass_res.pipeline_stats.prewarmed += created;
+
+    Ok(created)
+}
+
+fn forget_evicted_pipeline_infos(compiled: &CompiledGraph, pass_res: &PassResources) {
+    for (pass, accessor) in &compiled.compute_passes {
+        (accessor.retain_pipeline_infos)(pass_res, *pass);
+    }
+
+    for (pass, accessor) in &compiled.graphic_passes {
+        (accessor.retain_pipeline_infos)(pass_res, *pass);
+    }
 }
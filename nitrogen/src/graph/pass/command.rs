@@ -8,6 +8,8 @@ use std::ops::Range;
 
 use gfx;
 
+use derive_more::Display;
+
 use crate::types;
 
 use crate::material::{MaterialInstanceHandle, MaterialStorage};
@@ -27,18 +29,120 @@ pub enum IndexType {
     U32,
 }
 
+/// Errors that can occur when uploading push-constant data.
+#[derive(Debug, Display)]
+pub enum PushConstantError {
+    /// The offset of the data is not a multiple of 4.
+    #[display(fmt = "Push constant offset {} is not aligned to 4 bytes", _0)]
+    UnalignedOffset(u32),
+
+    /// The data is not fully contained in the declared push-constant ranges it overlaps with.
+    #[display(
+        fmt = "Push constant data {:?} is not inside a declared push constant range",
+        _0
+    )]
+    OutOfRange(Range<u32>),
+}
+
+impl std::error::Error for PushConstantError {}
+
 pub(crate) struct ReadStorages<'a> {
     pub(crate) buffer: Ref<'a, BufferStorage>,
     pub(crate) material: Ref<'a, MaterialStorage>,
     pub(crate) _image: Ref<'a, ImageStorage>,
 }
 
+/// A rectangular area of a framebuffer (in pixels).
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Rect {
+    /// Upper left corner of the area.
+    pub origin: (i16, i16),
+    /// Width and height of the area.
+    pub size: (i16, i16),
+}
+
+impl From<Rect> for gfx::pso::Rect {
+    fn from(rect: Rect) -> Self {
+        gfx::pso::Rect {
+            x: rect.origin.0,
+            y: rect.origin.1,
+            w: rect.size.0,
+            h: rect.size.1,
+        }
+    }
+}
+
+/// Viewport transformation from normalized device coordinates to framebuffer coordinates.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Viewport {
+    /// Area of the framebuffer the viewport maps to.
+    pub rect: Rect,
+    /// Range normalized depth values get mapped to.
+    pub depth: Range<f32>,
+}
+
+impl From<&Viewport> for gfx::pso::Viewport {
+    fn from(viewport: &Viewport) -> Self {
+        gfx::pso::Viewport {
+            rect: viewport.rect.into(),
+            depth: viewport.depth.clone(),
+        }
+    }
+}
+
+// Encoder functionality needed for viewport and scissor state.
+//
+// This exists so that the conversion and forwarding of viewports and scissors can be tested
+// without a device.
+pub(crate) trait ViewportEncoder {
+    unsafe fn encode_viewports(&mut self, first: u32, viewports: &[gfx::pso::Viewport]);
+    unsafe fn encode_scissors(&mut self, first: u32, scissors: &[gfx::pso::Rect]);
+}
+
+impl<'a> ViewportEncoder for gfx::command::RenderPassInlineEncoder<'a, back::Backend> {
+    unsafe fn encode_viewports(&mut self, first: u32, viewports: &[gfx::pso::Viewport]) {
+        self.set_viewports(first, viewports);
+    }
+
+    unsafe fn encode_scissors(&mut self, first: u32, scissors: &[gfx::pso::Rect]) {
+        self.set_scissors(first, scissors);
+    }
+}
+
+unsafe fn set_viewports<E: ViewportEncoder>(encoder: &mut E, first: u32, viewports: &[Viewport]) {
+    let viewports = viewports
+        .iter()
+        .map(gfx::pso::Viewport::from)
+        .collect::<smallvec::SmallVec<[_; 4]>>();
+
+    encoder.encode_viewports(first, viewports.as_slice());
+}
+
+unsafe fn reset_viewport<E: ViewportEncoder>(encoder: &mut E, framebuffer_rect: gfx::pso::Rect) {
+    let viewport = gfx::pso::Viewport {
+        rect: framebuffer_rect,
+        depth: 0.0..1.0,
+    };
+
+    encoder.encode_viewports(0, &[viewport]);
+}
+
+unsafe fn set_scissors<E: ViewportEncoder>(encoder: &mut E, first: u32, scissors: &[Rect]) {
+    let scissors = scissors
+        .iter()
+        .map(|rect| gfx::pso::Rect::from(*rect))
+        .collect::<smallvec::SmallVec<[_; 4]>>();
+
+    encoder.encode_scissors(first, scissors.as_slice());
+}
+
 /// CommandBuffer object used to issue commands to a graphics queue.
 pub struct GraphicsCommandBuffer<'a> {
     pub(crate) encoder: gfx::command::RenderPassInlineEncoder<'a, back::Backend>,
     pub(crate) storages: &'a ReadStorages<'a>,
 
     pub(crate) pipeline_layout: &'a types::PipelineLayout,
+    pub(crate) push_constants: &'a [(gfx::pso::ShaderStageFlags, Range<u32>)],
     pub(crate) viewport_rect: gfx::pso::Rect,
 }
 
@@ -65,11 +169,53 @@ impl<'a> GraphicsCommandBuffer<'a> {
         self.encoder.draw_indexed(indices, base_vertex, instances);
     }
 
+    /// Dispatch `draw_count` draw calls with parameters read from `buffer`.
+    ///
+    /// The parameters are read starting at `offset` bytes, consecutive draws are `stride` bytes
+    /// apart. Graph buffers used this way have to be declared with
+    /// [`ResourceDescriptor::buffer_read_indirect`].
+    ///
+    /// [`ResourceDescriptor::buffer_read_indirect`]: ../../builder/resource_descriptor/struct.ResourceDescriptor.html#method.buffer_read_indirect
+    pub unsafe fn draw_indirect<B: Into<BufferHandle>>(
+        &mut self,
+        buffer: B,
+        offset: u64,
+        draw_count: u32,
+        stride: u32,
+    ) -> Option<()> {
+        let buffer = self.storages.buffer.raw(buffer.into())?;
+
+        self.encoder
+            .draw_indirect(buffer.buffer.raw(), offset, draw_count, stride);
+
+        Some(())
+    }
+
+    /// Dispatch `draw_count` indexed draw calls with parameters read from `buffer`.
+    ///
+    /// The parameters are read starting at `offset` bytes, consecutive draws are `stride` bytes
+    /// apart.
+    pub unsafe fn draw_indexed_indirect<B: Into<BufferHandle>>(
+        &mut self,
+        buffer: B,
+        offset: u64,
+        draw_count: u32,
+        stride: u32,
+    ) -> Option<()> {
+        let buffer = self.storages.buffer.raw(buffer.into())?;
+
+        self.encoder
+            .draw_indexed_indirect(buffer.buffer.raw(), offset, draw_count, stride);
+
+        Some(())
+    }
+
     /// Bind vertex buffers for the next draw call.
     /// The provided pairs of buffer and `usize` represent the buffer to bind
     /// and the **offset into the buffer**.
-    /// The first pair will be bound to vertex buffer 0, the second to 1, etc...
-    pub unsafe fn bind_vertex_buffers<T, I>(&mut self, buffers: T)
+    /// The first pair will be bound to vertex buffer `first_binding`, the second to
+    /// `first_binding + 1`, etc...
+    pub unsafe fn bind_vertex_buffers<T, I>(&mut self, first_binding: u32, buffers: T)
     where
         T: IntoIterator<Item = I>,
         T::Item: std::borrow::Borrow<(BufferHandle, usize)>,
@@ -84,7 +230,7 @@ impl<'a> GraphicsCommandBuffer<'a> {
                 .map(|buf| (buf.buffer.raw(), *index as u64))
         });
 
-        self.encoder.bind_vertex_buffers(0, bufs);
+        self.encoder.bind_vertex_buffers(first_binding, bufs);
     }
 
     /// Bind an index buffer, starting from `offset` bytes in the buffer represented by `buffer`.
@@ -135,44 +281,111 @@ impl<'a> GraphicsCommandBuffer<'a> {
         Some(())
     }
 
-    unsafe fn push_constant_raw(&mut self, offset: u32, data: &[u32]) {
-        self.encoder.push_graphics_constants(
-            self.pipeline_layout,
-            gfx::pso::ShaderStageFlags::ALL,
-            offset,
-            data,
-        )
+    unsafe fn push_constant_raw(
+        &mut self,
+        stages: gfx::pso::ShaderStageFlags,
+        offset: u32,
+        data: &[u32],
+    ) {
+        self.encoder
+            .push_graphics_constants(self.pipeline_layout, stages, offset / 4, data)
     }
 
-    /// Upload a value to the push-constant memory.
-    pub unsafe fn push_constant<T: Sized + Copy>(&mut self, offset: u32, data: T) {
-        use smallvec::SmallVec;
-        let mut buf = SmallVec::<[u32; 256]>::new();
+    /// Upload a value to the push-constant memory at `offset` bytes.
+    ///
+    /// The value is made visible to all shader stages of the push-constant ranges it overlaps
+    /// with, so it has to be fully contained in each of those ranges.
+    pub unsafe fn push_constant<T: Sized + Copy>(
+        &mut self,
+        offset: u32,
+        data: T,
+    ) -> Result<(), PushConstantError> {
+        let stages = push_constant_stages(self.push_constants, offset, padded_size::<T>())?;
 
-        buf.set_len(256);
+        let mut buf = push_constant_buffer::<T>();
+        let u32_slice = data_to_u32_slice(data, &mut buf[..]);
+        self.push_constant_raw(stages, offset, u32_slice);
 
-        {
-            let u32_slice = data_to_u32_slice(data, &mut buf[..]);
-            self.push_constant_raw(offset, u32_slice);
-        }
+        Ok(())
+    }
+
+    /// Set the viewport transformation of the first viewport.
+    ///
+    /// `depth` is the range that normalized depth values get mapped to.
+    pub unsafe fn set_viewport(&mut self, origin: (i16, i16), size: (i16, i16), depth: Range<f32>) {
+        let viewport = Viewport {
+            rect: Rect { origin, size },
+            depth,
+        };
+
+        set_viewports(&mut self.encoder, 0, &[viewport]);
+    }
+
+    /// Set the viewport transformations of consecutive viewports, starting at index `first`.
+    ///
+    /// Viewports other than the first one can only be selected by geometry shaders and need
+    /// device support for multiple viewports.
+    pub unsafe fn set_viewports(&mut self, first: u32, viewports: &[Viewport]) {
+        set_viewports(&mut self.encoder, first, viewports);
+    }
+
+    /// Reset the first viewport. This sets the viewport to the area of the framebuffer and the
+    /// depth range to `0.0..1.0`.
+    pub unsafe fn reset_viewport(&mut self) {
+        reset_viewport(&mut self.encoder, self.viewport_rect);
     }
 
     /// Set the scissor "cutoff".
     pub unsafe fn set_scissor(&mut self, origin: (i16, i16), size: (i16, i16)) {
-        self.encoder.set_scissors(
-            0,
-            &[gfx::pso::Rect {
-                x: origin.0,
-                y: origin.1,
-                w: size.0,
-                h: size.1,
-            }],
-        );
+        set_scissors(&mut self.encoder, 0, &[Rect { origin, size }]);
+    }
+
+    /// Set the scissor "cutoff" of consecutive viewports, starting at index `first`.
+    pub unsafe fn set_scissors(&mut self, first: u32, scissors: &[Rect]) {
+        set_scissors(&mut self.encoder, first, scissors);
     }
 
     /// Reset the scissor state. This sets the scissor rect to the area of the framebuffer.
     pub unsafe fn reset_scissor(&mut self) {
-        self.encoder.set_scissors(0, &[self.viewport_rect]);
+        self.encoder.encode_scissors(0, &[self.viewport_rect]);
+    }
+
+    /// Set the depth bias of pipelines using [`DepthBias::Dynamic`].
+    ///
+    /// [`DepthBias::Dynamic`]: ../enum.DepthBias.html#variant.Dynamic
+    pub unsafe fn set_depth_bias(&mut self, constant_factor: f32, clamp: f32, slope_factor: f32) {
+        self.encoder.set_depth_bias(gfx::pso::DepthBias {
+            const_factor: constant_factor,
+            clamp,
+            slope_factor,
+        });
+    }
+
+    /// Set the line width of pipelines using [`LineWidth::Dynamic`].
+    ///
+    /// [`LineWidth::Dynamic`]: ../enum.LineWidth.html#variant.Dynamic
+    pub unsafe fn set_line_width(&mut self, width: f32) {
+        self.encoder.set_line_width(width);
+    }
+
+    /// Set the blend constants used by the `Const*` blend factors.
+    pub unsafe fn set_blend_constants(&mut self, color: [f32; 4]) {
+        self.encoder.set_blend_constants(color);
+    }
+
+    /// Set the reference value used for stencil tests of front- and back-facing primitives.
+    pub unsafe fn set_stencil_reference(&mut self, value: u32) {
+        self.encoder
+            .set_stencil_reference(gfx::pso::Face::all(), value);
+    }
+
+    /// Set separate reference values used for stencil tests of front- and back-facing
+    /// primitives.
+    pub unsafe fn set_stencil_reference_separate(&mut self, front: u32, back: u32) {
+        self.encoder
+            .set_stencil_reference(gfx::pso::Face::FRONT, front);
+        self.encoder
+            .set_stencil_reference(gfx::pso::Face::BACK, back);
     }
 }
 
@@ -182,6 +395,7 @@ pub struct ComputeCommandBuffer<'a> {
     pub(crate) storages: &'a ReadStorages<'a>,
 
     pub(crate) pipeline_layout: &'a types::PipelineLayout,
+    pub(crate) push_constants: &'a [(gfx::pso::ShaderStageFlags, Range<u32>)],
 }
 
 impl<'a> ComputeCommandBuffer<'a> {
@@ -190,6 +404,24 @@ impl<'a> ComputeCommandBuffer<'a> {
         self.buf.dispatch(workgroup_count)
     }
 
+    /// Execute a workgroup with the workgroup count read from `buffer` at `offset` bytes.
+    ///
+    /// Graph buffers used this way have to be declared with
+    /// [`ResourceDescriptor::buffer_read_indirect`].
+    ///
+    /// [`ResourceDescriptor::buffer_read_indirect`]: ../../builder/resource_descriptor/struct.ResourceDescriptor.html#method.buffer_read_indirect
+    pub unsafe fn dispatch_indirect<B: Into<BufferHandle>>(
+        &mut self,
+        buffer: B,
+        offset: u64,
+    ) -> Option<()> {
+        let buffer = self.storages.buffer.raw(buffer.into())?;
+
+        self.buf.dispatch_indirect(buffer.buffer.raw(), offset);
+
+        Some(())
+    }
+
     /// bind a [`MaterialInstance`] to a descriptor set in the pipeline.
     pub unsafe fn bind_material(
         &mut self,
@@ -211,20 +443,75 @@ impl<'a> ComputeCommandBuffer<'a> {
 
     unsafe fn push_constant_raw(&mut self, offset: u32, data: &[u32]) {
         self.buf
-            .push_compute_constants(self.pipeline_layout, offset, data);
+            .push_compute_constants(self.pipeline_layout, offset / 4, data);
     }
 
-    /// Upload a value to the push-constant memory.
-    pub unsafe fn push_constant<T: Sized + Copy>(&mut self, offset: u32, data: T) {
-        use smallvec::SmallVec;
-        let mut buf = SmallVec::<[u32; 256]>::new();
+    /// Upload a value to the push-constant memory at `offset` bytes.
+    ///
+    /// The value has to be fully contained in the push-constant range of the pipeline.
+    pub unsafe fn push_constant<T: Sized + Copy>(
+        &mut self,
+        offset: u32,
+        data: T,
+    ) -> Result<(), PushConstantError> {
+        push_constant_stages(self.push_constants, offset, padded_size::<T>())?;
+
+        let mut buf = push_constant_buffer::<T>();
+        let u32_slice = data_to_u32_slice(data, &mut buf[..]);
+        self.push_constant_raw(offset, u32_slice);
+
+        Ok(())
+    }
+}
+
+/// Size of `T` in bytes, rounded up to a multiple of 4.
+fn padded_size<T: Sized>() -> u32 {
+    let size = std::mem::size_of::<T>() as u32;
+    (size + 3) / 4 * 4
+}
+
+fn push_constant_buffer<T: Sized>() -> smallvec::SmallVec<[u32; 32]> {
+    smallvec::SmallVec::from_elem(0, padded_size::<T>() as usize / 4)
+}
+
+/// Compute the shader stages a push of `size` bytes at `offset` has to be made visible to.
+///
+/// Every declared range overlapping the pushed bytes has to contain all of them, otherwise
+/// the push would be visible to a stage outside of the range declared for it.
+fn push_constant_stages(
+    ranges: &[(gfx::pso::ShaderStageFlags, Range<u32>)],
+    offset: u32,
+    size: u32,
+) -> Result<gfx::pso::ShaderStageFlags, PushConstantError> {
+    if offset % 4 != 0 {
+        return Err(PushConstantError::UnalignedOffset(offset));
+    }
 
-        buf.set_len(256);
+    let end = offset
+        .checked_add(size)
+        .ok_or(PushConstantError::OutOfRange(offset..u32::max_value()))?;
+    let pushed = offset..end;
 
-        {
-            let u32_slice = data_to_u32_slice(data, &mut buf[..]);
-            self.push_constant_raw(offset, u32_slice);
+    let mut stages = gfx::pso::ShaderStageFlags::empty();
+
+    for (range_stages, range) in ranges {
+        let overlaps = range.start < pushed.end && pushed.start < range.end;
+
+        if !overlaps {
+            continue;
+        }
+
+        if range.start > pushed.start || range.end < pushed.end {
+            return Err(PushConstantError::OutOfRange(pushed));
         }
+
+        stages |= *range_stages;
+    }
+
+    if stages.is_empty() {
+        Err(PushConstantError::OutOfRange(pushed))
+    } else {
+        Ok(stages)
     }
 }
 
@@ -264,3 +551,184 @@ unsafe fn data_to_u32_slice<T: Sized>(data: T, buf: &mut [u32]) -> &[u32] {
         from_raw_parts(buf_ptr, slice_len)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct RecordingEncoder {
+        viewports: Vec<(u32, Vec<gfx::pso::Viewport>)>,
+        scissors: Vec<(u32, Vec<gfx::pso::Rect>)>,
+    }
+
+    impl ViewportEncoder for RecordingEncoder {
+        unsafe fn encode_viewports(&mut self, first: u32, viewports: &[gfx::pso::Viewport]) {
+            self.viewports.push((first, viewports.to_vec()));
+        }
+
+        unsafe fn encode_scissors(&mut self, first: u32, scissors: &[gfx::pso::Rect]) {
+            self.scissors.push((first, scissors.to_vec()));
+        }
+    }
+
+    fn rect(x: i16, y: i16, w: i16, h: i16) -> gfx::pso::Rect {
+        gfx::pso::Rect { x, y, w, h }
+    }
+
+    #[test]
+    fn viewport_forwarded() {
+        let mut encoder = RecordingEncoder::default();
+
+        let viewport = Viewport {
+            rect: Rect {
+                origin: (10, 20),
+                size: (300, 400),
+            },
+            depth: 0.25..0.75,
+        };
+
+        unsafe {
+            set_viewports(&mut encoder, 0, &[viewport]);
+        }
+
+        assert_eq!(encoder.viewports.len(), 1);
+        assert!(encoder.scissors.is_empty());
+
+        let (first, viewports) = &encoder.viewports[0];
+
+        assert_eq!(*first, 0);
+        assert_eq!(viewports.len(), 1);
+        assert_eq!(viewports[0].rect, rect(10, 20, 300, 400));
+        assert_eq!(viewports[0].depth, 0.25..0.75);
+    }
+
+    #[test]
+    fn multiple_viewports_forwarded() {
+        let mut encoder = RecordingEncoder::default();
+
+        let viewports = [
+            Viewport {
+                rect: Rect {
+                    origin: (0, 0),
+                    size: (50, 50),
+                },
+                depth: 0.0..1.0,
+            },
+            Viewport {
+                rect: Rect {
+                    origin: (50, 0),
+                    size: (50, 50),
+                },
+                depth: 0.0..0.5,
+            },
+        ];
+
+        unsafe {
+            set_viewports(&mut encoder, 2, &viewports);
+        }
+
+        let (first, recorded) = &encoder.viewports[0];
+
+        assert_eq!(*first, 2);
+        assert_eq!(recorded.len(), 2);
+        assert_eq!(recorded[0].rect, rect(0, 0, 50, 50));
+        assert_eq!(recorded[0].depth, 0.0..1.0);
+        assert_eq!(recorded[1].rect, rect(50, 0, 50, 50));
+        assert_eq!(recorded[1].depth, 0.0..0.5);
+    }
+
+    #[test]
+    fn viewport_reset_to_framebuffer() {
+        let mut encoder = RecordingEncoder::default();
+
+        unsafe {
+            reset_viewport(&mut encoder, rect(0, 0, 1280, 720));
+        }
+
+        let (first, viewports) = &encoder.viewports[0];
+
+        assert_eq!(*first, 0);
+        assert_eq!(viewports.len(), 1);
+        assert_eq!(viewports[0].rect, rect(0, 0, 1280, 720));
+        assert_eq!(viewports[0].depth, 0.0..1.0);
+    }
+
+    #[test]
+    fn scissors_forwarded() {
+        let mut encoder = RecordingEncoder::default();
+
+        let scissors = [
+            Rect {
+                origin: (1, 2),
+                size: (3, 4),
+            },
+            Rect {
+                origin: (5, 6),
+                size: (7, 8),
+            },
+        ];
+
+        unsafe {
+            set_scissors(&mut encoder, 1, &scissors);
+        }
+
+        assert!(encoder.viewports.is_empty());
+
+        let (first, recorded) = &encoder.scissors[0];
+
+        assert_eq!(*first, 1);
+        assert_eq!(recorded, &vec![rect(1, 2, 3, 4), rect(5, 6, 7, 8)]);
+    }
+
+    #[test]
+    fn push_constant_stages_union() {
+        use gfx::pso::ShaderStageFlags as S;
+
+        let ranges = [
+            (S::VERTEX, 0..64),
+            (S::FRAGMENT, 0..16),
+            (S::FRAGMENT, 64..80),
+        ];
+
+        assert_eq!(
+            push_constant_stages(&ranges, 0, 16).unwrap(),
+            S::VERTEX | S::FRAGMENT
+        );
+        assert_eq!(push_constant_stages(&ranges, 16, 16).unwrap(), S::VERTEX);
+        assert_eq!(push_constant_stages(&ranges, 64, 16).unwrap(), S::FRAGMENT);
+    }
+
+    #[test]
+    fn push_constant_stages_invalid() {
+        use gfx::pso::ShaderStageFlags as S;
+
+        let ranges = [(S::VERTEX, 0..64), (S::FRAGMENT, 0..16)];
+
+        match push_constant_stages(&ranges, 2, 4) {
+            Err(PushConstantError::UnalignedOffset(2)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        // straddles the end of the fragment range
+        match push_constant_stages(&ranges, 8, 16) {
+            Err(PushConstantError::OutOfRange(range)) => assert_eq!(range, 8..24),
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        // exceeds all declared ranges
+        match push_constant_stages(&ranges, 60, 8) {
+            Err(PushConstantError::OutOfRange(range)) => assert_eq!(range, 60..68),
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        assert!(push_constant_stages(&[], 0, 4).is_err());
+    }
+
+    #[test]
+    fn push_constant_padding() {
+        assert_eq!(padded_size::<u8>(), 4);
+        assert_eq!(padded_size::<[f32; 3]>(), 12);
+        assert_eq!(push_constant_buffer::<[u16; 3]>().len(), 2);
+    }
+}
@@ -9,9 +9,7 @@ use crate::device::DeviceContext;
 
 use crate::graph::builder::resource_descriptor::ResourceType;
 use crate::graph::builder::{ComputePassContext, GraphicsPassContext};
-use crate::graph::execution::{
-    create_pipeline_compute, GraphResources, PassResources, PipelineResources,
-};
+use crate::graph::execution::{ensure_pipeline_compute, GraphResources, PassResources};
 use crate::graph::pass::command::{ComputeCommandBuffer, ReadStorages};
 use crate::graph::pass::{ComputePass, PassId};
 use crate::graph::{CompiledGraph, PrepareError, ResourceId, ResourceName, Storages};
@@ -90,6 +88,12 @@ pub struct BufferWriteRef(pub(crate) BufferHandle);
 #[derive(Clone, Copy, Debug)]
 pub struct BufferReadRef(pub(crate) BufferHandle);
 
+impl From<BufferReadRef> for BufferHandle {
+    fn from(buffer: BufferReadRef) -> Self {
+        buffer.0
+    }
+}
+
 pub use self::compute::*;
 mod compute {
     use super::*;
@@ -271,31 +275,23 @@ mod compute {
             };
 
             // fetch pipeline from cache or create a new one.
-            let compute_pipelines = &mut self.pass_res.compute_pipelines;
-            let pass_materials = &mut self.pass_res.pass_material;
-
-            let pass_mat = pass_materials.get(&self.pass_id).cloned();
-
-            let pipelines = compute_pipelines.entry(self.pass_id).or_default();
-
-            if !pipelines.contains_key(&desc) {
-                // create new pipeline!
-                let pipe = create_pipeline_compute(
-                    self.device,
-                    self.storages,
-                    self.pass_id,
-                    pass_mat,
-                    &desc,
-                )?;
-
-                pipelines.insert(
-                    desc.clone(),
-                    PipelineResources {
-                        pipeline_handle: pipe,
-                    },
-                );
+            let created = ensure_pipeline_compute(
+                self.device,
+                self.storages,
+                self.pass_res,
+                self.pass_id,
+                desc,
+            )?;
+
+            if created {
+                self.pass_res.pipeline_stats.misses += 1;
+            } else {
+                self.pass_res.pipeline_stats.hits += 1;
             }
 
+            let pass_mat = self.pass_res.pass_material.get(&self.pass_id).cloned();
+            let pipelines = &self.pass_res.compute_pipelines[&self.pass_id];
+
             let pipeline_storage = self.storages.pipeline.borrow();
 
             let read_storages = ReadStorages {
@@ -333,6 +329,7 @@ mod compute {
                     buf: self.cmd,
                     storages: &read_storages,
                     pipeline_layout: &pipe_raw.layout,
+                    push_constants: &pipe_raw.push_constants,
                 }
             };
 
@@ -347,7 +344,7 @@ pub use self::graphics::*;
 mod graphics {
     use super::*;
     use crate::graph::builder::resource_descriptor::ImageClearValue;
-    use crate::graph::execution::create_pipeline_graphics;
+    use crate::graph::execution::ensure_pipeline_graphics;
     use crate::graph::pass::command::GraphicsCommandBuffer;
     use crate::graph::pass::GraphicsPass;
 
@@ -608,31 +605,24 @@ mod graphics {
                 pipeline_desc_cache.entry(config).or_insert(desc)
             };
 
-            let graphics_pipelines = &mut self.pass_res.graphic_pipelines;
-            let pass_materials = &mut self.pass_res.pass_material;
-
-            let pass_mat = pass_materials.get(&self.pass_id).cloned();
-            let pipelines = graphics_pipelines.entry(self.pass_id).or_default();
-
-            if !pipelines.contains_key(desc) {
-                // create new pipeline!!
-                let pipe = create_pipeline_graphics(
-                    self.device,
-                    self.storages,
-                    self.pass_id,
-                    pass_mat,
-                    &desc,
-                    render_pass_handle,
-                )?;
-
-                pipelines.insert(
-                    desc.clone(),
-                    PipelineResources {
-                        pipeline_handle: pipe,
-                    },
-                );
+            // fetch pipeline from cache or create a new one.
+            let created = ensure_pipeline_graphics(
+                self.device,
+                self.storages,
+                self.pass_res,
+                self.pass_id,
+                desc,
+            )?;
+
+            if created {
+                self.pass_res.pipeline_stats.misses += 1;
+            } else {
+                self.pass_res.pipeline_stats.hits += 1;
             }
 
+            let pass_mat = self.pass_res.pass_material.get(&self.pass_id).cloned();
+            let pipelines = &self.pass_res.graphic_pipelines[&self.pass_id];
+
             let pipeline_storage = self.storages.pipeline.borrow();
 
             let read_storages = ReadStorages {
@@ -691,6 +681,11 @@ mod graphics {
                 self.cmd.set_viewports(0, &[viewport.clone()]);
                 self.cmd.set_scissors(0, &[viewport.rect]);
 
+                // the stencil reference is dynamic state, start out with a well defined value.
+                if desc.stencil_mode.is_some() {
+                    self.cmd.set_stencil_reference(gfx::pso::Face::all(), 0);
+                }
+
                 {
                     let encoder =
                         self.cmd
@@ -700,6 +695,7 @@ mod graphics {
                         storages: &read_storages,
                         viewport_rect: viewport.rect,
                         pipeline_layout: &pipe_raw.layout,
+                        push_constants: &pipe_raw.push_constants,
                         encoder,
                     };
 
@@ -13,14 +13,17 @@ pub use self::dispatcher::*;
 use crate::graph::{builder, GraphExecError};
 
 use crate::material::MaterialHandle;
+use crate::util::CowString;
 use crate::vertex_attrib::VertexAttrib;
 
 use crate::resources::shader::{
-    ComputeShaderHandle, FragmentShaderHandle, GeometryShaderHandle, VertexShaderHandle,
+    ComputeShaderHandle, FragmentShaderHandle, GeometryShaderHandle, ShaderStages,
+    TessControlShaderHandle, TessEvaluationShaderHandle, VertexShaderHandle,
 };
+use bitflags::bitflags;
 use smallvec::SmallVec;
 
-use std::hash::Hash;
+use std::hash::{Hash, Hasher};
 
 /// Numerical identifier for a pass.
 #[derive(Copy, Clone, Hash, Eq, PartialEq, Ord, PartialOrd, Debug)]
@@ -36,6 +39,8 @@ pub enum Primitive {
     LineStrip,
     TriangleList,
     TriangleStrip,
+    /// Patches with the given number of control points, used with tessellation shaders.
+    PatchList(u8),
 }
 
 impl Default for Primitive {
@@ -52,19 +57,385 @@ impl From<Primitive> for gfx::Primitive {
             Primitive::LineStrip => gfx::Primitive::LineStrip,
             Primitive::TriangleList => gfx::Primitive::TriangleList,
             Primitive::TriangleStrip => gfx::Primitive::TriangleStrip,
+            Primitive::PatchList(control_points) => gfx::Primitive::PatchList(control_points),
         }
     }
 }
 
-// TODO add more modes
+/// Rasterizer settings used in graphics pipelines.
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub struct RasterizerState {
+    /// Faces that get discarded before rasterization.
+    pub cull_mode: CullMode,
+    /// Winding order of front-facing primitives.
+    pub front_face: FrontFace,
+    /// Rasterization mode of polygons.
+    pub polygon_mode: PolygonMode,
+    /// Clamp depth values instead of clipping primitives outside of the depth range.
+    pub depth_clamp: bool,
+    /// Bias added to the depth values of rasterized fragments, if any.
+    pub depth_bias: Option<DepthBias>,
+    /// Width of rasterized lines. Only used with [`PolygonMode::Line`].
+    ///
+    /// [`PolygonMode::Line`]: enum.PolygonMode.html#variant.Line
+    pub line_width: LineWidth,
+}
 
-/// Blend mode used for color attachments.
+impl Default for RasterizerState {
+    fn default() -> Self {
+        RasterizerState {
+            cull_mode: CullMode::None,
+            front_face: FrontFace::CounterClockwise,
+            polygon_mode: PolygonMode::Fill,
+            depth_clamp: false,
+            depth_bias: None,
+            line_width: LineWidth::Static(1.0),
+        }
+    }
+}
+
+impl From<RasterizerState> for gfx::pso::Rasterizer {
+    fn from(state: RasterizerState) -> Self {
+        use gfx::pso::State;
+
+        let line_width = match state.line_width {
+            LineWidth::Static(width) => State::Static(width),
+            LineWidth::Dynamic => State::Dynamic,
+        };
+
+        gfx::pso::Rasterizer {
+            polygon_mode: match state.polygon_mode {
+                PolygonMode::Fill => gfx::pso::PolygonMode::Fill,
+                PolygonMode::Line => gfx::pso::PolygonMode::Line(line_width),
+                PolygonMode::Point => gfx::pso::PolygonMode::Point,
+            },
+            cull_face: match state.cull_mode {
+                CullMode::None => gfx::pso::Face::NONE,
+                CullMode::Front => gfx::pso::Face::FRONT,
+                CullMode::Back => gfx::pso::Face::BACK,
+                CullMode::FrontAndBack => gfx::pso::Face::all(),
+            },
+            front_face: match state.front_face {
+                FrontFace::Clockwise => gfx::pso::FrontFace::Clockwise,
+                FrontFace::CounterClockwise => gfx::pso::FrontFace::CounterClockwise,
+            },
+            depth_clamping: state.depth_clamp,
+            depth_bias: state.depth_bias.map(|bias| match bias {
+                DepthBias::Static {
+                    constant_factor,
+                    clamp,
+                    slope_factor,
+                } => State::Static(gfx::pso::DepthBias {
+                    const_factor: constant_factor,
+                    clamp,
+                    slope_factor,
+                }),
+                DepthBias::Dynamic => State::Dynamic,
+            }),
+            conservative: false,
+        }
+    }
+}
+
+/// Faces that get culled during rasterization.
+#[allow(missing_docs)]
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub enum CullMode {
+    None,
+    Front,
+    Back,
+    FrontAndBack,
+}
+
+/// Winding order that determines which side of a primitive is the front.
 #[allow(missing_docs)]
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub enum FrontFace {
+    Clockwise,
+    CounterClockwise,
+}
+
+/// Rasterization mode of polygons.
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub enum PolygonMode {
+    /// Polygons are filled.
+    Fill,
+    /// Only the edges of polygons are drawn as lines.
+    Line,
+    /// Only the vertices of polygons are drawn as points.
+    Point,
+}
+
+/// Depth bias applied to rasterized fragments.
+///
+/// Dynamic depth bias is set with [`GraphicsCommandBuffer::set_depth_bias`].
+///
+/// [`GraphicsCommandBuffer::set_depth_bias`]: command/struct.GraphicsCommandBuffer.html#method.set_depth_bias
+#[derive(Debug, Clone, Copy)]
+pub enum DepthBias {
+    /// The depth bias is baked into the pipeline.
+    #[allow(missing_docs)]
+    Static {
+        constant_factor: f32,
+        clamp: f32,
+        slope_factor: f32,
+    },
+    /// The depth bias is set while recording commands.
+    Dynamic,
+}
+
+impl PartialEq for DepthBias {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (
+                DepthBias::Static {
+                    constant_factor: c_a,
+                    clamp: cl_a,
+                    slope_factor: s_a,
+                },
+                DepthBias::Static {
+                    constant_factor: c_b,
+                    clamp: cl_b,
+                    slope_factor: s_b,
+                },
+            ) => {
+                c_a.to_bits() == c_b.to_bits()
+                    && cl_a.to_bits() == cl_b.to_bits()
+                    && s_a.to_bits() == s_b.to_bits()
+            }
+            (DepthBias::Dynamic, DepthBias::Dynamic) => true,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for DepthBias {}
+
+impl Hash for DepthBias {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self {
+            DepthBias::Static {
+                constant_factor,
+                clamp,
+                slope_factor,
+            } => {
+                state.write_i8(0);
+                state.write_u32(constant_factor.to_bits());
+                state.write_u32(clamp.to_bits());
+                state.write_u32(slope_factor.to_bits());
+            }
+            DepthBias::Dynamic => {
+                state.write_i8(1);
+            }
+        }
+    }
+}
+
+/// Width of rasterized lines.
+///
+/// Dynamic line width is set with [`GraphicsCommandBuffer::set_line_width`].
+///
+/// [`GraphicsCommandBuffer::set_line_width`]: command/struct.GraphicsCommandBuffer.html#method.set_line_width
+#[derive(Debug, Clone, Copy)]
+pub enum LineWidth {
+    /// The line width is baked into the pipeline.
+    Static(f32),
+    /// The line width is set while recording commands.
+    Dynamic,
+}
+
+impl PartialEq for LineWidth {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (LineWidth::Static(a), LineWidth::Static(b)) => a.to_bits() == b.to_bits(),
+            (LineWidth::Dynamic, LineWidth::Dynamic) => true,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for LineWidth {}
+
+impl Hash for LineWidth {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self {
+            LineWidth::Static(width) => {
+                state.write_i8(0);
+                state.write_u32(width.to_bits());
+            }
+            LineWidth::Dynamic => {
+                state.write_i8(1);
+            }
+        }
+    }
+}
+
+/// Blend mode used for color attachments.
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum BlendMode {
+    /// Regular alpha blending.
     Alpha,
+    /// Alpha blending for colors that are already multiplied by their alpha value.
+    PremultipliedAlpha,
+    /// Additive blending.
     Add,
+    /// Multiplicative blending.
     Mul,
+    /// No blending, the output value replaces the stored value.
+    Replace,
+    /// Custom blend equations for the color and alpha components.
+    Custom(BlendState),
+}
+
+impl From<BlendMode> for gfx::pso::BlendState {
+    fn from(mode: BlendMode) -> Self {
+        use gfx::pso::{BlendOp, BlendState, Factor};
+
+        match mode {
+            BlendMode::Alpha => BlendState::ALPHA,
+            BlendMode::PremultipliedAlpha => {
+                let op = BlendOp::Add {
+                    src: Factor::One,
+                    dst: Factor::OneMinusSrcAlpha,
+                };
+                BlendState::On {
+                    color: op,
+                    alpha: op,
+                }
+            }
+            BlendMode::Add => BlendState::ADD,
+            BlendMode::Mul => BlendState::MULTIPLY,
+            BlendMode::Replace => BlendState::Off,
+            BlendMode::Custom(state) => BlendState::On {
+                color: state.color.into(),
+                alpha: state.alpha.into(),
+            },
+        }
+    }
+}
+
+/// Blend equations used for the color and alpha components of a color attachment.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct BlendState {
+    /// Equation used for the red, green and blue components.
+    pub color: BlendEquation,
+    /// Equation used for the alpha component.
+    pub alpha: BlendEquation,
+}
+
+/// A blend equation combining the source (output) value with the destination (stored) value.
+///
+/// The factors are ignored for the `Min` and `Max` operations.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct BlendEquation {
+    /// Operation combining the weighted source and destination values.
+    pub op: BlendOp,
+    /// Factor the source value is multiplied with.
+    pub src: BlendFactor,
+    /// Factor the destination value is multiplied with.
+    pub dst: BlendFactor,
+}
+
+impl From<BlendEquation> for gfx::pso::BlendOp {
+    fn from(eq: BlendEquation) -> Self {
+        use gfx::pso::BlendOp as GB;
+
+        let src = eq.src.into();
+        let dst = eq.dst.into();
+
+        match eq.op {
+            BlendOp::Add => GB::Add { src, dst },
+            BlendOp::Subtract => GB::Sub { src, dst },
+            BlendOp::ReverseSubtract => GB::RevSub { src, dst },
+            BlendOp::Min => GB::Min,
+            BlendOp::Max => GB::Max,
+        }
+    }
+}
+
+/// Operations used in blend equations.
+#[allow(missing_docs)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BlendOp {
+    Add,
+    Subtract,
+    ReverseSubtract,
+    Min,
+    Max,
+}
+
+/// Factors used in blend equations.
+///
+/// The `Const*` factors use the blend constants set with
+/// [`GraphicsCommandBuffer::set_blend_constants`].
+///
+/// [`GraphicsCommandBuffer::set_blend_constants`]: command/struct.GraphicsCommandBuffer.html#method.set_blend_constants
+#[allow(missing_docs)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BlendFactor {
+    Zero,
+    One,
+    SrcColor,
+    OneMinusSrcColor,
+    DstColor,
+    OneMinusDstColor,
+    SrcAlpha,
+    OneMinusSrcAlpha,
+    DstAlpha,
+    OneMinusDstAlpha,
+    ConstColor,
+    OneMinusConstColor,
+    ConstAlpha,
+    OneMinusConstAlpha,
+    SrcAlphaSaturate,
+}
+
+impl From<BlendFactor> for gfx::pso::Factor {
+    fn from(factor: BlendFactor) -> Self {
+        use self::BlendFactor as F;
+        use gfx::pso::Factor as GF;
+        match factor {
+            F::Zero => GF::Zero,
+            F::One => GF::One,
+            F::SrcColor => GF::SrcColor,
+            F::OneMinusSrcColor => GF::OneMinusSrcColor,
+            F::DstColor => GF::DstColor,
+            F::OneMinusDstColor => GF::OneMinusDstColor,
+            F::SrcAlpha => GF::SrcAlpha,
+            F::OneMinusSrcAlpha => GF::OneMinusSrcAlpha,
+            F::DstAlpha => GF::DstAlpha,
+            F::OneMinusDstAlpha => GF::OneMinusDstAlpha,
+            F::ConstColor => GF::ConstColor,
+            F::OneMinusConstColor => GF::OneMinusConstColor,
+            F::ConstAlpha => GF::ConstAlpha,
+            F::OneMinusConstAlpha => GF::OneMinusConstAlpha,
+            F::SrcAlphaSaturate => GF::SrcAlphaSaturate,
+        }
+    }
+}
+
+bitflags!(
+    /// Mask of color components written to a color attachment.
+    pub struct ColorMask: u8 {
+        /// Red component.
+        const RED = 0x1;
+        /// Green component.
+        const GREEN = 0x2;
+        /// Blue component.
+        const BLUE = 0x4;
+        /// Alpha component.
+        const ALPHA = 0x8;
+        /// All color components without alpha.
+        const COLOR = 0x7;
+        /// All components.
+        const ALL = 0xf;
+    }
+);
+
+impl From<ColorMask> for gfx::pso::ColorMask {
+    fn from(mask: ColorMask) -> Self {
+        gfx::pso::ColorMask::from_bits_truncate(mask.bits())
+    }
 }
 
 /// Depth-test mode description.
@@ -76,6 +447,107 @@ pub struct DepthMode {
     pub write: bool,
 }
 
+/// Stencil-test mode description.
+///
+/// The stencil reference value is dynamic state and can be changed during command recording
+/// using [`GraphicsCommandBuffer::set_stencil_reference`]. It defaults to `0` whenever a pipeline
+/// with a stencil mode is bound.
+///
+/// [`GraphicsCommandBuffer::set_stencil_reference`]: command/struct.GraphicsCommandBuffer.html#method.set_stencil_reference
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub struct StencilMode {
+    /// Stencil test and operations used for front-facing primitives.
+    pub front: StencilFace,
+    /// Stencil test and operations used for back-facing primitives.
+    pub back: StencilFace,
+}
+
+impl StencilMode {
+    /// Create a stencil mode which uses the same test and operations for front and back faces.
+    pub fn both(face: StencilFace) -> Self {
+        StencilMode {
+            front: face,
+            back: face,
+        }
+    }
+}
+
+/// Stencil test and operations for one face orientation.
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub struct StencilFace {
+    /// Function used to compare the (masked) reference value with the (masked) stored value.
+    pub func: Comparison,
+    /// Operation performed when both the stencil and the depth test pass.
+    pub pass: StencilOp,
+    /// Operation performed when the stencil test fails.
+    pub fail: StencilOp,
+    /// Operation performed when the stencil test passes but the depth test fails.
+    pub depth_fail: StencilOp,
+    /// Mask applied to the reference and stored value before comparing.
+    pub read_mask: u32,
+    /// Mask selecting the bits that get written by the stencil operations.
+    pub write_mask: u32,
+}
+
+impl Default for StencilFace {
+    fn default() -> Self {
+        StencilFace {
+            func: Comparison::Always,
+            pass: StencilOp::Keep,
+            fail: StencilOp::Keep,
+            depth_fail: StencilOp::Keep,
+            read_mask: !0,
+            write_mask: !0,
+        }
+    }
+}
+
+/// Operations performed on stored stencil values.
+#[allow(missing_docs)]
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub enum StencilOp {
+    Keep,
+    Zero,
+    Replace,
+    IncrementClamp,
+    DecrementClamp,
+    Invert,
+    IncrementWrap,
+    DecrementWrap,
+}
+
+impl From<StencilOp> for gfx::pso::StencilOp {
+    fn from(op: StencilOp) -> Self {
+        use self::StencilOp as S;
+        use gfx::pso::StencilOp as GS;
+        match op {
+            S::Keep => GS::Keep,
+            S::Zero => GS::Zero,
+            S::Replace => GS::Replace,
+            S::IncrementClamp => GS::IncrementClamp,
+            S::DecrementClamp => GS::DecrementClamp,
+            S::Invert => GS::Invert,
+            S::IncrementWrap => GS::IncrementWrap,
+            S::DecrementWrap => GS::DecrementWrap,
+        }
+    }
+}
+
+impl From<StencilFace> for gfx::pso::StencilFace {
+    fn from(face: StencilFace) -> Self {
+        use gfx::pso::State;
+        gfx::pso::StencilFace {
+            fun: face.func.into(),
+            mask_read: State::Static(face.read_mask),
+            mask_write: State::Static(face.write_mask),
+            op_fail: face.fail.into(),
+            op_depth_fail: face.depth_fail.into(),
+            op_pass: face.pass.into(),
+            reference: State::Dynamic,
+        }
+    }
+}
+
 /// Comparison modes used for depth and stencil tests.
 #[allow(missing_docs)]
 #[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
@@ -107,6 +579,27 @@ impl From<Comparison> for gfx::pso::Comparison {
     }
 }
 
+/// Range of push-constant memory (in bytes) accessible from a set of shader stages.
+///
+/// The start and end of the range have to be multiples of 4.
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+pub struct PushConstantRange {
+    /// Shader stages that can access the range.
+    pub stages: ShaderStages,
+    /// Range of the push-constant memory (in bytes).
+    pub range: std::ops::Range<u32>,
+}
+
+impl PushConstantRange {
+    /// Create a push-constant range accessible from all graphics shader stages.
+    pub fn graphics(range: std::ops::Range<u32>) -> Self {
+        PushConstantRange {
+            stages: ShaderStages::GRAPHICS,
+            range,
+        }
+    }
+}
+
 /// Set of shaders used in graphics passes.
 #[derive(Debug, Clone, Hash, Eq, PartialEq)]
 pub struct GraphicShaders {
@@ -116,6 +609,14 @@ pub struct GraphicShaders {
     pub fragment: Option<Shader<FragmentShaderHandle>>,
     /// Optional geometry-shader description.
     pub geometry: Option<Shader<GeometryShaderHandle>>,
+    /// Optional tessellation-control-shader description.
+    ///
+    /// Tessellation shaders have to be used together and need [`Primitive::PatchList`].
+    ///
+    /// [`Primitive::PatchList`]: enum.Primitive.html#variant.PatchList
+    pub tess_control: Option<Shader<TessControlShaderHandle>>,
+    /// Optional tessellation-evaluation-shader description.
+    pub tess_evaluation: Option<Shader<TessEvaluationShaderHandle>>,
 }
 
 /// Description of a graphics pass pipeline.
@@ -126,18 +627,25 @@ pub struct GraphicsPipelineInfo {
     /// Depth mode used for a possible depth attachment.
     pub depth_mode: Option<DepthMode>,
     /// Stencil mode used for a possible stencil attachment.
-    /// TODO
-    pub stencil_mode: Option<()>,
+    pub stencil_mode: Option<StencilMode>,
     /// Set of shader programs.
     pub shaders: GraphicShaders,
     /// Primitive mode used for rasterization.
     pub primitive: Primitive,
+    /// Rasterizer settings.
+    pub rasterizer: RasterizerState,
     /// Blend modes used for the color attachments.
     pub blend_modes: Vec<BlendMode>,
+    /// Color components written to the color attachments.
+    ///
+    /// Attachments without an entry use [`ColorMask::ALL`].
+    ///
+    /// [`ColorMask::ALL`]: struct.ColorMask.html#associatedconstant.ALL
+    pub color_masks: Vec<ColorMask>,
     /// Materials used in the pass with their associated set-bindings.
     pub materials: Vec<(usize, MaterialHandle)>,
-    /// Range of push constants used.
-    pub push_constants: Option<std::ops::Range<u32>>,
+    /// Push-constant ranges used, together with the shader stages accessing them.
+    pub push_constants: Vec<PushConstantRange>,
 }
 
 /// Trait used to implement graphics pass functionality.
@@ -154,6 +662,14 @@ pub trait GraphicsPass: Sized {
     /// Create a graphics-pipeline info from a given configuration.
     fn configure(&self, config: &Self::Config) -> GraphicsPipelineInfo;
 
+    /// List the configurations the pass is going to use.
+    ///
+    /// Pipelines for these configurations are created when the graph is created, so that no
+    /// pipeline compilation has to happen during the first executions.
+    fn configs(&self) -> Vec<Self::Config> {
+        vec![]
+    }
+
     /// The `describe` function is called during graph compilation and records all resource
     /// creations and dependencies in the graph-`builder`.
     fn describe(&mut self, res: &mut builder::ResourceDescriptor);
@@ -175,6 +691,9 @@ pub trait GraphicsPass: Sized {
 pub struct Specialization {
     pub(crate) id: u32,
     pub(crate) value: SmallVec<[u8; 256]>,
+
+    /// preprocessor define selected by a shader variant, used instead of a constant.
+    pub(crate) define: Option<CowString>,
 }
 
 impl Specialization {
@@ -185,6 +704,7 @@ impl Specialization {
         let mut spec_constant = Specialization {
             id,
             value: SmallVec::with_capacity(data_size),
+            define: None,
         };
 
         unsafe {
@@ -199,6 +719,19 @@ impl Specialization {
 
         spec_constant
     }
+
+    /// Select a preprocessor define instead of setting a constant.
+    ///
+    /// The shader gets compiled again with the define, see [`ShaderVariantSet`].
+    ///
+    /// [`ShaderVariantSet`]: ../../shader_variant/struct.ShaderVariantSet.html
+    pub(crate) fn define(name: impl Into<CowString>) -> Self {
+        Specialization {
+            id: 0,
+            value: SmallVec::new(),
+            define: Some(name.into()),
+        }
+    }
 }
 
 /// A description of a shader object used for pipeline-creation.
@@ -215,7 +748,9 @@ pub struct Shader<HandleType> {
 pub struct ComputePipelineInfo {
     /// Materials used, described by the descriptor-set binding and a handle to the material.
     pub materials: Vec<(usize, MaterialHandle)>,
-    /// Push-constant range (in bytes).
+    /// Push-constant range (in bytes) accessible from the compute shader.
+    ///
+    /// The start and end of the range have to be multiples of 4.
     pub push_constant_range: Option<std::ops::Range<u32>>,
     /// Shader used in this pipeline.
     pub shader: Shader<ComputeShaderHandle>,
@@ -235,6 +770,14 @@ pub trait ComputePass: Sized {
     /// Create a compute-pipeline info from a given configuration.
     fn configure(&self, config: &Self::Config) -> ComputePipelineInfo;
 
+    /// List the configurations the pass is going to use.
+    ///
+    /// Pipelines for these configurations are created when the graph is created, so that no
+    /// pipeline compilation has to happen during the first executions.
+    fn configs(&self) -> Vec<Self::Config> {
+        vec![]
+    }
+
     /// The `describe` function is called during graph compilation and records all resource
     /// creations and dependencies in the graph-`builder`.
     fn describe(&mut self, res: &mut builder::ResourceDescriptor);
@@ -79,9 +79,15 @@ pub use crate::resources::buffer;
 pub use crate::resources::image;
 pub use crate::resources::material;
 pub(crate) use crate::resources::pipeline;
+pub use crate::resources::pipeline_cache;
 pub(crate) use crate::resources::render_pass;
 pub use crate::resources::sampler;
 pub use crate::resources::shader;
+#[cfg(feature = "shaderc")]
+pub use crate::resources::shader_compiler;
+pub use crate::resources::shader_variant;
+pub use crate::resources::shader_watcher;
+pub use crate::resources::spirv;
 pub use crate::resources::vertex_attrib;
 
 pub mod graph;
@@ -152,7 +158,7 @@ impl Context {
         let image_storage = image::ImageStorage::new();
         let sampler_storage = sampler::SamplerStorage::new();
         let buffer_storage = buffer::BufferStorage::new(memory_atom_size);
-        let pipeline_storage = pipeline::PipelineStorage::new();
+        let pipeline_storage = pipeline::PipelineStorage::new(&device_ctx);
         let render_pass_storage = render_pass::RenderPassStorage::new();
         let material_storage = material::MaterialStorage::new();
         let shader_storage = shader::ShaderStorage::new();
@@ -240,6 +246,8 @@ impl Context {
         self.sampler_storage.into_inner().release(&self.device_ctx);
 
         self.material_storage.into_inner().release(&self.device_ctx);
+        self.pipeline_storage.into_inner().release(&self.device_ctx);
+        self.shader_storage.into_inner().release(&self.device_ctx);
 
         for (_, display) in self.displays {
             display.release(&self.device_ctx);
@@ -270,6 +278,35 @@ impl Context {
         self.image_storage.borrow().usage(image)
     }
 
+    /// Query the ways images with the given format can be used on the current device.
+    pub fn image_format_support(&self, format: image::ImageFormat) -> image::ImageUsage {
+        image::format_support(&self.device_ctx, format)
+    }
+
+    // pipeline cache
+
+    /// Replace the pipeline cache with data previously retrieved using [`pipeline_cache_save`].
+    ///
+    /// Data that was saved on a different adapter, with a driver that can't use it anymore or that
+    /// is corrupted is rejected. In that case the current cache stays in use.
+    ///
+    /// [`pipeline_cache_save`]: #method.pipeline_cache_save
+    pub unsafe fn pipeline_cache_load(
+        &mut self,
+        data: &[u8],
+    ) -> Result<(), pipeline_cache::PipelineCacheError> {
+        self.pipeline_storage
+            .borrow_mut()
+            .load_cache(&self.device_ctx, data)
+    }
+
+    /// Retrieve the content of the pipeline cache so it can be stored on disk.
+    pub unsafe fn pipeline_cache_save(
+        &self,
+    ) -> Result<Vec<u8>, pipeline_cache::PipelineCacheError> {
+        self.pipeline_storage.borrow().save_cache(&self.device_ctx)
+    }
+
     // sampler
 
     /// Create sampler objects and retrieve handles for them.
@@ -313,6 +350,19 @@ impl Context {
             .device_local_create(&self.device_ctx, create_info)
     }
 
+    /// Create a view of a buffer which can be used as a uniform- or storage-texel buffer.
+    ///
+    /// The range has to lie within the buffer and the format has to support the texel usages
+    /// the buffer was created with.
+    pub unsafe fn buffer_view_create(
+        &mut self,
+        create_info: buffer::BufferViewCreateInfo,
+    ) -> Result<buffer::BufferViewHandle, buffer::BufferError> {
+        self.buffer_storage
+            .borrow_mut()
+            .view_create(&self.device_ctx, create_info)
+    }
+
     // material
 
     /// Create material objects and retrieve handles for them.
@@ -324,9 +374,11 @@ impl Context {
         &mut self,
         create_info: material::MaterialCreateInfo,
     ) -> Result<material::MaterialHandle, material::MaterialError> {
-        self.material_storage
-            .borrow_mut()
-            .create(&self.device_ctx, create_info)
+        self.material_storage.borrow_mut().create(
+            &self.device_ctx,
+            &mut *self.sampler_storage.borrow_mut(),
+            create_info,
+        )
     }
 
     /// Create material instances and retrieve handles for them.
@@ -344,13 +396,17 @@ impl Context {
     }
 
     /// Update a material instance with resource handles.
-    pub unsafe fn material_write_instance<T>(
+    ///
+    /// Fails without writing anything if any of the writes does not match the parameters of
+    /// the material.
+    pub unsafe fn material_write_instance<'w, T>(
         &mut self,
         instance: material::MaterialInstanceHandle,
         data: T,
-    ) where
+    ) -> Result<(), material::MaterialError>
+    where
         T: IntoIterator,
-        T::Item: ::std::borrow::Borrow<material::InstanceWrite>,
+        T::Item: ::std::borrow::Borrow<material::InstanceWrite<'w>>,
     {
         self.material_storage.borrow_mut().write_instance(
             &self.device_ctx,
@@ -359,7 +415,7 @@ impl Context {
             &*self.buffer_storage.borrow(),
             instance,
             data,
-        );
+        )
     }
 
     // graph
@@ -369,19 +425,67 @@ impl Context {
         &mut self,
         builder: graph::GraphBuilder,
     ) -> Result<graph::GraphHandle, graph::GraphError> {
-        let mut storages = graph::Storages {
+        let mut res_list = submit_group::ResourceList::new(self.device_ctx.clone());
+
+        let handle = {
+            let mut storages = graph::Storages {
+                shader: &self.shader_storage,
+                render_pass: &mut self.render_pass_storage,
+                pipeline: &mut self.pipeline_storage,
+                image: &mut self.image_storage,
+                buffer: &mut self.buffer_storage,
+                sampler: &mut self.sampler_storage,
+                material: &mut self.material_storage,
+            };
+
+            self.graph_storage.borrow_mut().create(
+                &self.device_ctx,
+                &mut storages,
+                &mut res_list,
+                builder,
+            )
+        };
+
+        // resources of a graph that failed to be created were never used by the device, so
+        // they can be freed right away.
+        res_list.free_resources(self);
+
+        handle
+    }
+
+    /// Create the pipelines for all configurations listed by the passes of a graph.
+    ///
+    /// This already happens when the graph is created, but passes might list new configurations
+    /// later on. Returns the number of pipelines that were created.
+    pub unsafe fn graph_prewarm(
+        &mut self,
+        graph: graph::GraphHandle,
+    ) -> Result<usize, graph::GraphExecError> {
+        let storages = graph::Storages {
             shader: &self.shader_storage,
-            render_pass: &mut self.render_pass_storage,
-            pipeline: &mut self.pipeline_storage,
-            image: &mut self.image_storage,
-            buffer: &mut self.buffer_storage,
-            sampler: &mut self.sampler_storage,
-            material: &mut self.material_storage,
+            render_pass: &self.render_pass_storage,
+            pipeline: &self.pipeline_storage,
+            image: &self.image_storage,
+            buffer: &self.buffer_storage,
+            sampler: &self.sampler_storage,
+            material: &self.material_storage,
         };
 
         self.graph_storage
             .borrow_mut()
-            .create(&self.device_ctx, &mut storages, builder)
+            .prewarm(&self.device_ctx, &storages, graph)
+    }
+
+    /// Retrieve statistics about the pipelines created for a graph.
+    pub fn graph_pipeline_stats(&self, graph: graph::GraphHandle) -> Option<graph::PipelineStats> {
+        self.graph_storage.borrow().pipeline_stats(graph)
+    }
+
+    /// Set the limits for the pipelines kept alive by the passes of all graphs.
+    ///
+    /// Pipelines exceeding the budget are evicted before a graph gets executed.
+    pub fn graph_pipeline_budget_set(&mut self, budget: graph::PipelineBudget) {
+        self.graph_storage.borrow_mut().pipeline_budget = budget;
     }
 
     // shader
@@ -390,7 +494,7 @@ impl Context {
     pub fn compute_shader_create(
         &mut self,
         info: shader::ShaderInfo,
-    ) -> shader::ComputeShaderHandle {
+    ) -> Result<shader::ComputeShaderHandle, shader::ShaderError> {
         self.shader_storage.borrow_mut().create_compute_shader(info)
     }
 
@@ -402,7 +506,10 @@ impl Context {
     }
 
     /// Create a vertex shader and retrieve the handle.
-    pub fn vertex_shader_create(&mut self, info: shader::ShaderInfo) -> shader::VertexShaderHandle {
+    pub fn vertex_shader_create(
+        &mut self,
+        info: shader::ShaderInfo,
+    ) -> Result<shader::VertexShaderHandle, shader::ShaderError> {
         self.shader_storage.borrow_mut().create_vertex_shader(info)
     }
 
@@ -417,7 +524,7 @@ impl Context {
     pub fn fragment_shader_create(
         &mut self,
         info: shader::ShaderInfo,
-    ) -> shader::FragmentShaderHandle {
+    ) -> Result<shader::FragmentShaderHandle, shader::ShaderError> {
         self.shader_storage
             .borrow_mut()
             .create_fragment_shader(info)
@@ -434,7 +541,7 @@ impl Context {
     pub fn geometry_shader_create(
         &mut self,
         info: shader::ShaderInfo,
-    ) -> shader::GeometryShaderHandle {
+    ) -> Result<shader::GeometryShaderHandle, shader::ShaderError> {
         self.shader_storage
             .borrow_mut()
             .create_geometry_shader(info)
@@ -447,6 +554,85 @@ impl Context {
             .destroy_geometry_shader(handle);
     }
 
+    /// Create a tessellation control (hull) shader and retrieve the handle.
+    pub fn tess_control_shader_create(
+        &mut self,
+        info: shader::ShaderInfo,
+    ) -> Result<shader::TessControlShaderHandle, shader::ShaderError> {
+        self.shader_storage
+            .borrow_mut()
+            .create_tess_control_shader(info)
+    }
+
+    /// Destroy a tessellation control shader object.
+    pub fn tess_control_shader_destroy(&mut self, handle: shader::TessControlShaderHandle) {
+        self.shader_storage
+            .borrow_mut()
+            .destroy_tess_control_shader(handle);
+    }
+
+    /// Create a tessellation evaluation (domain) shader and retrieve the handle.
+    pub fn tess_evaluation_shader_create(
+        &mut self,
+        info: shader::ShaderInfo,
+    ) -> Result<shader::TessEvaluationShaderHandle, shader::ShaderError> {
+        self.shader_storage
+            .borrow_mut()
+            .create_tess_evaluation_shader(info)
+    }
+
+    /// Destroy a tessellation evaluation shader object.
+    pub fn tess_evaluation_shader_destroy(&mut self, handle: shader::TessEvaluationShaderHandle) {
+        self.shader_storage
+            .borrow_mut()
+            .destroy_tess_evaluation_shader(handle);
+    }
+
+    /// Replace the program of a shader of any type.
+    ///
+    /// Pipelines which use the shader are destroyed and recreated the next time they are used
+    /// in a graph execution. If the new program is invalid the old one stays in place.
+    ///
+    /// Shaders created from source code lose their source, so variants with additional defines
+    /// can no longer be used with them.
+    pub fn shader_replace(
+        &mut self,
+        handle: impl Into<shader::ShaderHandle>,
+        info: shader::ShaderInfo,
+    ) -> Result<(), shader::ShaderError> {
+        self.shader_storage
+            .borrow_mut()
+            .replace(handle.into(), info)
+    }
+
+    /// Compile GLSL or HLSL source code and create a shader of the given kind.
+    ///
+    /// `defines` are passed to the preprocessor as `#define name value`. Files included by the
+    /// source code are provided by the `include_resolver`, without one includes are errors.
+    ///
+    /// The compiled code is cached, compiling the same source with the same defines again does
+    /// not invoke the compiler. The source is kept with the shader, so [`ShaderVariantSet`]s can
+    /// select additional defines.
+    ///
+    /// [`ShaderVariantSet`]: ./shader_variant/struct.ShaderVariantSet.html
+    #[cfg(feature = "shaderc")]
+    pub fn shader_create_from_source(
+        &mut self,
+        kind: shader_compiler::ShaderKind,
+        source: shader_compiler::ShaderSource,
+        entry: &str,
+        defines: &[(&str, Option<&str>)],
+        include_resolver: Option<std::rc::Rc<dyn shader_compiler::IncludeResolver>>,
+    ) -> Result<shader::ShaderHandle, shader_compiler::ShaderCompileError> {
+        self.shader_storage.borrow_mut().create_from_source(
+            kind,
+            source,
+            entry,
+            defines,
+            include_resolver,
+        )
+    }
+
     // submit group
 
     /// Create a new [`SubmitGroup`] to record and execute commands
@@ -9,13 +9,18 @@ use bitflags::bitflags;
 use std;
 use std::borrow::Borrow;
 use std::collections::BTreeSet;
+use std::ops::Range;
+
+use smallvec::SmallVec;
 
 use crate::device::DeviceContext;
+use crate::types;
 
 use crate::util::allocator::{AllocatorError, Buffer as AllocBuffer, BufferRequest};
 use crate::util::storage::{Handle, Storage};
 
 use crate::resources::command_pool::CommandPoolTransfer;
+use crate::resources::image::{self, ImageFormat};
 use crate::submit_group::{QueueSyncRefs, ResourceList};
 
 pub(crate) type BufferTypeInternal = AllocBuffer;
@@ -30,13 +35,42 @@ pub(crate) type BufferTypeInternal = AllocBuffer;
 pub struct Buffer {
     pub(crate) buffer: BufferTypeInternal,
     size: u64,
-    _usage: gfx::buffer::Usage,
+    pub(crate) usage: gfx::buffer::Usage,
     _properties: gfx::memory::Properties,
+    views: SmallVec<[BufferViewHandle; 2]>,
 }
 
 /// Opaque handle to a buffer object.
 pub type BufferHandle = Handle<Buffer>;
 
+/// A buffer view interprets a region of a buffer as an array of formatted texels.
+///
+/// Buffer views are needed to use a buffer as a uniform-texel or storage-texel buffer.
+/// A view is destroyed together with the buffer it was created from.
+#[derive(Debug)]
+pub struct BufferView {
+    pub(crate) view: types::BufferView,
+    pub(crate) buffer: BufferHandle,
+}
+
+/// Opaque handle to a buffer view.
+pub type BufferViewHandle = Handle<BufferView>;
+
+/// Information needed to create a buffer view.
+#[derive(Debug, Clone)]
+pub struct BufferViewCreateInfo {
+    /// The buffer to create the view for.
+    ///
+    /// The buffer needs to be created with `UNIFORM_TEXEL` or `STORAGE_TEXEL` usage.
+    pub buffer: BufferHandle,
+    /// Format of the texels in the buffer.
+    pub format: ImageFormat,
+    /// Region of the buffer covered by the view (in bytes).
+    ///
+    /// The start has to be a multiple of the device's texel buffer offset alignment.
+    pub range: Range<Option<u64>>,
+}
+
 /// Errors that can occur when operating on buffer objects.
 #[derive(Debug, Display, From)]
 #[allow(missing_docs)]
@@ -55,6 +89,51 @@ pub enum BufferError {
 
     #[display(fmt = "The buffer could not be written to (not CPU visible and not TRANSFER_DST)")]
     CantWriteToBuffer,
+
+    #[display(fmt = "Buffers can not have a size of 0 bytes")]
+    ZeroSize,
+
+    #[display(fmt = "The size of the buffer does not fit in 64 bits")]
+    SizeOverflow,
+
+    #[display(fmt = "The tile size of a per-tile buffer can not be 0")]
+    InvalidTileSize,
+
+    #[display(fmt = "The buffer can not be viewed (not UNIFORM_TEXEL and not STORAGE_TEXEL)")]
+    CantViewBuffer,
+
+    #[display(fmt = "The range of the buffer view is empty or exceeds the buffer")]
+    ViewOutOfBounds,
+
+    #[display(
+        fmt = "The buffer view offset {} is not a multiple of the required alignment {}",
+        offset,
+        alignment
+    )]
+    MisalignedViewOffset { offset: u64, alignment: u64 },
+
+    #[display(
+        fmt = "The buffer view range of {} bytes is not a multiple of the texel size {}",
+        range,
+        texel_size
+    )]
+    MisalignedViewRange { range: u64, texel_size: u64 },
+
+    #[display(
+        fmt = "The buffer view has {} texels, more than the maximum of {}",
+        elements,
+        max
+    )]
+    TooManyViewElements { elements: u64, max: u64 },
+
+    #[display(
+        fmt = "The format {:?} can not be used for texel buffers with the usage of the buffer",
+        _0
+    )]
+    UnsupportedViewFormat(ImageFormat),
+
+    #[display(fmt = "Failed to create buffer view")]
+    CantCreateView(gfx::buffer::ViewCreationError),
 }
 
 impl std::error::Error for BufferError {}
@@ -121,6 +200,70 @@ impl From<BufferUsage> for gfx::buffer::Usage {
     }
 }
 
+/// Size mode used for buffer resources created in graphs.
+///
+/// Buffers holding per-pixel or per-tile data can be sized relative to the context reference
+/// size, in which case they are recreated when the context changes. See [`ExecutionContext`].
+///
+/// [`ExecutionContext`]: ../graph/struct.ExecutionContext.html
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum BufferSizeMode {
+    /// The size of the buffer is specified directly (in bytes).
+    #[allow(missing_docs)]
+    Absolute { size: u64 },
+
+    /// The buffer holds `bytes` bytes for every pixel of the context reference size.
+    #[allow(missing_docs)]
+    PerPixel { bytes: u64 },
+
+    /// The buffer holds `bytes` bytes for every `tile_size` x `tile_size` tile covering the
+    /// context reference size. Partially covered tiles at the border count as whole tiles.
+    #[allow(missing_docs)]
+    PerTile { tile_size: u32, bytes: u64 },
+}
+
+impl BufferSizeMode {
+    /// Compute the size of the buffer in bytes using the context reference size.
+    ///
+    /// Sizes of 0 bytes (for example from an empty reference size) and sizes which overflow are
+    /// errors.
+    pub fn absolute(&self, reference: (u32, u32)) -> Result<u64, BufferError> {
+        let (width, height) = (u64::from(reference.0), u64::from(reference.1));
+
+        let size = match *self {
+            BufferSizeMode::Absolute { size } => Some(size),
+            BufferSizeMode::PerPixel { bytes } => (width * height).checked_mul(bytes),
+            BufferSizeMode::PerTile { tile_size, bytes } => {
+                if tile_size == 0 {
+                    return Err(BufferError::InvalidTileSize);
+                }
+
+                let tile_size = u64::from(tile_size);
+
+                // can't overflow, all values fit in 32 bits
+                let tiles_x = (width + tile_size - 1) / tile_size;
+                let tiles_y = (height + tile_size - 1) / tile_size;
+
+                (tiles_x * tiles_y).checked_mul(bytes)
+            }
+        };
+
+        match size {
+            Some(0) => Err(BufferError::ZeroSize),
+            Some(size) => Ok(size),
+            None => Err(BufferError::SizeOverflow),
+        }
+    }
+
+    /// Returns `true` if the size depends on the context reference size.
+    pub fn is_context_relative(&self) -> bool {
+        match self {
+            BufferSizeMode::Absolute { .. } => false,
+            BufferSizeMode::PerPixel { .. } | BufferSizeMode::PerTile { .. } => true,
+        }
+    }
+}
+
 /// Description of a cpu-visible buffer's properties.
 ///
 /// A cpu-visible buffer is backed by memory visible both from the host and the device.
@@ -164,6 +307,7 @@ pub(crate) struct BufferStorage {
     device_local: BTreeSet<usize>,
 
     buffers: Storage<Buffer>,
+    views: Storage<BufferView>,
 
     atom_size: usize,
 }
@@ -174,14 +318,21 @@ impl BufferStorage {
             cpu_visible: BTreeSet::new(),
             device_local: BTreeSet::new(),
             buffers: Storage::new(),
+            views: Storage::new(),
 
             atom_size,
         }
     }
 
     pub(crate) unsafe fn release(self, device: &DeviceContext) {
+        use gfx::Device;
+
         let mut alloc = device.allocator();
 
+        for (_, view) in self.views {
+            device.device.destroy_buffer_view(view.view);
+        }
+
         for (_, buffer) in self.buffers {
             alloc.destroy_buffer(&device.device, buffer.buffer);
         }
@@ -206,6 +357,10 @@ impl BufferStorage {
     {
         use gfx::memory::Properties;
 
+        if create_info.size == 0 {
+            return Err(BufferError::ZeroSize);
+        }
+
         let mut allocator = device.allocator();
 
         let props = Properties::CPU_VISIBLE | Properties::COHERENT;
@@ -234,7 +389,8 @@ impl BufferStorage {
             size,
             buffer: raw_buffer,
             _properties: props,
-            _usage: usage,
+            usage,
+            views: SmallVec::new(),
         };
 
         let handle = self.buffers.insert(buffer);
@@ -293,6 +449,10 @@ impl BufferStorage {
     {
         use gfx::memory::Properties;
 
+        if create_info.size == 0 {
+            return Err(BufferError::ZeroSize);
+        }
+
         let mut allocator = device.allocator();
 
         let props = Properties::DEVICE_LOCAL;
@@ -321,7 +481,8 @@ impl BufferStorage {
             size,
             buffer: raw_buffer,
             _properties: props,
-            _usage: usage,
+            usage,
+            views: SmallVec::new(),
         };
 
         let handle = self.buffers.insert(buffer);
@@ -391,6 +552,80 @@ impl BufferStorage {
         Ok(())
     }
 
+    pub(crate) fn view_raw(&self, handle: BufferViewHandle) -> Option<&BufferView> {
+        self.views.get(handle)
+    }
+
+    /// Create a view of a buffer, checking the range and format against the buffer and device.
+    pub(crate) unsafe fn view_create(
+        &mut self,
+        device: &DeviceContext,
+        create_info: BufferViewCreateInfo,
+    ) -> Result<BufferViewHandle, BufferError> {
+        use gfx::adapter::PhysicalDevice;
+        use gfx::buffer::Usage;
+        use gfx::Device;
+
+        let buffer = self
+            .buffers
+            .get_mut(create_info.buffer)
+            .ok_or(BufferError::HandleInvalid)?;
+
+        if !buffer
+            .usage
+            .intersects(Usage::UNIFORM_TEXEL | Usage::STORAGE_TEXEL)
+        {
+            return Err(BufferError::CantViewBuffer);
+        }
+
+        let limits = device.adapter.physical_device.limits();
+
+        validate_view(
+            buffer.size,
+            buffer.usage,
+            &create_info.range,
+            limits.min_texel_buffer_offset_alignment,
+            limits.max_texel_buffer_elements as u64,
+            image::buffer_format_support(device, create_info.format),
+            create_info.format,
+        )?;
+
+        let view = device.device.create_buffer_view(
+            buffer.buffer.raw(),
+            Some(create_info.format.into()),
+            (create_info.range.start, create_info.range.end),
+        )?;
+
+        let handle = self.views.insert(BufferView {
+            view,
+            buffer: create_info.buffer,
+        });
+
+        buffer.views.push(handle);
+
+        Ok(handle)
+    }
+
+    pub(crate) fn view_destroy<V>(&mut self, res_list: &mut ResourceList, views: V)
+    where
+        V: IntoIterator,
+        V::Item: std::borrow::Borrow<BufferViewHandle>,
+    {
+        for handle in views.into_iter() {
+            let handle = *handle.borrow();
+            let view = match self.views.remove(handle) {
+                Some(view) => view,
+                None => continue,
+            };
+
+            if let Some(buffer) = self.buffers.get_mut(view.buffer) {
+                buffer.views.retain(|v| *v != handle);
+            }
+
+            res_list.queue_buffer_view(view.view);
+        }
+    }
+
     pub fn destroy<B>(&mut self, res_list: &mut ResourceList, buffers: B)
     where
         B: IntoIterator,
@@ -404,6 +639,13 @@ impl BufferStorage {
             };
             self.device_local.remove(&handle.0);
             self.cpu_visible.remove(&handle.0);
+
+            for view in buffer.views {
+                if let Some(view) = self.views.remove(view) {
+                    res_list.queue_buffer_view(view.view);
+                }
+            }
+
             res_list.queue_buffer(buffer.buffer);
         }
     }
@@ -494,3 +736,185 @@ unsafe fn read_data_from_buffer(
 
     Ok(())
 }
+
+/// Check that a view covering `range` can be created for a buffer of `size` bytes.
+///
+/// `features` are the texel buffer features of the format supported by the device. Views
+/// reaching to the end of the buffer cover as many whole texels as fit, explicit ranges have to
+/// be a multiple of the texel size.
+fn validate_view(
+    size: u64,
+    usage: gfx::buffer::Usage,
+    range: &Range<Option<u64>>,
+    alignment: u64,
+    max_elements: u64,
+    features: gfx::format::BufferFeature,
+    format: ImageFormat,
+) -> Result<(), BufferError> {
+    use gfx::buffer::Usage;
+    use gfx::format::BufferFeature;
+
+    let start = range.start.unwrap_or(0);
+    let end = range.end.unwrap_or(size);
+
+    if start >= end || end > size {
+        return Err(BufferError::ViewOutOfBounds);
+    }
+
+    let alignment = alignment.max(1);
+
+    if start % alignment != 0 {
+        return Err(BufferError::MisalignedViewOffset {
+            offset: start,
+            alignment,
+        });
+    }
+
+    let texel_size = u64::from(format.bytes_per_block());
+
+    if range.end.is_some() && (end - start) % texel_size != 0 {
+        return Err(BufferError::MisalignedViewRange {
+            range: end - start,
+            texel_size,
+        });
+    }
+
+    let elements = (end - start) / texel_size;
+
+    if elements > max_elements {
+        return Err(BufferError::TooManyViewElements {
+            elements,
+            max: max_elements,
+        });
+    }
+
+    let mut required = BufferFeature::empty();
+
+    if usage.contains(Usage::UNIFORM_TEXEL) {
+        required |= BufferFeature::UNIFORM_TEXEL;
+    }
+
+    if usage.contains(Usage::STORAGE_TEXEL) {
+        required |= BufferFeature::STORAGE_TEXEL;
+    }
+
+    if !features.contains(required) {
+        return Err(BufferError::UnsupportedViewFormat(format));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn size_modes() {
+        let tiles = BufferSizeMode::PerTile {
+            tile_size: 16,
+            bytes: 4,
+        };
+
+        assert_eq!(tiles.absolute((33, 16)).unwrap(), 3 * 4);
+
+        let pixels = BufferSizeMode::PerPixel { bytes: 4 };
+
+        assert_eq!(pixels.absolute((2, 3)).unwrap(), 24);
+
+        match pixels.absolute((0, 3)) {
+            Err(BufferError::ZeroSize) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        let huge = BufferSizeMode::PerPixel {
+            bytes: u64::max_value(),
+        };
+
+        match huge.absolute((2, 1)) {
+            Err(BufferError::SizeOverflow) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        let no_tiles = BufferSizeMode::PerTile {
+            tile_size: 0,
+            bytes: 4,
+        };
+
+        match no_tiles.absolute((16, 16)) {
+            Err(BufferError::InvalidTileSize) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+
+    #[test]
+    fn view_validation() {
+        use gfx::buffer::Usage;
+        use gfx::format::BufferFeature;
+
+        let format = ImageFormat::Rgba32Float;
+        let uniform = BufferFeature::UNIFORM_TEXEL;
+
+        let validate = |range: Range<Option<u64>>, usage, features| {
+            validate_view(256, usage, &range, 64, 16, features, format)
+        };
+
+        assert!(validate(None..None, Usage::UNIFORM_TEXEL, uniform).is_ok());
+        assert!(validate(Some(64)..Some(256), Usage::UNIFORM_TEXEL, uniform).is_ok());
+
+        for range in vec![Some(0)..Some(257), Some(128)..Some(128), Some(256)..None] {
+            match validate(range, Usage::UNIFORM_TEXEL, uniform) {
+                Err(BufferError::ViewOutOfBounds) => {}
+                res => panic!("unexpected result {:?}", res),
+            }
+        }
+
+        match validate(Some(16)..None, Usage::UNIFORM_TEXEL, uniform) {
+            Err(BufferError::MisalignedViewOffset {
+                offset: 16,
+                alignment: 64,
+            }) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        match validate(Some(64)..Some(200), Usage::UNIFORM_TEXEL, uniform) {
+            Err(BufferError::MisalignedViewRange {
+                range: 136,
+                texel_size: 16,
+            }) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        // views to the end of the buffer cover the whole texels which fit
+        let partial_texel = |range: Range<Option<u64>>| {
+            validate_view(200, Usage::UNIFORM_TEXEL, &range, 64, 16, uniform, format)
+        };
+
+        assert!(partial_texel(Some(64)..None).is_ok());
+
+        // 16 texels of 16 bytes each fit into the limit, but not 17
+        let large = |range: Range<Option<u64>>| {
+            validate_view(1024, Usage::UNIFORM_TEXEL, &range, 64, 16, uniform, format)
+        };
+
+        assert!(large(Some(0)..Some(256)).is_ok());
+        assert!(large(Some(768)..None).is_ok());
+
+        for range in vec![Some(0)..Some(272), Some(0)..None] {
+            match large(range) {
+                Err(BufferError::TooManyViewElements { max: 16, .. }) => {}
+                res => panic!("unexpected result {:?}", res),
+            }
+        }
+
+        // the format has to support every texel usage of the buffer
+        match validate(
+            None..None,
+            Usage::UNIFORM_TEXEL | Usage::STORAGE_TEXEL,
+            uniform,
+        ) {
+            Err(BufferError::UnsupportedViewFormat(ImageFormat::Rgba32Float)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+}
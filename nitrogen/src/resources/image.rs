@@ -17,6 +17,7 @@ use crate::util::storage::{Handle, Storage};
 use crate::util::transfer;
 
 use crate::device::DeviceContext;
+use crate::graph::{ReferenceName, ResourceName};
 use crate::resources::command_pool::CommandPoolTransfer;
 use crate::submit_group::{QueueSyncRefs, ResourceList};
 
@@ -111,7 +112,7 @@ impl ImageDimension {
 }
 
 /// Size mode used for image resources created in graphs.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub enum ImageSizeMode {
     /// The size of the image depends on the context reference size. See [`ExecutionContext`].
     ///
@@ -119,16 +120,46 @@ pub enum ImageSizeMode {
     #[allow(missing_docs)]
     ContextRelative { width: f32, height: f32 },
 
+    /// The size of the image depends on a named reference size of the context.
+    /// See [`ExecutionContext::reference_sizes`].
+    ///
+    /// [`ExecutionContext::reference_sizes`]: ../graph/struct.ExecutionContext.html#structfield.reference_sizes
+    #[allow(missing_docs)]
+    ReferenceRelative {
+        reference: ReferenceName,
+        width: f32,
+        height: f32,
+    },
+
+    /// The size of the image depends on the size of another image resource in the same graph.
+    #[allow(missing_docs)]
+    RelativeTo {
+        resource: ResourceName,
+        width: f32,
+        height: f32,
+    },
+
     /// The size of the image is specified directly.
     #[allow(missing_docs)]
     Absolute { width: u32, height: u32 },
 }
 
 impl ImageSizeMode {
+    /// Compute the absolute size of the image.
+    ///
+    /// `reference` is the size that relative modes scale. For [`ContextRelative`] this is the
+    /// context reference size, for [`ReferenceRelative`] the named reference size and for
+    /// [`RelativeTo`] the size of the referenced resource. It is ignored for [`Absolute`].
     ///
+    /// [`ContextRelative`]: #variant.ContextRelative
+    /// [`ReferenceRelative`]: #variant.ReferenceRelative
+    /// [`RelativeTo`]: #variant.RelativeTo
+    /// [`Absolute`]: #variant.Absolute
     pub fn absolute(&self, reference: (u32, u32)) -> (u32, u32) {
         match self {
-            ImageSizeMode::ContextRelative { width, height } => (
+            ImageSizeMode::ContextRelative { width, height }
+            | ImageSizeMode::ReferenceRelative { width, height, .. }
+            | ImageSizeMode::RelativeTo { width, height, .. } => (
                 (f64::from(*width) * f64::from(reference.0)) as u32,
                 (f64::from(*height) * f64::from(reference.1)) as u32,
             ),
@@ -148,6 +179,14 @@ impl Hash for ImageSizeMode {
                 state.write_u32(*width);
                 state.write_u32(*height);
             }
+            ImageSizeMode::ReferenceRelative { reference, .. } => {
+                state.write_i8(2);
+                reference.hash(state);
+            }
+            ImageSizeMode::RelativeTo { resource, .. } => {
+                state.write_i8(3);
+                resource.hash(state);
+            }
         }
     }
 }
@@ -247,19 +286,42 @@ pub struct ImageUploadInfo<'a> {
 /// Image formats
 #[repr(u8)]
 #[allow(missing_docs)]
-#[derive(Copy, Clone, Debug, PartialEq, Hash)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum ImageFormat {
     RUnorm,
     RgUnorm,
     RgbUnorm,
     RgbaUnorm,
+    RgbaUnormSrgb,
+    BgraUnorm,
+    BgraUnormSrgb,
 
+    R16Float,
+    Rg16Float,
+    Rgba16Float,
     Rgba32Float,
 
+    R11g11b10Float,
     E5b9g9r9Float,
 
+    R32Uint,
+
+    D16Unorm,
     D32Float,
+    D24UnormS8Uint,
     D32FloatS8Uint,
+
+    Bc1RgbaUnorm,
+    Bc1RgbaSrgb,
+    Bc2Unorm,
+    Bc2Srgb,
+    Bc3Unorm,
+    Bc3Srgb,
+    Bc4Unorm,
+    Bc5Unorm,
+    Bc6hUfloat,
+    Bc7Unorm,
+    Bc7Srgb,
 }
 
 impl Default for ImageFormat {
@@ -277,55 +339,147 @@ impl From<ImageFormat> for gfx::format::Format {
             ImageFormat::RgUnorm => Format::Rg8Unorm,
             ImageFormat::RgbUnorm => Format::Rgb8Unorm,
             ImageFormat::RgbaUnorm => Format::Rgba8Unorm,
+            ImageFormat::RgbaUnormSrgb => Format::Rgba8Srgb,
+            ImageFormat::BgraUnorm => Format::Bgra8Unorm,
+            ImageFormat::BgraUnormSrgb => Format::Bgra8Srgb,
 
+            ImageFormat::R16Float => Format::R16Sfloat,
+            ImageFormat::Rg16Float => Format::Rg16Sfloat,
+            ImageFormat::Rgba16Float => Format::Rgba16Sfloat,
             ImageFormat::Rgba32Float => Format::Rgba32Sfloat,
 
+            ImageFormat::R11g11b10Float => Format::B10g11r11Ufloat,
             ImageFormat::E5b9g9r9Float => Format::E5b9g9r9Ufloat,
 
+            ImageFormat::R32Uint => Format::R32Uint,
+
+            ImageFormat::D16Unorm => Format::D16Unorm,
             ImageFormat::D32Float => Format::D32Sfloat,
+            ImageFormat::D24UnormS8Uint => Format::D24UnormS8Uint,
             ImageFormat::D32FloatS8Uint => Format::D32SfloatS8Uint,
+
+            ImageFormat::Bc1RgbaUnorm => Format::Bc1RgbaUnorm,
+            ImageFormat::Bc1RgbaSrgb => Format::Bc1RgbaSrgb,
+            ImageFormat::Bc2Unorm => Format::Bc2Unorm,
+            ImageFormat::Bc2Srgb => Format::Bc2Srgb,
+            ImageFormat::Bc3Unorm => Format::Bc3Unorm,
+            ImageFormat::Bc3Srgb => Format::Bc3Srgb,
+            ImageFormat::Bc4Unorm => Format::Bc4Unorm,
+            ImageFormat::Bc5Unorm => Format::Bc5Unorm,
+            ImageFormat::Bc6hUfloat => Format::Bc6hUfloat,
+            ImageFormat::Bc7Unorm => Format::Bc7Unorm,
+            ImageFormat::Bc7Srgb => Format::Bc7Srgb,
         }
     }
 }
 
-impl Into<ImageFormat> for gfx::format::Format {
-    fn into(self) -> ImageFormat {
+/// Error returned when a `gfx` format has no corresponding [`ImageFormat`].
+///
+/// [`ImageFormat`]: enum.ImageFormat.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+#[display(fmt = "The format {:?} is not supported", _0)]
+pub struct UnsupportedFormat(pub gfx::format::Format);
+
+impl std::error::Error for UnsupportedFormat {}
+
+impl std::convert::TryFrom<gfx::format::Format> for ImageFormat {
+    type Error = UnsupportedFormat;
+
+    fn try_from(format: gfx::format::Format) -> Result<Self, Self::Error> {
         use gfx::format::Format;
 
-        match self {
+        let format = match format {
             Format::R8Unorm => ImageFormat::RUnorm,
             Format::Rg8Unorm => ImageFormat::RgUnorm,
             Format::Rgb8Unorm => ImageFormat::RgbUnorm,
             Format::Rgba8Unorm => ImageFormat::RgbaUnorm,
+            Format::Rgba8Srgb => ImageFormat::RgbaUnormSrgb,
+            Format::Bgra8Unorm => ImageFormat::BgraUnorm,
+            Format::Bgra8Srgb => ImageFormat::BgraUnormSrgb,
 
+            Format::R16Sfloat => ImageFormat::R16Float,
+            Format::Rg16Sfloat => ImageFormat::Rg16Float,
+            Format::Rgba16Sfloat => ImageFormat::Rgba16Float,
             Format::Rgba32Sfloat => ImageFormat::Rgba32Float,
 
+            Format::B10g11r11Ufloat => ImageFormat::R11g11b10Float,
             Format::E5b9g9r9Ufloat => ImageFormat::E5b9g9r9Float,
 
+            Format::R32Uint => ImageFormat::R32Uint,
+
+            Format::D16Unorm => ImageFormat::D16Unorm,
             Format::D32Sfloat => ImageFormat::D32Float,
+            Format::D24UnormS8Uint => ImageFormat::D24UnormS8Uint,
             Format::D32SfloatS8Uint => ImageFormat::D32FloatS8Uint,
 
-            _ => unimplemented!(),
-        }
+            Format::Bc1RgbaUnorm => ImageFormat::Bc1RgbaUnorm,
+            Format::Bc1RgbaSrgb => ImageFormat::Bc1RgbaSrgb,
+            Format::Bc2Unorm => ImageFormat::Bc2Unorm,
+            Format::Bc2Srgb => ImageFormat::Bc2Srgb,
+            Format::Bc3Unorm => ImageFormat::Bc3Unorm,
+            Format::Bc3Srgb => ImageFormat::Bc3Srgb,
+            Format::Bc4Unorm => ImageFormat::Bc4Unorm,
+            Format::Bc5Unorm => ImageFormat::Bc5Unorm,
+            Format::Bc6hUfloat => ImageFormat::Bc6hUfloat,
+            Format::Bc7Unorm => ImageFormat::Bc7Unorm,
+            Format::Bc7Srgb => ImageFormat::Bc7Srgb,
+
+            format => return Err(UnsupportedFormat(format)),
+        };
+
+        Ok(format)
     }
 }
 
 impl ImageFormat {
+    /// Number of bytes used to store one block of texels.
+    ///
+    /// For uncompressed formats a block consists of a single texel, so this is the number of
+    /// bytes per texel.
+    pub fn bytes_per_block(self) -> u32 {
+        let format: gfx::format::Format = self.into();
+        u32::from(format.surface_desc().bits) / 8
+    }
+
+    /// Width and height (in texels) of one block.
+    ///
+    /// Uncompressed formats have a block size of `(1, 1)`, block compressed formats `(4, 4)`.
+    pub fn block_size(self) -> (u32, u32) {
+        let format: gfx::format::Format = self.into();
+        let (width, height) = format.surface_desc().dim;
+        (u32::from(width), u32::from(height))
+    }
+
+    /// Determine if the given format is block compressed.
+    pub fn is_compressed(self) -> bool {
+        self.block_size() != (1, 1)
+    }
+
+    /// The image aspects (color, depth, stencil) contained in the format.
+    pub fn aspects(self) -> gfx::format::Aspects {
+        let format: gfx::format::Format = self.into();
+        format.surface_desc().aspects
+    }
+
+    /// Determine if the given format stores color values in the sRGB color space.
+    pub fn is_srgb(self) -> bool {
+        let format: gfx::format::Format = self.into();
+        format.base_format().1 == gfx::format::ChannelType::Srgb
+    }
+
+    /// Determine if the given format contains a color component
+    pub fn is_color(self) -> bool {
+        self.aspects().contains(gfx::format::Aspects::COLOR)
+    }
+
     /// Determine if the given format contains a depth component
     pub fn is_depth(self) -> bool {
-        match self {
-            ImageFormat::D32FloatS8Uint => true,
-            ImageFormat::D32Float => true,
-            _ => false,
-        }
+        self.aspects().contains(gfx::format::Aspects::DEPTH)
     }
 
     /// Determine if the given format contains a stencil component
     pub fn is_stencil(self) -> bool {
-        match self {
-            ImageFormat::D32FloatS8Uint => true,
-            _ => false,
-        }
+        self.aspects().contains(gfx::format::Aspects::STENCIL)
     }
 
     /// Determine if the given format contains both a depth and a stencil component
@@ -334,6 +488,50 @@ impl ImageFormat {
     }
 }
 
+/// Query the ways an image with the given format can be used on the device.
+///
+/// The returned flags describe images using optimal tiling, which is used for all images
+/// created by nitrogen.
+pub(crate) fn format_support(device: &DeviceContext, format: ImageFormat) -> ImageUsage {
+    use gfx::adapter::PhysicalDevice;
+    use gfx::format::ImageFeature;
+
+    let properties = device
+        .adapter
+        .physical_device
+        .format_properties(Some(format.into()));
+
+    let features = properties.optimal_tiling;
+
+    let supported = !features.is_empty();
+    let color_attachment = features.contains(ImageFeature::COLOR_ATTACHMENT);
+    let depth_stencil_attachment = features.contains(ImageFeature::DEPTH_STENCIL_ATTACHMENT);
+
+    ImageUsage {
+        transfer_src: supported,
+        transfer_dst: supported,
+        sampling: features.contains(ImageFeature::SAMPLED),
+        color_attachment,
+        depth_stencil_attachment,
+        storage_image: features.contains(ImageFeature::STORAGE),
+        input_attachment: color_attachment || depth_stencil_attachment,
+    }
+}
+
+/// Query the ways a texel buffer with the given format can be used on the device.
+pub(crate) fn buffer_format_support(
+    device: &DeviceContext,
+    format: ImageFormat,
+) -> gfx::format::BufferFeature {
+    use gfx::adapter::PhysicalDevice;
+
+    device
+        .adapter
+        .physical_device
+        .format_properties(Some(format.into()))
+        .buffer_features
+}
+
 /// Kind of image
 ///
 /// Different kinds of images may contains the same "physical" data, but sampling might be
@@ -780,3 +978,90 @@ fn image_copy_buffer_size(
 
     (buffer_size, row_pitch, texel_size)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gfx::format::{Aspects, Format};
+    use std::convert::TryFrom;
+
+    const COLOR: Aspects = Aspects::COLOR;
+    const DEPTH: Aspects = Aspects::DEPTH;
+
+    fn depth_stencil() -> Aspects {
+        Aspects::DEPTH | Aspects::STENCIL
+    }
+
+    // (format, bytes per block, block size, aspects)
+    fn formats() -> Vec<(ImageFormat, u32, (u32, u32), Aspects)> {
+        vec![
+            (ImageFormat::RUnorm, 1, (1, 1), COLOR),
+            (ImageFormat::RgUnorm, 2, (1, 1), COLOR),
+            (ImageFormat::RgbUnorm, 3, (1, 1), COLOR),
+            (ImageFormat::RgbaUnorm, 4, (1, 1), COLOR),
+            (ImageFormat::RgbaUnormSrgb, 4, (1, 1), COLOR),
+            (ImageFormat::BgraUnorm, 4, (1, 1), COLOR),
+            (ImageFormat::BgraUnormSrgb, 4, (1, 1), COLOR),
+            (ImageFormat::R16Float, 2, (1, 1), COLOR),
+            (ImageFormat::Rg16Float, 4, (1, 1), COLOR),
+            (ImageFormat::Rgba16Float, 8, (1, 1), COLOR),
+            (ImageFormat::Rgba32Float, 16, (1, 1), COLOR),
+            (ImageFormat::R11g11b10Float, 4, (1, 1), COLOR),
+            (ImageFormat::E5b9g9r9Float, 4, (1, 1), COLOR),
+            (ImageFormat::R32Uint, 4, (1, 1), COLOR),
+            (ImageFormat::D16Unorm, 2, (1, 1), DEPTH),
+            (ImageFormat::D32Float, 4, (1, 1), DEPTH),
+            (ImageFormat::D24UnormS8Uint, 4, (1, 1), depth_stencil()),
+            (ImageFormat::Bc1RgbaUnorm, 8, (4, 4), COLOR),
+            (ImageFormat::Bc1RgbaSrgb, 8, (4, 4), COLOR),
+            (ImageFormat::Bc2Unorm, 16, (4, 4), COLOR),
+            (ImageFormat::Bc2Srgb, 16, (4, 4), COLOR),
+            (ImageFormat::Bc3Unorm, 16, (4, 4), COLOR),
+            (ImageFormat::Bc3Srgb, 16, (4, 4), COLOR),
+            (ImageFormat::Bc4Unorm, 8, (4, 4), COLOR),
+            (ImageFormat::Bc5Unorm, 16, (4, 4), COLOR),
+            (ImageFormat::Bc6hUfloat, 16, (4, 4), COLOR),
+            (ImageFormat::Bc7Unorm, 16, (4, 4), COLOR),
+            (ImageFormat::Bc7Srgb, 16, (4, 4), COLOR),
+        ]
+    }
+
+    #[test]
+    fn format_properties() {
+        for (format, bytes, block, aspects) in formats() {
+            assert_eq!(format.bytes_per_block(), bytes, "{:?}", format);
+            assert_eq!(format.block_size(), block, "{:?}", format);
+            assert_eq!(format.is_compressed(), block != (1, 1), "{:?}", format);
+            assert_eq!(format.aspects(), aspects, "{:?}", format);
+        }
+
+        // the size of the packed stencil component is up to the implementation, only check the
+        // aspects here.
+        let format = ImageFormat::D32FloatS8Uint;
+        assert_eq!(format.aspects(), depth_stencil());
+        assert!(format.is_depth_stencil());
+        assert!(!format.is_color());
+    }
+
+    #[test]
+    fn format_conversion() {
+        let all = formats()
+            .into_iter()
+            .map(|(format, ..)| format)
+            .chain(std::iter::once(ImageFormat::D32FloatS8Uint));
+
+        for format in all {
+            let raw = Format::from(format);
+            assert_eq!(ImageFormat::try_from(raw), Ok(format));
+        }
+
+        assert_eq!(
+            ImageFormat::try_from(Format::R8Snorm),
+            Err(UnsupportedFormat(Format::R8Snorm))
+        );
+        assert_eq!(
+            ImageFormat::try_from(Format::Bc1RgbUnorm),
+            Err(UnsupportedFormat(Format::Bc1RgbUnorm))
+        );
+    }
+}
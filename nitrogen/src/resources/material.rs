@@ -8,9 +8,10 @@ use crate::util::storage::{Handle, Storage};
 
 use crate::device::DeviceContext;
 
-use crate::resources::buffer::{BufferHandle, BufferStorage};
+use crate::resources::buffer::{BufferHandle, BufferStorage, BufferViewHandle};
 use crate::resources::image::{ImageHandle, ImageStorage};
 use crate::resources::sampler::{SamplerHandle, SamplerStorage};
+use crate::resources::shader::ShaderStages;
 
 use crate::types;
 
@@ -69,20 +70,70 @@ impl MaterialInstanceHandle {
 pub struct Material {
     sets_per_pool: u8,
 
-    parameters: Vec<(u32, MaterialParameterType)>,
+    parameters: Vec<MaterialParameter>,
     pub(crate) desc_set_layout: types::DescriptorSetLayout,
     pool_allocated: Vec<u8>,
     pool_used: Vec<u8>,
     pools: Vec<types::DescriptorPool>,
 
+    /// Immutable samplers of the parameters, one for each element.
+    immutable_samplers: Vec<(u32, SmallVec<[SamplerHandle; 4]>)>,
+
     instances: Storage<MaterialInstance>,
 }
 
 /// Information needed to create a material object.
 pub struct MaterialCreateInfo<'a> {
-    // TODO add support for arrays later on?
     /// Paramters of the material.
+    ///
+    /// Every binding can only be used once, here as well as in the lists below.
     pub parameters: &'a [(u32, MaterialParameterType)],
+
+    /// Parameters which hold an array of descriptors, by binding.
+    ///
+    /// Parameters not listed here hold a single descriptor.
+    pub arrays: &'a [(u32, ParameterArray)],
+
+    /// Shader stages which can access a parameter, by binding.
+    ///
+    /// Parameters not listed here are visible to all stages. Restricting the visibility can
+    /// save descriptor resources on some drivers.
+    pub visibility: &'a [(u32, ShaderStages)],
+
+    /// Samplers which are baked into the material, by binding.
+    ///
+    /// Only `Sampler` and `CombinedImageSampler` parameters can have immutable samplers, one
+    /// for every element of the parameter. The samplers are kept alive until the material is
+    /// destroyed.
+    pub immutable_samplers: &'a [(u32, &'a [SamplerHandle])],
+}
+
+/// Description of a parameter holding an array of descriptors.
+///
+/// Arrays can be used for texture atlases or "bindless" material tables, where shaders index
+/// into a large number of resources in a single binding. Unless the array is partially bound, all
+/// elements have to be written before an instance of the material is used.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct ParameterArray {
+    /// Number of descriptors in the array.
+    pub count: u32,
+
+    /// Allow elements of the array to stay unwritten as long as shaders don't access them.
+    ///
+    /// Requires support for descriptor indexing by the device, creating the material fails
+    /// otherwise.
+    pub partially_bound: bool,
+}
+
+/// A parameter of a material.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct MaterialParameter {
+    pub(crate) binding: u32,
+    pub(crate) ty: MaterialParameterType,
+    /// Number of descriptors in the binding.
+    pub(crate) count: u32,
+    pub(crate) stages: ShaderStages,
+    pub(crate) immutable_samplers: bool,
 }
 
 /// An instance of a material.
@@ -100,9 +151,10 @@ pub(crate) struct MaterialStorage {
 
 /// Type of material parameter.
 #[allow(missing_docs)]
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum MaterialParameterType {
     Sampler,
+    CombinedImageSampler,
     SampledImage,
     StorageImage,
     UniformTexelBuffer,
@@ -119,6 +171,7 @@ impl From<MaterialParameterType> for gfx::pso::DescriptorType {
         use gfx::pso::DescriptorType;
         match ty {
             MaterialParameterType::Sampler => DescriptorType::Sampler,
+            MaterialParameterType::CombinedImageSampler => DescriptorType::CombinedImageSampler,
             MaterialParameterType::SampledImage => DescriptorType::SampledImage,
             MaterialParameterType::StorageImage => DescriptorType::StorageImage,
             MaterialParameterType::UniformTexelBuffer => DescriptorType::UniformTexelBuffer,
@@ -132,11 +185,14 @@ impl From<MaterialParameterType> for gfx::pso::DescriptorType {
 }
 
 /// Description of a data-write to a material-instance.
-pub struct InstanceWrite {
+pub struct InstanceWrite<'a> {
     /// Binding point of the data.
     pub binding: u32,
-    /// Data to write.
-    pub data: InstanceWriteData,
+    /// Element of the array the first descriptor is written to, `0` for parameters which are
+    /// not arrays.
+    pub array_offset: u32,
+    /// Data to write, one entry for each consecutive element starting at `array_offset`.
+    pub data: &'a [InstanceWriteData],
 }
 
 /// Data to write to an instance.
@@ -151,6 +207,15 @@ pub enum InstanceWriteData {
         /// Handle to image object.
         image: ImageHandle,
     },
+    /// An image object together with a sampler.
+    CombinedImageSampler {
+        /// Handle to image object.
+        image: ImageHandle,
+        /// Handle to sampler object.
+        ///
+        /// Has to be `None` if the parameter has immutable samplers, which can't be replaced.
+        sampler: Option<SamplerHandle>,
+    },
     /// A buffer object.
     Buffer {
         /// Handle to buffer object.
@@ -158,15 +223,16 @@ pub enum InstanceWriteData {
         /// Region of the buffer to map (in bytes).
         region: Range<Option<u64>>,
     },
-    // TODO buffer views for texel buffers?
-    /*
+    /// A buffer view used as a uniform-texel buffer.
     UniformTexelBuffer {
-        buffer: BufferHandle,
+        /// Handle to the buffer view.
+        view: BufferViewHandle,
     },
+    /// A buffer view used as a storage-texel buffer.
     StorageTexelBuffer {
-        buffer: BufferHandle,
-    }
-    */
+        /// Handle to the buffer view.
+        view: BufferViewHandle,
+    },
 }
 
 impl MaterialStorage {
@@ -179,22 +245,44 @@ impl MaterialStorage {
     pub(crate) unsafe fn create(
         &mut self,
         device: &DeviceContext,
+        sampler_storage: &mut SamplerStorage,
         create_info: MaterialCreateInfo,
     ) -> Result<MaterialHandle, MaterialError> {
         use gfx::Device;
 
-        let descriptors = create_info
-            .parameters
+        let mut parameters = material_parameters(&create_info, partially_bound_supported(device))?;
+
+        // immutable samplers are consumed by the layout in the order of the bindings
+        let mut samplers = SmallVec::<[_; 16]>::new();
+        let mut immutable_samplers = Vec::new();
+
+        for param in parameters.iter().filter(|param| param.immutable_samplers) {
+            let (_, handles) = create_info
+                .immutable_samplers
+                .iter()
+                .find(|(b, _)| *b == param.binding)
+                .unwrap();
+
+            immutable_samplers.push((param.binding, handles.iter().cloned().collect()));
+
+            for handle in handles.iter() {
+                let raw = sampler_storage
+                    .raw(*handle)
+                    .ok_or(MaterialError::InvalidResourceHandle(param.binding))?;
+
+                samplers.push(&raw.0);
+            }
+        }
+
+        let descriptors = parameters
             .iter()
-            .map(
-                |(binding, desc_type)| gfx::pso::DescriptorSetLayoutBinding {
-                    binding: *binding,
-                    ty: desc_type.clone().into(),
-                    count: 1,
-                    stage_flags: gfx::pso::ShaderStageFlags::ALL,
-                    immutable_samplers: false,
-                },
-            )
+            .map(|param| gfx::pso::DescriptorSetLayoutBinding {
+                binding: param.binding,
+                ty: param.ty.into(),
+                count: param.count as usize,
+                stage_flags: param.stages.into(),
+                immutable_samplers: param.immutable_samplers,
+            })
             .collect::<SmallVec<[_; 16]>>();
 
         if descriptors.is_empty() {
@@ -203,15 +291,21 @@ impl MaterialStorage {
 
         let set = device
             .device
-            .create_descriptor_set_layout(descriptors.as_slice(), &[])?;
+            .create_descriptor_set_layout(descriptors.as_slice(), samplers)?;
+
+        for (_, handles) in &immutable_samplers {
+            for handle in handles {
+                sampler_storage.add_material_use(*handle);
+            }
+        }
 
-        let mut parameters = create_info.parameters.to_vec();
-        parameters.sort_by_key(|(binding, _)| *binding);
+        parameters.sort_by_key(|param| param.binding);
 
         let mat = Material {
             sets_per_pool: MAX_SETS_PER_POOL,
             parameters,
             desc_set_layout: set,
+            immutable_samplers,
             instances: Storage::new(),
             pool_allocated: Vec::new(),
             pool_used: Vec::new(),
@@ -229,16 +323,14 @@ impl MaterialStorage {
         sets_per_pool: u8,
     ) -> Option<MaterialHandle>
     where
-        P: Iterator<Item = gfx::pso::DescriptorRangeDesc>,
+        P: Iterator<Item = (u32, gfx::pso::DescriptorType)>,
     {
-        let mat = Material {
+        let mut mat = Material {
             parameters: params
-                .map(|desc| {
+                .map(|(binding, desc_ty)| {
                     use gfx::pso::DescriptorType;
-                    // Since the binding is only used when creating a material
-                    // from a user-facing description, we can just put anything in here
-                    // since it will never be used afterwards.
-                    let ty = match desc.ty {
+
+                    let ty = match desc_ty {
                         DescriptorType::Sampler => MaterialParameterType::Sampler,
                         DescriptorType::SampledImage => MaterialParameterType::SampledImage,
                         DescriptorType::StorageImage => MaterialParameterType::StorageImage,
@@ -259,17 +351,26 @@ impl MaterialStorage {
                         _ => unreachable!(),
                     };
 
-                    (0, ty)
+                    MaterialParameter {
+                        binding,
+                        ty,
+                        count: 1,
+                        stages: ShaderStages::ALL,
+                        immutable_samplers: false,
+                    }
                 })
                 .collect(),
             sets_per_pool,
             desc_set_layout: layout,
+            immutable_samplers: vec![],
             instances: Storage::new(),
             pool_allocated: vec![],
             pool_used: vec![],
             pools: vec![],
         };
 
+        mat.parameters.sort_by_key(|param| param.binding);
+
         if mat.parameters.is_empty() {
             use gfx::Device;
 
@@ -282,9 +383,18 @@ impl MaterialStorage {
         Some(self.storage.insert(mat))
     }
 
-    pub(crate) unsafe fn destroy(&mut self, device: &DeviceContext, materials: &[MaterialHandle]) {
+    pub(crate) unsafe fn destroy(
+        &mut self,
+        device: &DeviceContext,
+        sampler_storage: &mut SamplerStorage,
+        materials: &[MaterialHandle],
+    ) {
         for handle in materials {
             if let Some(mat) = self.storage.remove(*handle) {
+                for (_, samplers) in &mat.immutable_samplers {
+                    sampler_storage.release_material_uses(device, samplers);
+                }
+
                 mat.release(device);
             }
         }
@@ -309,7 +419,7 @@ impl MaterialStorage {
         Ok(MaterialInstanceHandle { material, instance })
     }
 
-    pub(crate) unsafe fn write_instance<I>(
+    pub(crate) unsafe fn write_instance<'w, I>(
         &self,
         device: &DeviceContext,
         sampler_storage: &SamplerStorage,
@@ -317,46 +427,61 @@ impl MaterialStorage {
         buffer_storage: &BufferStorage,
         instance: MaterialInstanceHandle,
         data: I,
-    ) -> Option<()>
+    ) -> Result<(), MaterialError>
     where
         I: IntoIterator,
-        I::Item: ::std::borrow::Borrow<InstanceWrite>,
+        I::Item: ::std::borrow::Borrow<InstanceWrite<'w>>,
     {
         use gfx::Device;
 
-        let mat = self.storage.get(instance.material)?;
-
-        let instance = mat.instances.get(instance.instance)?;
+        let mat = self
+            .storage
+            .get(instance.material)
+            .ok_or(MaterialError::InvalidHandle)?;
 
-        // TODO verify that types match?
-        let writes = data.into_iter().filter_map(|write| {
-            use std::borrow::Borrow;
+        let instance = mat
+            .instances
+            .get(instance.instance)
+            .ok_or(MaterialError::InvalidHandle)?;
 
-            let write = write.borrow();
-            Some(gfx::pso::DescriptorSetWrite {
-                set: &instance.set,
-                binding: write.binding,
-                array_offset: 0,
-                descriptors: Some(match write.data {
-                    InstanceWriteData::Sampler { sampler } => {
-                        let raw = sampler_storage.raw(sampler)?;
-                        gfx::pso::Descriptor::Sampler(&raw.0)
-                    }
-                    InstanceWriteData::Image { image } => {
-                        let raw = image_storage.raw(image)?;
-                        gfx::pso::Descriptor::Image(&raw.view, gfx::image::Layout::Undefined)
-                    }
-                    InstanceWriteData::Buffer { buffer, ref region } => {
-                        let raw = buffer_storage.raw(buffer)?;
-                        gfx::pso::Descriptor::Buffer(raw.buffer.raw(), region.clone())
-                    }
-                }),
+        // validate all writes before any descriptor is touched
+        let writes = data
+            .into_iter()
+            .map(|write| {
+                use std::borrow::Borrow;
+
+                let write = write.borrow();
+
+                let param = write_parameter(&mat.parameters, write)?;
+
+                let descriptors = write
+                    .data
+                    .iter()
+                    .zip(write.array_offset..)
+                    .map(|(data, element)| {
+                        instance_descriptor(
+                            sampler_storage,
+                            image_storage,
+                            buffer_storage,
+                            param,
+                            mat.immutable_sampler(param.binding, element),
+                            data,
+                        )
+                    })
+                    .collect::<Result<SmallVec<[_; 4]>, _>>()?;
+
+                Ok(gfx::pso::DescriptorSetWrite {
+                    set: &instance.set,
+                    binding: write.binding,
+                    array_offset: write.array_offset as usize,
+                    descriptors,
+                })
             })
-        });
+            .collect::<Result<SmallVec<[_; 16]>, _>>()?;
 
         device.device.write_descriptor_sets(writes);
 
-        Some(())
+        Ok(())
     }
 
     pub(crate) unsafe fn destroy_instances(&mut self, instances: &[MaterialInstanceHandle]) {
@@ -377,7 +502,313 @@ impl MaterialStorage {
     }
 }
 
+/// Check if partially bound arrays can be used with the device.
+///
+/// This requires the descriptor indexing feature, which gfx-hal does not expose yet, so no
+/// device supports it for now.
+fn partially_bound_supported(_device: &DeviceContext) -> bool {
+    false
+}
+
+/// Validate the arrays, visibilities and immutable samplers of `create_info` and collect the
+/// parameters of the material.
+fn material_parameters(
+    create_info: &MaterialCreateInfo,
+    partially_bound_supported: bool,
+) -> Result<Vec<MaterialParameter>, MaterialError> {
+    let duplicate = duplicate_binding(create_info.parameters.iter().map(|(b, _)| *b))
+        .or_else(|| duplicate_binding(create_info.arrays.iter().map(|(b, _)| *b)))
+        .or_else(|| duplicate_binding(create_info.visibility.iter().map(|(b, _)| *b)))
+        .or_else(|| duplicate_binding(create_info.immutable_samplers.iter().map(|(b, _)| *b)));
+
+    if let Some(binding) = duplicate {
+        return Err(MaterialError::DuplicateBinding(binding));
+    }
+
+    for (binding, array) in create_info.arrays {
+        if !create_info.parameters.iter().any(|(b, _)| b == binding) {
+            return Err(MaterialError::InvalidArrayBinding(*binding));
+        }
+
+        if array.count == 0 {
+            return Err(MaterialError::EmptyArray(*binding));
+        }
+
+        if array.partially_bound && !partially_bound_supported {
+            return Err(MaterialError::PartiallyBoundUnsupported(*binding));
+        }
+    }
+
+    for (binding, _) in create_info.visibility {
+        if !create_info.parameters.iter().any(|(b, _)| b == binding) {
+            return Err(MaterialError::InvalidVisibilityBinding(*binding));
+        }
+    }
+
+    for (binding, _) in create_info.immutable_samplers {
+        if !create_info.parameters.iter().any(|(b, _)| b == binding) {
+            return Err(MaterialError::InvalidImmutableSamplers(*binding));
+        }
+    }
+
+    create_info
+        .parameters
+        .iter()
+        .map(|(binding, ty)| {
+            let count = create_info
+                .arrays
+                .iter()
+                .find(|(b, _)| b == binding)
+                .map(|(_, array)| array.count)
+                .unwrap_or(1);
+
+            let stages = create_info
+                .visibility
+                .iter()
+                .find(|(b, _)| b == binding)
+                .map(|(_, stages)| *stages)
+                .unwrap_or(ShaderStages::ALL);
+
+            let samplers = create_info
+                .immutable_samplers
+                .iter()
+                .find(|(b, _)| b == binding)
+                .map(|(_, samplers)| samplers);
+
+            if let Some(samplers) = samplers {
+                let accepts_samplers = match ty {
+                    MaterialParameterType::Sampler => true,
+                    MaterialParameterType::CombinedImageSampler => true,
+                    _ => false,
+                };
+
+                if !accepts_samplers || samplers.len() != count as usize {
+                    return Err(MaterialError::InvalidImmutableSamplers(*binding));
+                }
+            }
+
+            Ok(MaterialParameter {
+                binding: *binding,
+                ty: *ty,
+                count,
+                stages,
+                immutable_samplers: samplers.is_some(),
+            })
+        })
+        .collect()
+}
+
+/// Find a binding which is listed more than once.
+fn duplicate_binding<I>(bindings: I) -> Option<u32>
+where
+    I: IntoIterator<Item = u32>,
+{
+    let mut seen = SmallVec::<[u32; 16]>::new();
+
+    for binding in bindings {
+        if seen.contains(&binding) {
+            return Some(binding);
+        }
+
+        seen.push(binding);
+    }
+
+    None
+}
+
+/// Find the parameter written by `write`, checking that the written elements are part of it.
+fn write_parameter<'p>(
+    parameters: &'p [MaterialParameter],
+    write: &InstanceWrite,
+) -> Result<&'p MaterialParameter, MaterialError> {
+    let param = parameters
+        .iter()
+        .find(|p| p.binding == write.binding)
+        .ok_or(MaterialError::InvalidBinding(write.binding))?;
+
+    let end = u64::from(write.array_offset) + write.data.len() as u64;
+
+    if write.data.is_empty() || end > u64::from(param.count) {
+        return Err(MaterialError::InvalidArrayRange {
+            binding: write.binding,
+            offset: write.array_offset,
+            count: write.data.len(),
+        });
+    }
+
+    Ok(param)
+}
+
+/// Create the descriptor for `data`, checking that it can be bound to `param`.
+///
+/// `immutable_sampler` is the sampler baked into the written element, if any.
+fn instance_descriptor<'a>(
+    sampler_storage: &'a SamplerStorage,
+    image_storage: &'a ImageStorage,
+    buffer_storage: &'a BufferStorage,
+    param: &MaterialParameter,
+    immutable_sampler: Option<SamplerHandle>,
+    data: &InstanceWriteData,
+) -> Result<gfx::pso::Descriptor<'a, back::Backend>, MaterialError> {
+    use self::MaterialParameterType as P;
+    use gfx::buffer::Usage as BUsage;
+    use gfx::image::Usage as IUsage;
+
+    let binding = param.binding;
+
+    let mismatch = MaterialError::TypeMismatch {
+        binding,
+        expected: param.ty,
+    };
+
+    match data {
+        InstanceWriteData::Sampler { sampler } => {
+            if param.ty != P::Sampler {
+                return Err(mismatch);
+            }
+
+            if immutable_sampler.is_some() {
+                return Err(MaterialError::ImmutableSamplerWrite(binding));
+            }
+
+            let raw = sampler_storage
+                .raw(*sampler)
+                .ok_or(MaterialError::InvalidResourceHandle(binding))?;
+
+            Ok(gfx::pso::Descriptor::Sampler(&raw.0))
+        }
+        InstanceWriteData::Image { image } => {
+            let usage = match param.ty {
+                P::SampledImage => IUsage::SAMPLED,
+                P::StorageImage => IUsage::STORAGE,
+                _ => return Err(mismatch),
+            };
+
+            let raw = image_storage
+                .raw(*image)
+                .ok_or(MaterialError::InvalidResourceHandle(binding))?;
+
+            check_usage(binding, raw.usage, usage)?;
+
+            Ok(gfx::pso::Descriptor::Image(
+                &raw.view,
+                gfx::image::Layout::Undefined,
+            ))
+        }
+        InstanceWriteData::CombinedImageSampler { image, sampler } => {
+            if param.ty != P::CombinedImageSampler {
+                return Err(mismatch);
+            }
+
+            // drivers ignore the sampler of elements with immutable samplers, but one has to
+            // be provided anyway. Immutable samplers are kept alive even if they were destroyed.
+            let sampler = match (*sampler, immutable_sampler) {
+                (Some(_), Some(_)) => return Err(MaterialError::ImmutableSamplerWrite(binding)),
+                (None, None) => return Err(MaterialError::MissingSampler(binding)),
+                (Some(sampler), None) => sampler_storage.raw(sampler),
+                (None, Some(sampler)) => sampler_storage.storage.get(sampler),
+            };
+
+            let sampler = sampler.ok_or(MaterialError::InvalidResourceHandle(binding))?;
+
+            let image = image_storage
+                .raw(*image)
+                .ok_or(MaterialError::InvalidResourceHandle(binding))?;
+
+            check_usage(binding, image.usage, IUsage::SAMPLED)?;
+
+            Ok(gfx::pso::Descriptor::CombinedImageSampler(
+                &image.view,
+                gfx::image::Layout::Undefined,
+                &sampler.0,
+            ))
+        }
+        InstanceWriteData::Buffer { buffer, region } => {
+            let usage = match param.ty {
+                P::UniformBuffer | P::UniformBufferDynamic => BUsage::UNIFORM,
+                P::StorageBuffer | P::StorageBufferDynamic => BUsage::STORAGE,
+                _ => return Err(mismatch),
+            };
+
+            let raw = buffer_storage
+                .raw(*buffer)
+                .ok_or(MaterialError::InvalidResourceHandle(binding))?;
+
+            check_usage(binding, raw.usage, usage)?;
+
+            Ok(gfx::pso::Descriptor::Buffer(
+                raw.buffer.raw(),
+                region.clone(),
+            ))
+        }
+        InstanceWriteData::UniformTexelBuffer { view } => {
+            if param.ty != P::UniformTexelBuffer {
+                return Err(mismatch);
+            }
+
+            let raw = texel_view(buffer_storage, binding, *view, BUsage::UNIFORM_TEXEL)?;
+
+            Ok(gfx::pso::Descriptor::UniformTexelBuffer(raw))
+        }
+        InstanceWriteData::StorageTexelBuffer { view } => {
+            if param.ty != P::StorageTexelBuffer {
+                return Err(mismatch);
+            }
+
+            let raw = texel_view(buffer_storage, binding, *view, BUsage::STORAGE_TEXEL)?;
+
+            Ok(gfx::pso::Descriptor::StorageTexelBuffer(raw))
+        }
+    }
+}
+
+/// Look up a buffer view whose buffer was created with `usage`.
+fn texel_view(
+    buffer_storage: &BufferStorage,
+    binding: u32,
+    view: BufferViewHandle,
+    usage: gfx::buffer::Usage,
+) -> Result<&types::BufferView, MaterialError> {
+    let raw = buffer_storage
+        .view_raw(view)
+        .ok_or(MaterialError::InvalidResourceHandle(binding))?;
+
+    let buffer = buffer_storage
+        .raw(raw.buffer)
+        .ok_or(MaterialError::InvalidResourceHandle(binding))?;
+
+    check_usage(binding, buffer.usage, usage)?;
+
+    Ok(&raw.view)
+}
+
+/// Check that a resource written to `binding` was created with all `required` usage flags.
+fn check_usage<U>(binding: u32, usage: U, required: U) -> Result<(), MaterialError>
+where
+    U: Copy + PartialEq + std::ops::BitAnd<Output = U>,
+{
+    if usage & required != required {
+        return Err(MaterialError::MissingUsage(binding));
+    }
+
+    Ok(())
+}
+
 impl Material {
+    /// Bindings and types of the parameters, sorted by binding.
+    pub(crate) fn parameters(&self) -> &[MaterialParameter] {
+        &self.parameters
+    }
+
+    /// The immutable sampler of an element of a parameter.
+    fn immutable_sampler(&self, binding: u32, element: u32) -> Option<SamplerHandle> {
+        self.immutable_samplers
+            .iter()
+            .find(|(b, _)| *b == binding)
+            .and_then(|(_, samplers)| samplers.get(element as usize))
+            .copied()
+    }
+
     fn next_nonempty_pool(&self) -> Option<usize> {
         for (i, allocd) in self.pool_allocated.iter().enumerate() {
             if *allocd < self.sets_per_pool {
@@ -394,9 +825,9 @@ impl Material {
         let descriptors = self
             .parameters
             .iter()
-            .map(|(_binding, ty)| gfx::pso::DescriptorRangeDesc {
-                count: self.sets_per_pool as usize,
-                ty: ty.clone().into(),
+            .map(|param| gfx::pso::DescriptorRangeDesc {
+                count: self.sets_per_pool as usize * param.count as usize,
+                ty: param.ty.into(),
             })
             .collect::<SmallVec<[_; 16]>>();
 
@@ -491,6 +922,70 @@ pub enum MaterialError {
     #[display(fmt = "Invalid handle used")]
     InvalidHandle,
 
+    #[display(fmt = "Array size given for binding {} which is not a parameter", _0)]
+    InvalidArrayBinding(u32),
+
+    #[display(fmt = "Array parameter at binding {} has no elements", _0)]
+    EmptyArray(u32),
+
+    #[display(
+        fmt = "Partially bound array at binding {} is not supported by the device",
+        _0
+    )]
+    PartiallyBoundUnsupported(u32),
+
+    #[display(fmt = "Visibility given for binding {} which is not a parameter", _0)]
+    InvalidVisibilityBinding(u32),
+
+    #[display(
+        fmt = "Immutable samplers given for binding {} do not fit the parameter",
+        _0
+    )]
+    InvalidImmutableSamplers(u32),
+
+    #[display(fmt = "Binding {} is not a parameter of the material", _0)]
+    InvalidBinding(u32),
+
+    #[display(fmt = "Binding {} is given more than once", _0)]
+    DuplicateBinding(u32),
+
+    #[display(fmt = "Binding {} uses immutable samplers which can't be replaced", _0)]
+    ImmutableSamplerWrite(u32),
+
+    #[display(fmt = "Image written to binding {} needs a sampler", _0)]
+    MissingSampler(u32),
+
+    #[display(
+        fmt = "Writing {} descriptors at array offset {} is out of range for binding {}",
+        count,
+        offset,
+        binding
+    )]
+    InvalidArrayRange {
+        binding: u32,
+        offset: u32,
+        count: usize,
+    },
+
+    #[display(
+        fmt = "Data written to binding {} does not match the parameter type {:?}",
+        binding,
+        expected
+    )]
+    TypeMismatch {
+        binding: u32,
+        expected: MaterialParameterType,
+    },
+
+    #[display(fmt = "Invalid resource handle written to binding {}", _0)]
+    InvalidResourceHandle(u32),
+
+    #[display(
+        fmt = "Resource written to binding {} lacks the usage flags needed",
+        _0
+    )]
+    MissingUsage(u32),
+
     #[display(fmt = "Material could not be created because of insufficient memory")]
     CreateError(gfx::device::OutOfMemory),
 
@@ -499,3 +994,458 @@ pub enum MaterialError {
 }
 
 impl std::error::Error for MaterialError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_info<'a>(
+        parameters: &'a [(u32, MaterialParameterType)],
+        arrays: &'a [(u32, ParameterArray)],
+    ) -> MaterialCreateInfo<'a> {
+        MaterialCreateInfo {
+            parameters,
+            arrays,
+            visibility: &[],
+            immutable_samplers: &[],
+        }
+    }
+
+    fn with_visibility<'a>(
+        parameters: &'a [(u32, MaterialParameterType)],
+        visibility: &'a [(u32, ShaderStages)],
+    ) -> MaterialCreateInfo<'a> {
+        MaterialCreateInfo {
+            visibility,
+            ..create_info(parameters, &[])
+        }
+    }
+
+    fn with_samplers<'a>(
+        parameters: &'a [(u32, MaterialParameterType)],
+        arrays: &'a [(u32, ParameterArray)],
+        immutable_samplers: &'a [(u32, &'a [SamplerHandle])],
+    ) -> MaterialCreateInfo<'a> {
+        MaterialCreateInfo {
+            immutable_samplers,
+            ..create_info(parameters, arrays)
+        }
+    }
+
+    fn array(count: u32, partially_bound: bool) -> ParameterArray {
+        ParameterArray {
+            count,
+            partially_bound,
+        }
+    }
+
+    fn sampled_images(count: usize) -> Vec<InstanceWriteData> {
+        (0..count)
+            .map(|i| InstanceWriteData::Image {
+                image: Handle::new(i, 0),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn array_parameters() {
+        use self::MaterialParameterType as P;
+
+        let params = [(0, P::SampledImage), (1, P::Sampler)];
+
+        let info = create_info(&params, &[(0, array(16, false))]);
+        let parameters = material_parameters(&info, false).unwrap();
+
+        assert_eq!(parameters[0].count, 16);
+        assert_eq!(parameters[1].count, 1);
+
+        match material_parameters(&create_info(&params, &[(2, array(4, false))]), false) {
+            Err(MaterialError::InvalidArrayBinding(2)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        match material_parameters(&create_info(&params, &[(0, array(0, false))]), false) {
+            Err(MaterialError::EmptyArray(0)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        // partially bound arrays depend on the device
+        let partial = create_info(&params, &[(0, array(16, true))]);
+
+        match material_parameters(&partial, false) {
+            Err(MaterialError::PartiallyBoundUnsupported(0)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        assert_eq!(material_parameters(&partial, true).unwrap()[0].count, 16);
+    }
+
+    #[test]
+    fn array_write_ranges() {
+        use self::MaterialParameterType as P;
+
+        let params = [(0, P::SampledImage), (1, P::Sampler)];
+        let info = create_info(&params, &[(0, array(4, false))]);
+        let parameters = material_parameters(&info, false).unwrap();
+
+        let data = sampled_images(3);
+
+        let write = |binding, array_offset, data| InstanceWrite {
+            binding,
+            array_offset,
+            data,
+        };
+
+        // several descriptors are written to consecutive elements
+        let param = write_parameter(&parameters, &write(0, 1, &data)).unwrap();
+        assert_eq!(param.binding, 0);
+
+        assert!(write_parameter(&parameters, &write(0, 0, &data[..1])).is_ok());
+        assert!(write_parameter(&parameters, &write(1, 0, &data[..1])).is_ok());
+
+        let out_of_range = vec![
+            (0, 2, &data[..]),
+            (0, 4, &data[..1]),
+            (0, u32::max_value(), &data[..]),
+            (0, 0, &data[..0]),
+            (1, 0, &data[..2]),
+        ];
+
+        for (binding, offset, data) in out_of_range {
+            match write_parameter(&parameters, &write(binding, offset, data)) {
+                Err(MaterialError::InvalidArrayRange {
+                    binding: b,
+                    offset: o,
+                    count,
+                }) => {
+                    assert_eq!((b, o, count), (binding, offset, data.len()));
+                }
+                res => panic!("unexpected result {:?}", res),
+            }
+        }
+    }
+
+    fn parameter(binding: u32, ty: MaterialParameterType) -> MaterialParameter {
+        MaterialParameter {
+            binding,
+            ty,
+            count: 1,
+            stages: ShaderStages::ALL,
+            immutable_samplers: false,
+        }
+    }
+
+    /// Write `data` to `param` of an instance while no resources exist.
+    fn descriptor_error(param: &MaterialParameter, data: &InstanceWriteData) -> MaterialError {
+        immutable_descriptor_error(param, None, data)
+    }
+
+    fn immutable_descriptor_error(
+        param: &MaterialParameter,
+        immutable_sampler: Option<SamplerHandle>,
+        data: &InstanceWriteData,
+    ) -> MaterialError {
+        let samplers = SamplerStorage::new();
+        let images = ImageStorage::new();
+        let buffers = BufferStorage::new(1);
+
+        match instance_descriptor(&samplers, &images, &buffers, param, immutable_sampler, data) {
+            Ok(_) => panic!("write to binding {} was accepted", param.binding),
+            Err(err) => err,
+        }
+    }
+
+    struct WriteData {
+        sampler: InstanceWriteData,
+        image: InstanceWriteData,
+        combined: InstanceWriteData,
+        buffer: InstanceWriteData,
+        uniform_texel: InstanceWriteData,
+        storage_texel: InstanceWriteData,
+    }
+
+    fn write_data() -> WriteData {
+        WriteData {
+            sampler: InstanceWriteData::Sampler {
+                sampler: Handle::new(0, 0),
+            },
+            image: InstanceWriteData::Image {
+                image: Handle::new(0, 0),
+            },
+            combined: InstanceWriteData::CombinedImageSampler {
+                image: Handle::new(0, 0),
+                sampler: Some(Handle::new(0, 0)),
+            },
+            buffer: InstanceWriteData::Buffer {
+                buffer: Handle::new(0, 0),
+                region: None..None,
+            },
+            uniform_texel: InstanceWriteData::UniformTexelBuffer {
+                view: Handle::new(0, 0),
+            },
+            storage_texel: InstanceWriteData::StorageTexelBuffer {
+                view: Handle::new(0, 0),
+            },
+        }
+    }
+
+    #[test]
+    fn write_type_mismatch() {
+        use self::MaterialParameterType as P;
+
+        let data = write_data();
+
+        let cases = vec![
+            (P::SampledImage, &data.sampler),
+            (P::Sampler, &data.image),
+            (P::CombinedImageSampler, &data.image),
+            (P::SampledImage, &data.combined),
+            (P::UniformTexelBuffer, &data.buffer),
+            (P::UniformBuffer, &data.uniform_texel),
+            (P::UniformTexelBuffer, &data.storage_texel),
+            (P::StorageTexelBuffer, &data.uniform_texel),
+        ];
+
+        for (binding, (ty, data)) in cases.into_iter().enumerate() {
+            let binding = binding as u32;
+
+            match descriptor_error(&parameter(binding, ty), data) {
+                MaterialError::TypeMismatch {
+                    binding: b,
+                    expected,
+                } => {
+                    assert_eq!((b, expected), (binding, ty));
+                }
+                err => panic!("unexpected error {:?}", err),
+            }
+        }
+    }
+
+    #[test]
+    fn write_invalid_handles() {
+        use self::MaterialParameterType as P;
+
+        let data = write_data();
+
+        let cases = vec![
+            (P::Sampler, &data.sampler),
+            (P::SampledImage, &data.image),
+            (P::StorageImage, &data.image),
+            (P::CombinedImageSampler, &data.combined),
+            (P::UniformBuffer, &data.buffer),
+            (P::StorageBufferDynamic, &data.buffer),
+            (P::UniformTexelBuffer, &data.uniform_texel),
+            (P::StorageTexelBuffer, &data.storage_texel),
+        ];
+
+        for (binding, (ty, data)) in cases.into_iter().enumerate() {
+            let binding = binding as u32;
+
+            match descriptor_error(&parameter(binding, ty), data) {
+                MaterialError::InvalidResourceHandle(b) => assert_eq!(b, binding),
+                err => panic!("unexpected error {:?}", err),
+            }
+        }
+
+        let buffers = BufferStorage::new(1);
+
+        match texel_view(
+            &buffers,
+            3,
+            Handle::new(0, 0),
+            gfx::buffer::Usage::UNIFORM_TEXEL,
+        ) {
+            Err(MaterialError::InvalidResourceHandle(3)) => {}
+            Err(err) => panic!("unexpected error {:?}", err),
+            Ok(_) => panic!("invalid buffer view was accepted"),
+        }
+    }
+
+    #[test]
+    fn write_missing_usage() {
+        use gfx::buffer::Usage as BUsage;
+        use gfx::image::Usage as IUsage;
+
+        assert!(check_usage(0, IUsage::SAMPLED | IUsage::TRANSFER_DST, IUsage::SAMPLED).is_ok());
+        assert!(check_usage(0, BUsage::UNIFORM | BUsage::STORAGE, BUsage::STORAGE).is_ok());
+
+        match check_usage(1, IUsage::SAMPLED, IUsage::STORAGE) {
+            Err(MaterialError::MissingUsage(1)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        match check_usage(2, BUsage::UNIFORM_TEXEL, BUsage::STORAGE_TEXEL) {
+            Err(MaterialError::MissingUsage(2)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+
+    #[test]
+    fn write_unknown_binding() {
+        use self::MaterialParameterType as P;
+
+        let parameters = [parameter(0, P::SampledImage), parameter(2, P::Sampler)];
+        let data = sampled_images(1);
+
+        let write = InstanceWrite {
+            binding: 1,
+            array_offset: 0,
+            data: &data,
+        };
+
+        match write_parameter(&parameters, &write) {
+            Err(MaterialError::InvalidBinding(1)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+
+    #[test]
+    fn parameter_visibility() {
+        use self::MaterialParameterType as P;
+
+        let params = [(0, P::UniformBuffer), (1, P::SampledImage)];
+
+        let parameters = material_parameters(
+            &with_visibility(&params, &[(1, ShaderStages::FRAGMENT)]),
+            false,
+        )
+        .unwrap();
+
+        assert_eq!(parameters[0].stages, ShaderStages::ALL);
+        assert_eq!(parameters[1].stages, ShaderStages::FRAGMENT);
+
+        match material_parameters(
+            &with_visibility(&params, &[(2, ShaderStages::VERTEX)]),
+            false,
+        ) {
+            Err(MaterialError::InvalidVisibilityBinding(2)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        let twice = [(1, ShaderStages::VERTEX), (1, ShaderStages::FRAGMENT)];
+
+        match material_parameters(&with_visibility(&params, &twice), false) {
+            Err(MaterialError::DuplicateBinding(1)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+
+    #[test]
+    fn duplicate_bindings() {
+        use self::MaterialParameterType as P;
+
+        let params = [(0, P::SampledImage), (0, P::Sampler)];
+
+        match material_parameters(&create_info(&params, &[]), false) {
+            Err(MaterialError::DuplicateBinding(0)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        let params = [(0, P::SampledImage)];
+        let arrays = [(0, array(4, false)), (0, array(8, false))];
+
+        match material_parameters(&create_info(&params, &arrays), false) {
+            Err(MaterialError::DuplicateBinding(0)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+
+    #[test]
+    fn immutable_samplers() {
+        use self::MaterialParameterType as P;
+
+        let params = [
+            (0, P::Sampler),
+            (1, P::CombinedImageSampler),
+            (2, P::SampledImage),
+        ];
+        let arrays = [(1, array(2, false))];
+
+        let one: &[SamplerHandle] = &[Handle::new(0, 0)];
+        let two: &[SamplerHandle] = &[Handle::new(0, 0), Handle::new(1, 0)];
+
+        let parameters = material_parameters(
+            &with_samplers(&params, &arrays, &[(0, one), (1, two)]),
+            false,
+        )
+        .unwrap();
+
+        assert!(parameters[0].immutable_samplers);
+        assert!(parameters[1].immutable_samplers);
+        assert!(!parameters[2].immutable_samplers);
+
+        let invalid = [
+            // one sampler for every element
+            (1, one),
+            (0, two),
+            // only for sampler parameters
+            (2, one),
+            // only for existing parameters
+            (3, one),
+        ];
+
+        for (binding, samplers) in invalid.iter() {
+            match material_parameters(
+                &with_samplers(&params, &arrays, &[(*binding, *samplers)]),
+                false,
+            ) {
+                Err(MaterialError::InvalidImmutableSamplers(b)) => assert_eq!(b, *binding),
+                res => panic!("unexpected result {:?}", res),
+            }
+        }
+
+        match material_parameters(
+            &with_samplers(&params, &arrays, &[(0, one), (0, one)]),
+            false,
+        ) {
+            Err(MaterialError::DuplicateBinding(0)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+
+    #[test]
+    fn immutable_sampler_writes() {
+        use self::MaterialParameterType as P;
+
+        let immutable = Some(Handle::new(0, 0));
+        let data = write_data();
+
+        let sampler = MaterialParameter {
+            immutable_samplers: true,
+            ..parameter(0, P::Sampler)
+        };
+
+        let combined = MaterialParameter {
+            immutable_samplers: true,
+            ..parameter(1, P::CombinedImageSampler)
+        };
+
+        // immutable samplers can't be replaced by writes of either type
+        match immutable_descriptor_error(&sampler, immutable, &data.sampler) {
+            MaterialError::ImmutableSamplerWrite(0) => {}
+            err => panic!("unexpected error {:?}", err),
+        }
+
+        match immutable_descriptor_error(&combined, immutable, &data.combined) {
+            MaterialError::ImmutableSamplerWrite(1) => {}
+            err => panic!("unexpected error {:?}", err),
+        }
+
+        // without a sampler in the write, the immutable sampler is used
+        let image_only = InstanceWriteData::CombinedImageSampler {
+            image: Handle::new(0, 0),
+            sampler: None,
+        };
+
+        match immutable_descriptor_error(&combined, immutable, &image_only) {
+            MaterialError::InvalidResourceHandle(1) => {}
+            err => panic!("unexpected error {:?}", err),
+        }
+
+        // other parameters need the sampler in the write
+        match descriptor_error(&parameter(2, P::CombinedImageSampler), &image_only) {
+            MaterialError::MissingSampler(2) => {}
+            err => panic!("unexpected error {:?}", err),
+        }
+    }
+}
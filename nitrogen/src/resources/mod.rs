@@ -11,10 +11,16 @@ pub(crate) mod command_pool;
 pub mod image;
 pub mod material;
 pub(crate) mod pipeline;
+pub mod pipeline_cache;
 pub(crate) mod render_pass;
 pub mod sampler;
 pub(crate) mod semaphore_pool;
 pub mod shader;
+#[cfg(feature = "shaderc")]
+pub mod shader_compiler;
+pub mod shader_variant;
+pub mod shader_watcher;
+pub mod spirv;
 pub mod vertex_attrib;
 
 use bitflags::bitflags;
@@ -3,10 +3,15 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use crate::device::DeviceContext;
+use crate::pipeline_cache::{self, CacheIdentity, PipelineCacheError};
 use crate::storage::{Handle, Storage};
 
-use crate::graph::{BlendMode, DepthMode};
-use crate::render_pass::{RenderPassHandle, RenderPassStorage};
+use crate::graph::{
+    BlendMode, ColorMask, ComputePipelineInfo, DepthMode, GraphicsPipelineInfo, RasterizerState,
+    StencilMode,
+};
+use crate::material::MaterialParameter;
+use crate::render_pass::{RenderPassCompat, RenderPassHandle, RenderPassStorage};
 use crate::vertex_attrib::VertexAttribResource;
 
 use crate::types;
@@ -18,10 +23,12 @@ use gfx::pso;
 use gfx::Device;
 
 use crate::graph::pass::Specialization;
+use crate::resources::spirv::EntryPoint;
 use smallvec::SmallVec;
 use std::borrow::Cow;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
 use std::error::Error;
+use std::ops::Range;
 
 #[derive(Clone, Debug, From, Display)]
 pub enum PipelineError {
@@ -47,19 +54,51 @@ pub(crate) enum Pipeline {
     Compute,
 }
 
+/// Versions of the shaders a pipeline depends on, including the shaders variants were compiled
+/// from.
+pub(crate) type ShaderVersions = SmallVec<[u64; 10]>;
+
+/// Identifies graphics pipelines which can be shared between passes and graphs.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub(crate) struct GraphicsPipelineKey {
+    pub(crate) info: GraphicsPipelineInfo,
+    /// parameters of the pass material, the material itself is specific to a graph.
+    pub(crate) pass_material: Option<Vec<MaterialParameter>>,
+    pub(crate) shader_versions: ShaderVersions,
+    pub(crate) render_pass: RenderPassCompat,
+}
+
+/// Identifies compute pipelines which can be shared between passes and graphs.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub(crate) struct ComputePipelineKey {
+    pub(crate) info: ComputePipelineInfo,
+    /// parameters of the pass material, the material itself is specific to a graph.
+    pub(crate) pass_material: Option<Vec<MaterialParameter>>,
+    pub(crate) shader_versions: ShaderVersions,
+}
+
 pub(crate) struct GraphicsPipeline {
     pub(crate) pipeline: types::GraphicsPipeline,
     pub(crate) layout: types::PipelineLayout,
+    /// push constant ranges in bytes
+    pub(crate) push_constants: SmallVec<[(pso::ShaderStageFlags, Range<u32>); 2]>,
+
+    key: GraphicsPipelineKey,
 }
 
 pub(crate) struct ComputePipeline {
     pub(crate) pipeline: types::ComputePipeline,
     pub(crate) layout: types::PipelineLayout,
+    /// push constant ranges in bytes
+    pub(crate) push_constants: SmallVec<[(pso::ShaderStageFlags, Range<u32>); 2]>,
+
+    key: ComputePipelineKey,
 }
 
 #[derive(Clone)]
 pub(crate) struct ShaderInfo<'a> {
-    pub(crate) content: &'a [u8],
+    pub(crate) interface: &'a EntryPoint,
+    pub(crate) module: &'a ShaderModule,
     pub(crate) entry: &'a str,
     pub(crate) specialization: &'a [Specialization],
 }
@@ -67,136 +106,212 @@ pub(crate) struct ShaderInfo<'a> {
 #[derive(Clone)]
 pub(crate) struct GraphicsPipelineCreateInfo<'a> {
     pub(crate) primitive: crate::graph::Primitive,
+    pub(crate) rasterizer: RasterizerState,
 
     pub(crate) vertex_attribs: Option<VertexAttribResource>,
 
     pub(crate) descriptor_set_layout: &'a [&'a types::DescriptorSetLayout],
-    // TODO shader stage flags
-    pub(crate) push_constants: &'a [std::ops::Range<u32>],
+    /// push constant ranges in bytes
+    pub(crate) push_constants: &'a [(pso::ShaderStageFlags, Range<u32>)],
     pub(crate) blend_modes: &'a [BlendMode],
+    pub(crate) color_masks: &'a [ColorMask],
     pub(crate) depth_mode: Option<DepthMode>,
-    // TODO stencil mode
+    pub(crate) stencil_mode: Option<StencilMode>,
     pub(crate) shader_vertex: ShaderInfo<'a>,
     pub(crate) shader_fragment: Option<ShaderInfo<'a>>,
     pub(crate) shader_geometry: Option<ShaderInfo<'a>>,
+    pub(crate) shader_tess_control: Option<ShaderInfo<'a>>,
+    pub(crate) shader_tess_evaluation: Option<ShaderInfo<'a>>,
 }
 
 #[derive(Clone)]
 pub(crate) struct ComputePipelineCreateInfo<'a> {
     pub(crate) descriptor_set_layout: &'a [&'a types::DescriptorSetLayout],
     pub(crate) shader: ShaderInfo<'a>,
-    // TODO shader stage flags
-    pub(crate) push_constants: &'a [std::ops::Range<u32>],
+    /// push constant ranges in bytes
+    pub(crate) push_constants: &'a [(pso::ShaderStageFlags, Range<u32>)],
 }
 
 pub(crate) struct PipelineStorage {
     graphic_pipelines: BTreeMap<usize, GraphicsPipeline>,
     compute_pipelines: BTreeMap<usize, ComputePipeline>,
     storage: Storage<Pipeline>,
+
+    graphics_lookup: HashMap<GraphicsPipelineKey, PipelineHandle>,
+    compute_lookup: HashMap<ComputePipelineKey, PipelineHandle>,
+
+    /// number of users of each pipeline, shared pipelines are destroyed once it drops to 0.
+    references: BTreeMap<usize, usize>,
+
+    cache: Option<types::PipelineCache>,
 }
 
 impl PipelineStorage {
-    pub(crate) fn new() -> Self {
+    pub(crate) unsafe fn new(device: &DeviceContext) -> Self {
+        // pipelines can be created without a cache, so not having one is not fatal.
+        let cache = device.device.create_pipeline_cache(None).ok();
+
         PipelineStorage {
             storage: Storage::new(),
             graphic_pipelines: BTreeMap::new(),
             compute_pipelines: BTreeMap::new(),
+            graphics_lookup: HashMap::new(),
+            compute_lookup: HashMap::new(),
+            references: BTreeMap::new(),
+            cache,
         }
     }
 
-    pub(crate) unsafe fn create_graphics_pipeline(
+    pub(crate) unsafe fn release(self, device: &DeviceContext) {
+        if let Some(cache) = self.cache {
+            device.device.destroy_pipeline_cache(cache);
+        }
+    }
+
+    /// Replace the pipeline cache with one created from previously saved data.
+    ///
+    /// If the data is rejected the current cache stays in use.
+    pub(crate) unsafe fn load_cache(
         &mut self,
         device: &DeviceContext,
-        render_pass_storage: &RenderPassStorage,
-        render_pass_handle: RenderPassHandle,
-        create_info: GraphicsPipelineCreateInfo,
-    ) -> Result<PipelineHandle> {
-        struct ShaderModules {
-            vertex: ShaderModule,
-            fragment: Option<ShaderModule>,
-            geometry: Option<ShaderModule>,
+        data: &[u8],
+    ) -> std::result::Result<(), PipelineCacheError> {
+        let identity = CacheIdentity::of(device)?;
+        let data = pipeline_cache::decode(&identity, data)?;
+
+        let cache = device.device.create_pipeline_cache(Some(data))?;
+
+        if let Some(old) = std::mem::replace(&mut self.cache, Some(cache)) {
+            device.device.destroy_pipeline_cache(old);
         }
 
-        let module = ShaderModules {
-            vertex: device
-                .device
-                .create_shader_module(create_info.shader_vertex.content)?,
+        Ok(())
+    }
 
-            // I'd love to use Option::map() here, but then I can't use ? for errors.
-            fragment: if let Some(ref frag) = create_info.shader_fragment {
-                Some(device.device.create_shader_module(frag.content)?)
-            } else {
-                None
-            },
+    /// Retrieve the content of the pipeline cache in a form suitable for `load_cache`.
+    pub(crate) unsafe fn save_cache(
+        &self,
+        device: &DeviceContext,
+    ) -> std::result::Result<Vec<u8>, PipelineCacheError> {
+        let cache = self.cache.as_ref().ok_or(PipelineCacheError::Unavailable)?;
 
-            geometry: if let Some(ref geom) = create_info.shader_geometry {
-                Some(device.device.create_shader_module(geom.content)?)
-            } else {
-                None
-            },
+        let data = device.device.get_pipeline_cache_data(cache)?;
+
+        let identity = CacheIdentity::from_driver_data(&device.adapter.info.name, &data)?;
+
+        Ok(pipeline_cache::encode(&identity, &data))
+    }
+
+    /// Retrieve an existing graphics pipeline matching the key and add a reference to it.
+    pub(crate) fn acquire_graphics(&mut self, key: &GraphicsPipelineKey) -> Option<PipelineHandle> {
+        let handle = *self.graphics_lookup.get(key)?;
+        self.add_reference(handle)
+    }
+
+    /// Retrieve an existing compute pipeline matching the key and add a reference to it.
+    pub(crate) fn acquire_compute(&mut self, key: &ComputePipelineKey) -> Option<PipelineHandle> {
+        let handle = *self.compute_lookup.get(key)?;
+        self.add_reference(handle)
+    }
+
+    fn add_reference(&mut self, handle: PipelineHandle) -> Option<PipelineHandle> {
+        if !self.storage.is_alive(handle) {
+            return None;
+        }
+
+        *self.references.get_mut(&handle.id())? += 1;
+
+        Some(handle)
+    }
+
+    /// Remove a reference to a pipeline.
+    ///
+    /// Returns `true` if this was the last reference, the handle is freed in that case.
+    fn release_reference(&mut self, handle: PipelineHandle) -> bool {
+        if !self.storage.is_alive(handle) {
+            return false;
+        }
+
+        let references = match self.references.get_mut(&handle.id()) {
+            Some(references) => references,
+            None => return false,
         };
 
+        *references -= 1;
+
+        if *references > 0 {
+            return false;
+        }
+
+        self.references.remove(&handle.id());
+        self.storage.remove(handle);
+
+        true
+    }
+
+    pub(crate) unsafe fn create_graphics_pipeline(
+        &mut self,
+        device: &DeviceContext,
+        render_pass_storage: &RenderPassStorage,
+        render_pass_handle: RenderPassHandle,
+        create_info: GraphicsPipelineCreateInfo,
+        key: GraphicsPipelineKey,
+    ) -> Result<PipelineHandle> {
         let layout = device.device.create_pipeline_layout(
             create_info.descriptor_set_layout.iter().cloned(),
             create_info
                 .push_constants
                 .iter()
-                .map(|range| (pso::ShaderStageFlags::ALL, range.clone())),
+                .map(|(stages, range)| (*stages, (range.start / 4)..(range.end / 4))),
         )?;
 
-        let pipeline = {
-            struct ShaderEntries<'a> {
-                vertex: pso::EntryPoint<'a, back::Backend>,
-                fragment: Option<pso::EntryPoint<'a, back::Backend>>,
-                geometry: Option<pso::EntryPoint<'a, back::Backend>>,
-            };
+        let specialization = |info: &Option<ShaderInfo>| {
+            info.as_ref()
+                .map(|info| specialization_data(info.specialization))
+        };
+
+        let vertex_spec = specialization_data(create_info.shader_vertex.specialization);
+        let hull_spec = specialization(&create_info.shader_tess_control);
+        let domain_spec = specialization(&create_info.shader_tess_evaluation);
+        let geometry_spec = specialization(&create_info.shader_geometry);
+        let fragment_spec = specialization(&create_info.shader_fragment);
 
-            let shader_entries = {
-                ShaderEntries {
-                    vertex: pso::EntryPoint {
-                        entry: create_info.shader_vertex.entry,
-                        module: &module.vertex,
-                        specialization: pso::Specialization {
-                            constants: Cow::Borrowed(&[]),
-                            data: Cow::Borrowed(&[]),
-                        },
+        let pipeline = {
+            fn entry_point<'a>(
+                info: &ShaderInfo<'a>,
+                (constants, data): &'a SpecializationData,
+            ) -> pso::EntryPoint<'a, back::Backend> {
+                pso::EntryPoint {
+                    entry: info.entry,
+                    module: info.module,
+                    specialization: pso::Specialization {
+                        constants: Cow::Borrowed(constants.as_slice()),
+                        data: Cow::Borrowed(data.as_slice()),
                     },
-                    fragment: create_info
-                        .shader_fragment
-                        .as_ref()
-                        .map(|s| pso::EntryPoint {
-                            entry: s.entry,
-                            module: module.fragment.as_ref().unwrap(),
-                            specialization: pso::Specialization {
-                                constants: Cow::Borrowed(&[]),
-                                data: Cow::Borrowed(&[]),
-                            },
-                        }),
-                    geometry: create_info
-                        .shader_geometry
-                        .as_ref()
-                        .map(|s| pso::EntryPoint {
-                            entry: s.entry,
-                            module: module.geometry.as_ref().unwrap(),
-                            specialization: pso::Specialization {
-                                constants: Cow::Borrowed(&[]),
-                                data: Cow::Borrowed(&[]),
-                            },
-                        }),
                 }
-            };
+            }
+
+            fn optional_entry_point<'a>(
+                info: &Option<ShaderInfo<'a>>,
+                spec: &'a Option<SpecializationData>,
+            ) -> Option<pso::EntryPoint<'a, back::Backend>> {
+                match (info, spec) {
+                    (Some(info), Some(spec)) => Some(entry_point(info, spec)),
+                    _ => None,
+                }
+            }
 
             let shaders = pso::GraphicsShaderSet {
-                vertex: shader_entries.vertex,
-                hull: None,
-                domain: None,
-                geometry: shader_entries.geometry,
-                fragment: shader_entries.fragment,
+                vertex: entry_point(&create_info.shader_vertex, &vertex_spec),
+                hull: optional_entry_point(&create_info.shader_tess_control, &hull_spec),
+                domain: optional_entry_point(&create_info.shader_tess_evaluation, &domain_spec),
+                geometry: optional_entry_point(&create_info.shader_geometry, &geometry_spec),
+                fragment: optional_entry_point(&create_info.shader_fragment, &fragment_spec),
             };
 
             let primitive = create_info.primitive.into();
 
-            let rasterizer = { pso::Rasterizer::FILL };
+            let rasterizer = create_info.rasterizer.into();
 
             let render_pass = render_pass_storage.raw(render_pass_handle).unwrap();
 
@@ -213,31 +328,35 @@ impl PipelineStorage {
                     desc.vertex_buffers.push(pso::VertexBufferDesc {
                         binding: buffer.binding as _,
                         stride: buffer.stride as _,
-                        rate: gfx::pso::VertexInputRate::Vertex,
+                        rate: buffer.rate.into(),
                     });
                 }
 
                 desc.attributes.extend_from_slice(&data.attribs[..]);
             }
 
-            // TODO allow for finer control over blend modes?
             desc.blender
                 .targets
-                .extend(create_info.blend_modes.iter().map(|mode| {
-                    pso::ColorBlendDesc(
-                        pso::ColorMask::ALL,
-                        match mode {
-                            BlendMode::Add => pso::BlendState::ADD,
-                            BlendMode::Alpha => pso::BlendState::ALPHA,
-                            BlendMode::Mul => pso::BlendState::MULTIPLY,
-                        },
-                    )
+                .extend(create_info.blend_modes.iter().enumerate().map(|(i, mode)| {
+                    let mask = create_info
+                        .color_masks
+                        .get(i)
+                        .cloned()
+                        .unwrap_or(ColorMask::ALL);
+
+                    pso::ColorBlendDesc(mask.into(), (*mode).into())
                 }));
 
             // depth and stencil
             {
-                // TODO implement stencil
-                desc.depth_stencil.stencil = gfx::pso::StencilTest::default();
+                desc.depth_stencil.stencil = if let Some(stencil) = create_info.stencil_mode {
+                    gfx::pso::StencilTest::On {
+                        front: stencil.front.into(),
+                        back: stencil.back.into(),
+                    }
+                } else {
+                    gfx::pso::StencilTest::Off
+                };
 
                 desc.depth_stencil.depth = if let Some(depth) = create_info.depth_mode {
                     gfx::pso::DepthTest::On {
@@ -250,32 +369,25 @@ impl PipelineStorage {
                 desc.depth_stencil.depth_bounds = false;
             }
 
-            device.device.create_graphics_pipeline(&desc, None)?
+            device
+                .device
+                .create_graphics_pipeline(&desc, self.cache.as_ref())?
         };
 
-        // destroy shader modules
-        {
-            let ShaderModules {
-                vertex,
-                fragment,
-                geometry,
-            } = module;
-
-            device.device.destroy_shader_module(vertex);
-
-            if let Some(frag) = fragment {
-                device.device.destroy_shader_module(frag);
-            }
-
-            if let Some(geom) = geometry {
-                device.device.destroy_shader_module(geom);
-            }
-        }
-
         let handle = self.storage.insert(Pipeline::Graphics);
 
-        self.graphic_pipelines
-            .insert(handle.id(), GraphicsPipeline { pipeline, layout });
+        self.graphics_lookup.insert(key.clone(), handle);
+
+        self.graphic_pipelines.insert(
+            handle.id(),
+            GraphicsPipeline {
+                pipeline,
+                layout,
+                push_constants: create_info.push_constants.iter().cloned().collect(),
+                key,
+            },
+        );
+        self.references.insert(handle.id(), 1);
 
         Ok(handle)
     }
@@ -284,43 +396,22 @@ impl PipelineStorage {
         &mut self,
         device: &DeviceContext,
         create_info: ComputePipelineCreateInfo,
+        key: ComputePipelineKey,
     ) -> Result<PipelineHandle> {
-        let shader_module = device
-            .device
-            .create_shader_module(create_info.shader.content)?;
-
         let layout = device.device.create_pipeline_layout(
             create_info.descriptor_set_layout.iter().cloned(),
             create_info
                 .push_constants
                 .iter()
-                .map(|range| (pso::ShaderStageFlags::COMPUTE, range.clone())),
+                .map(|(stages, range)| (*stages, (range.start / 4)..(range.end / 4))),
         )?;
 
-        let (spec_const, spec_data) = {
-            let mut constants = SmallVec::<[_; 16]>::new();
-            let mut data = SmallVec::<[u8; 128]>::new();
-
-            for spec in create_info.shader.specialization {
-                let start = data.len();
-                let end = start + spec.value.len();
-
-                let constant = pso::SpecializationConstant {
-                    id: spec.id,
-                    range: (start as u16)..(end as u16),
-                };
-
-                constants.push(constant);
-                data.extend_from_slice(&spec.value);
-            }
-
-            (constants, data)
-        };
+        let (spec_const, spec_data) = specialization_data(create_info.shader.specialization);
 
         let pipeline = {
             let shader_entry = pso::EntryPoint {
                 entry: create_info.shader.entry,
-                module: &shader_module,
+                module: create_info.shader.module,
                 specialization: pso::Specialization {
                     constants: Cow::Borrowed(spec_const.as_slice()),
                     data: Cow::Borrowed(spec_data.as_slice()),
@@ -329,15 +420,25 @@ impl PipelineStorage {
 
             let desc = pso::ComputePipelineDesc::new(shader_entry, &layout);
 
-            device.device.create_compute_pipeline(&desc, None)?
+            device
+                .device
+                .create_compute_pipeline(&desc, self.cache.as_ref())?
         };
 
-        device.device.destroy_shader_module(shader_module);
-
         let handle = self.storage.insert(Pipeline::Compute);
 
-        self.compute_pipelines
-            .insert(handle.id(), ComputePipeline { pipeline, layout });
+        self.compute_lookup.insert(key.clone(), handle);
+
+        self.compute_pipelines.insert(
+            handle.id(),
+            ComputePipeline {
+                pipeline,
+                layout,
+                push_constants: create_info.push_constants.iter().cloned().collect(),
+                key,
+            },
+        );
+        self.references.insert(handle.id(), 1);
 
         Ok(handle)
     }
@@ -352,15 +453,20 @@ impl PipelineStorage {
         for handle in pipelines.into_iter() {
             let handle = *handle.borrow();
 
-            if self.storage.remove(handle).is_some() {
-                if let Some(gfx) = self.graphic_pipelines.remove(&handle.0) {
-                    res_list.queue_pipeline_graphic(gfx.pipeline);
-                    res_list.queue_pipeline_layout(gfx.layout);
-                }
-                if let Some(cmpt) = self.compute_pipelines.remove(&handle.0) {
-                    res_list.queue_pipeline_compute(cmpt.pipeline);
-                    res_list.queue_pipeline_layout(cmpt.layout);
-                }
+            // pipelines can be shared, only destroy them once nothing references them anymore.
+            if !self.release_reference(handle) {
+                continue;
+            }
+
+            if let Some(gfx) = self.graphic_pipelines.remove(&handle.0) {
+                self.graphics_lookup.remove(&gfx.key);
+                res_list.queue_pipeline_graphic(gfx.pipeline);
+                res_list.queue_pipeline_layout(gfx.layout);
+            }
+            if let Some(cmpt) = self.compute_pipelines.remove(&handle.0) {
+                self.compute_lookup.remove(&cmpt.key);
+                res_list.queue_pipeline_compute(cmpt.pipeline);
+                res_list.queue_pipeline_layout(cmpt.layout);
             }
         }
     }
@@ -391,3 +497,68 @@ impl PipelineStorage {
         }
     }
 }
+
+type SpecializationData = (
+    SmallVec<[pso::SpecializationConstant; 16]>,
+    SmallVec<[u8; 128]>,
+);
+
+/// Pack the specialization constants of a shader, defines were already applied to the module.
+fn specialization_data(specialization: &[Specialization]) -> SpecializationData {
+    let mut constants = SmallVec::new();
+    let mut data = SmallVec::new();
+
+    for spec in specialization.iter().filter(|spec| spec.define.is_none()) {
+        let start = data.len();
+        let end = start + spec.value.len();
+
+        let constant = pso::SpecializationConstant {
+            id: spec.id,
+            range: (start as u16)..(end as u16),
+        };
+
+        constants.push(constant);
+        data.extend_from_slice(&spec.value);
+    }
+
+    (constants, data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn storage() -> PipelineStorage {
+        PipelineStorage {
+            storage: Storage::new(),
+            graphic_pipelines: BTreeMap::new(),
+            compute_pipelines: BTreeMap::new(),
+            graphics_lookup: HashMap::new(),
+            compute_lookup: HashMap::new(),
+            references: BTreeMap::new(),
+            cache: None,
+        }
+    }
+
+    #[test]
+    fn shared_pipeline_references() {
+        let mut pipelines = storage();
+
+        let handle = pipelines.storage.insert(Pipeline::Compute);
+        pipelines.references.insert(handle.id(), 1);
+
+        // another pass uses the same pipeline
+        assert_eq!(pipelines.add_reference(handle), Some(handle));
+
+        // one user remains, so the pipeline is kept.
+        assert!(!pipelines.release_reference(handle));
+        assert!(pipelines.storage.is_alive(handle));
+
+        assert!(pipelines.release_reference(handle));
+        assert!(!pipelines.storage.is_alive(handle));
+
+        // a destroyed pipeline can't be released or acquired again.
+        assert!(!pipelines.release_reference(handle));
+        assert_eq!(pipelines.add_reference(handle), None);
+    }
+}
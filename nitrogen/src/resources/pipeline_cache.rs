@@ -0,0 +1,338 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Persistence of the pipeline cache.
+//!
+//! Creating pipelines involves compiling shader programs for the GPU, which can take a long time
+//! for bigger graphs. The driver can keep the results of those compilations in a cache, which can
+//! be saved with [`Context::pipeline_cache_save`] and loaded on the next start with
+//! [`Context::pipeline_cache_load`].
+//!
+//! The saved data starts with a header that identifies the adapter and driver the data was created
+//! with, as well as a checksum of the cache content. The driver is identified by the
+//! `pipelineCacheUUID` it reports in its own cache data, which changes whenever the driver can't
+//! use previously created data anymore. Data that was created for a different adapter or driver or
+//! which got corrupted is rejected before it reaches the driver.
+//!
+//! [`Context::pipeline_cache_save`]: ../struct.Context.html#method.pipeline_cache_save
+//! [`Context::pipeline_cache_load`]: ../struct.Context.html#method.pipeline_cache_load
+
+use crate::device::DeviceContext;
+
+const MAGIC: [u8; 4] = *b"NPCC";
+
+/// Version of the header layout.
+const FORMAT_VERSION: u32 = 2;
+
+/// `VK_PIPELINE_CACHE_HEADER_VERSION_ONE`
+const DRIVER_HEADER_VERSION: u32 = 1;
+
+/// Size of the header at the start of the driver data.
+const DRIVER_HEADER_SIZE: u32 = 32;
+
+/// Errors that can occur when loading or saving pipeline cache data.
+#[derive(Clone, Debug, Display)]
+pub enum PipelineCacheError {
+    /// The data is not pipeline cache data or it is corrupted.
+    #[display(fmt = "Invalid pipeline cache data")]
+    InvalidData,
+
+    /// The data was saved in an unsupported format version.
+    #[display(fmt = "Unsupported pipeline cache format version {}", _0)]
+    UnsupportedVersion(u32),
+
+    /// The data was created for a different adapter or driver.
+    #[display(fmt = "Pipeline cache data was created for a different adapter or driver")]
+    AdapterMismatch,
+
+    /// The driver does not identify the pipeline cache data it creates.
+    #[display(fmt = "Driver pipeline cache data has an unknown header")]
+    UnknownDriverHeader,
+
+    /// No pipeline cache is in use.
+    #[display(fmt = "No pipeline cache is available")]
+    Unavailable,
+
+    /// The cache could not be created or read.
+    #[display(fmt = "Ran out of memory: {}", _0)]
+    OutOfMemory(gfx::device::OutOfMemory),
+}
+
+impl std::error::Error for PipelineCacheError {}
+
+impl From<gfx::device::OutOfMemory> for PipelineCacheError {
+    fn from(err: gfx::device::OutOfMemory) -> Self {
+        PipelineCacheError::OutOfMemory(err)
+    }
+}
+
+/// Identity of the adapter and driver pipeline cache data belongs to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct CacheIdentity {
+    pub(crate) vendor: u32,
+    pub(crate) device: u32,
+    pub(crate) adapter_name: String,
+    /// `pipelineCacheUUID` of the driver.
+    pub(crate) driver_uuid: [u8; 16],
+}
+
+impl CacheIdentity {
+    /// Identity of the driver currently used by the device.
+    ///
+    /// The driver only reports its identity in the header of pipeline cache data, so an empty
+    /// cache is created to read it.
+    pub(crate) unsafe fn of(device: &DeviceContext) -> Result<Self, PipelineCacheError> {
+        let cache = device.device.create_pipeline_cache(None)?;
+        let data = device.device.get_pipeline_cache_data(&cache);
+        device.device.destroy_pipeline_cache(cache);
+
+        Self::from_driver_data(&device.adapter.info.name, &data?)
+    }
+
+    /// Read the identity from the header the driver puts in front of pipeline cache data.
+    pub(crate) fn from_driver_data(
+        adapter_name: &str,
+        data: &[u8],
+    ) -> Result<Self, PipelineCacheError> {
+        let mut reader = Reader { bytes: data };
+
+        let header_size = reader.u32();
+        let header_version = reader.u32();
+
+        if header_size.map_or(true, |size| size < DRIVER_HEADER_SIZE)
+            || header_version != Some(DRIVER_HEADER_VERSION)
+        {
+            return Err(PipelineCacheError::UnknownDriverHeader);
+        }
+
+        let (vendor, device) = match (reader.u32(), reader.u32()) {
+            (Some(vendor), Some(device)) => (vendor, device),
+            _ => return Err(PipelineCacheError::UnknownDriverHeader),
+        };
+
+        let mut driver_uuid = [0; 16];
+        driver_uuid.copy_from_slice(
+            reader
+                .take(16)
+                .ok_or(PipelineCacheError::UnknownDriverHeader)?,
+        );
+
+        Ok(CacheIdentity {
+            vendor,
+            device,
+            adapter_name: adapter_name.to_string(),
+            driver_uuid,
+        })
+    }
+
+    fn write(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.vendor.to_le_bytes());
+        out.extend_from_slice(&self.device.to_le_bytes());
+        write_bytes(out, self.adapter_name.as_bytes());
+        out.extend_from_slice(&self.driver_uuid);
+    }
+
+    fn read(reader: &mut Reader<'_>) -> Option<Self> {
+        let vendor = reader.u32()?;
+        let device = reader.u32()?;
+        let adapter_name = String::from_utf8(reader.bytes()?.to_vec()).ok()?;
+
+        let mut driver_uuid = [0; 16];
+        driver_uuid.copy_from_slice(reader.take(16)?);
+
+        Some(CacheIdentity {
+            vendor,
+            device,
+            adapter_name,
+            driver_uuid,
+        })
+    }
+}
+
+/// Wrap the driver data of a pipeline cache in a header.
+pub(crate) fn encode(identity: &CacheIdentity, data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() + 64);
+
+    out.extend_from_slice(&MAGIC);
+    out.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    identity.write(&mut out);
+    out.extend_from_slice(&checksum(data).to_le_bytes());
+    write_bytes(&mut out, data);
+
+    out
+}
+
+/// Check the header of saved pipeline cache data and extract the driver data.
+pub(crate) fn decode<'a>(
+    identity: &CacheIdentity,
+    bytes: &'a [u8],
+) -> Result<&'a [u8], PipelineCacheError> {
+    let mut reader = Reader { bytes };
+
+    if reader.take(MAGIC.len()) != Some(&MAGIC[..]) {
+        return Err(PipelineCacheError::InvalidData);
+    }
+
+    let version = reader.u32().ok_or(PipelineCacheError::InvalidData)?;
+    if version != FORMAT_VERSION {
+        return Err(PipelineCacheError::UnsupportedVersion(version));
+    }
+
+    let saved_identity = CacheIdentity::read(&mut reader).ok_or(PipelineCacheError::InvalidData)?;
+    if saved_identity != *identity {
+        return Err(PipelineCacheError::AdapterMismatch);
+    }
+
+    let sum = reader.u64().ok_or(PipelineCacheError::InvalidData)?;
+    let data = reader.bytes().ok_or(PipelineCacheError::InvalidData)?;
+
+    if !reader.bytes.is_empty() || checksum(data) != sum {
+        return Err(PipelineCacheError::InvalidData);
+    }
+
+    Ok(data)
+}
+
+fn write_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    out.extend_from_slice(&(bytes.len() as u64).to_le_bytes());
+    out.extend_from_slice(bytes);
+}
+
+/// 64 bit FNV-1a hash
+fn checksum(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.bytes.len() < len {
+            return None;
+        }
+
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+
+        Some(taken)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        let mut buf = [0; 4];
+        buf.copy_from_slice(self.take(4)?);
+        Some(u32::from_le_bytes(buf))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        let mut buf = [0; 8];
+        buf.copy_from_slice(self.take(8)?);
+        Some(u64::from_le_bytes(buf))
+    }
+
+    fn bytes(&mut self) -> Option<&'a [u8]> {
+        let len = self.u64()?;
+
+        if len > self.bytes.len() as u64 {
+            return None;
+        }
+
+        self.take(len as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Driver data consisting of only a Vulkan pipeline cache header.
+    fn driver_data(uuid: u8) -> Vec<u8> {
+        let mut data = vec![];
+        data.extend_from_slice(&DRIVER_HEADER_SIZE.to_le_bytes());
+        data.extend_from_slice(&DRIVER_HEADER_VERSION.to_le_bytes());
+        data.extend_from_slice(&0x10deu32.to_le_bytes());
+        data.extend_from_slice(&0x1b80u32.to_le_bytes());
+        data.extend_from_slice(&[uuid; 16]);
+        data
+    }
+
+    fn identity() -> CacheIdentity {
+        CacheIdentity::from_driver_data("Test adapter", &driver_data(1)).unwrap()
+    }
+
+    #[test]
+    fn driver_identity() {
+        let identity = identity();
+
+        assert_eq!(identity.vendor, 0x10de);
+        assert_eq!(identity.device, 0x1b80);
+        assert_eq!(identity.driver_uuid, [1; 16]);
+
+        let data = driver_data(1);
+
+        match CacheIdentity::from_driver_data("Test adapter", &data[..20]) {
+            Err(PipelineCacheError::UnknownDriverHeader) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        match CacheIdentity::from_driver_data("Test adapter", &[]) {
+            Err(PipelineCacheError::UnknownDriverHeader) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+
+    #[test]
+    fn roundtrip() {
+        let data = [1, 2, 3, 4, 5];
+
+        let encoded = encode(&identity(), &data);
+
+        assert_eq!(decode(&identity(), &encoded).unwrap(), &data[..]);
+    }
+
+    #[test]
+    fn reject_invalid_data() {
+        let encoded = encode(&identity(), &[1, 2, 3, 4, 5]);
+
+        match decode(&identity(), &encoded[..encoded.len() - 1]) {
+            Err(PipelineCacheError::InvalidData) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        let mut corrupted = encoded.clone();
+        *corrupted.last_mut().unwrap() ^= 0xff;
+
+        match decode(&identity(), &corrupted) {
+            Err(PipelineCacheError::InvalidData) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        let other = CacheIdentity {
+            device: 0x1b81,
+            ..identity()
+        };
+
+        match decode(&other, &encoded) {
+            Err(PipelineCacheError::AdapterMismatch) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        // same adapter, but an updated driver
+        let updated_driver =
+            CacheIdentity::from_driver_data("Test adapter", &driver_data(2)).unwrap();
+
+        match decode(&updated_driver, &encoded) {
+            Err(PipelineCacheError::AdapterMismatch) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        match decode(&identity(), b"not a cache") {
+            Err(PipelineCacheError::InvalidData) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+}
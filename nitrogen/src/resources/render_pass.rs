@@ -5,6 +5,7 @@
 use crate::storage::{Handle, Storage};
 
 use gfx::Device;
+use smallvec::SmallVec;
 
 use crate::device::DeviceContext;
 use crate::submit_group::ResourceList;
@@ -17,6 +18,47 @@ pub enum RenderPassError {
 
 pub struct RenderPass {
     render_pass: crate::types::RenderPass,
+    compat: RenderPassCompat,
+}
+
+/// The properties of a render pass that decide if pipelines created for it can be used with
+/// another render pass.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct RenderPassCompat {
+    attachments: SmallVec<[(Option<gfx::format::Format>, gfx::image::NumSamples); 8]>,
+    subpasses: SmallVec<[SubpassCompat; 1]>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct SubpassCompat {
+    colors: SmallVec<[gfx::pass::AttachmentId; 8]>,
+    depth_stencil: Option<gfx::pass::AttachmentId>,
+    inputs: SmallVec<[gfx::pass::AttachmentId; 8]>,
+    resolves: SmallVec<[gfx::pass::AttachmentId; 8]>,
+}
+
+impl RenderPassCompat {
+    fn new(create_info: &RenderPassCreateInfo) -> Self {
+        let ids = |refs: &[gfx::pass::AttachmentRef]| refs.iter().map(|(id, _)| *id).collect();
+
+        RenderPassCompat {
+            attachments: create_info
+                .attachments
+                .iter()
+                .map(|attachment| (attachment.format, attachment.samples))
+                .collect(),
+            subpasses: create_info
+                .subpasses
+                .iter()
+                .map(|subpass| SubpassCompat {
+                    colors: ids(subpass.colors),
+                    depth_stencil: subpass.depth_stencil.map(|(id, _)| *id),
+                    inputs: ids(subpass.inputs),
+                    resolves: ids(subpass.resolves),
+                })
+                .collect(),
+        }
+    }
 }
 
 pub type RenderPassHandle = Handle<RenderPass>;
@@ -43,6 +85,8 @@ impl RenderPassStorage {
         device: &DeviceContext,
         create_info: RenderPassCreateInfo,
     ) -> Result<RenderPassHandle, RenderPassError> {
+        let compat = RenderPassCompat::new(&create_info);
+
         let pass = device.device.create_render_pass(
             create_info.attachments,
             create_info.subpasses,
@@ -51,7 +95,10 @@ impl RenderPassStorage {
 
         match pass {
             Ok(render_pass) => {
-                let handle = self.storage.insert(RenderPass { render_pass });
+                let handle = self.storage.insert(RenderPass {
+                    render_pass,
+                    compat,
+                });
 
                 Ok(handle)
             }
@@ -67,6 +114,10 @@ impl RenderPassStorage {
         }
     }
 
+    pub(crate) fn compat(&self, handle: RenderPassHandle) -> Option<&RenderPassCompat> {
+        self.storage.get(handle).map(|pass| &pass.compat)
+    }
+
     pub(crate) fn destroy<P>(&mut self, res_list: &mut ResourceList, handles: P)
     where
         P: IntoIterator,
@@ -82,3 +133,98 @@ impl RenderPassStorage {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gfx::format::Format;
+    use gfx::image::Layout;
+    use gfx::pass::{Attachment, AttachmentLoadOp, AttachmentOps, AttachmentStoreOp};
+
+    fn attachment(format: Format, ops: AttachmentOps, layout: Layout) -> Attachment {
+        Attachment {
+            format: Some(format),
+            samples: 1,
+            ops,
+            stencil_ops: AttachmentOps::DONT_CARE,
+            layouts: Layout::General..layout,
+        }
+    }
+
+    fn compat(attachments: &[Attachment], depth: bool) -> RenderPassCompat {
+        let colors = [(0, Layout::ColorAttachmentOptimal)];
+        let depth_stencil = (1, Layout::DepthStencilAttachmentOptimal);
+
+        let subpass = gfx::pass::SubpassDesc {
+            colors: &colors,
+            depth_stencil: if depth { Some(&depth_stencil) } else { None },
+            inputs: &[],
+            resolves: &[],
+            preserves: &[],
+        };
+
+        RenderPassCompat::new(&RenderPassCreateInfo {
+            attachments,
+            subpasses: &[subpass],
+            dependencies: &[],
+        })
+    }
+
+    #[test]
+    fn render_pass_compatibility() {
+        let load = AttachmentOps {
+            load: AttachmentLoadOp::Load,
+            store: AttachmentStoreOp::Store,
+        };
+        let clear = AttachmentOps {
+            load: AttachmentLoadOp::Clear,
+            store: AttachmentStoreOp::DontCare,
+        };
+
+        let base = compat(
+            &[
+                attachment(Format::Rgba8Unorm, load, Layout::General),
+                attachment(Format::D32Sfloat, load, Layout::General),
+            ],
+            true,
+        );
+
+        // load/store operations and layouts don't affect compatibility
+        let other_ops = compat(
+            &[
+                attachment(Format::Rgba8Unorm, clear, Layout::ShaderReadOnlyOptimal),
+                attachment(Format::D32Sfloat, clear, Layout::General),
+            ],
+            true,
+        );
+        assert_eq!(base, other_ops);
+
+        // formats do
+        let other_format = compat(
+            &[
+                attachment(Format::Rgba16Sfloat, load, Layout::General),
+                attachment(Format::D32Sfloat, load, Layout::General),
+            ],
+            true,
+        );
+        assert_ne!(base, other_format);
+
+        // and so do the attachments used by the subpass
+        let no_depth = compat(
+            &[
+                attachment(Format::Rgba8Unorm, load, Layout::General),
+                attachment(Format::D32Sfloat, load, Layout::General),
+            ],
+            false,
+        );
+        assert_ne!(base, no_depth);
+
+        // as well as the sample count
+        let mut multisampled = [
+            attachment(Format::Rgba8Unorm, load, Layout::General),
+            attachment(Format::D32Sfloat, load, Layout::General),
+        ];
+        multisampled[0].samples = 4;
+        assert_ne!(base, compat(&multisampled, true));
+    }
+}
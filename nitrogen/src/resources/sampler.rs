@@ -14,6 +14,8 @@ use crate::util::storage::Storage;
 
 use crate::submit_group::ResourceList;
 
+use std::collections::{HashMap, HashSet};
+
 /// Samplers are used to determine how texture lookups should be performed.
 ///
 /// The most common use for samplers is to enable "linear filtering" to "unpixelate" images, or
@@ -103,12 +105,19 @@ pub type SamplerHandle = storage::Handle<Sampler>;
 
 pub(crate) struct SamplerStorage {
     pub storage: Storage<Sampler>,
+
+    /// Number of materials using a sampler as an immutable sampler.
+    material_uses: HashMap<SamplerHandle, usize>,
+    /// Samplers which were destroyed while materials still use them.
+    retired: HashSet<SamplerHandle>,
 }
 
 impl SamplerStorage {
     pub(crate) fn new() -> Self {
         Self {
             storage: Storage::new(),
+            material_uses: HashMap::new(),
+            retired: HashSet::new(),
         }
     }
 
@@ -136,7 +145,7 @@ impl SamplerStorage {
     }
 
     pub(crate) fn raw(&self, sampler: SamplerHandle) -> Option<&Sampler> {
-        if self.storage.is_alive(sampler) {
+        if self.storage.is_alive(sampler) && !self.retired.contains(&sampler) {
             Some(&self.storage[sampler])
         } else {
             None
@@ -151,9 +160,97 @@ impl SamplerStorage {
         for handle in handles.into_iter() {
             let handle = *handle.borrow();
 
+            if !self.storage.is_alive(handle) || !self.retire(handle) {
+                continue;
+            }
+
             if let Some(sampler) = self.storage.remove(handle) {
                 res_list.queue_sampler(sampler.0);
             }
         }
     }
+
+    /// Keep the sampler alive until the material using it is destroyed.
+    pub(crate) fn add_material_use(&mut self, sampler: SamplerHandle) {
+        *self.material_uses.entry(sampler).or_insert(0) += 1;
+    }
+
+    /// Release the samplers used by a destroyed material.
+    ///
+    /// Samplers which were destroyed before are freed once no material uses them anymore.
+    pub(crate) unsafe fn release_material_uses(
+        &mut self,
+        device: &DeviceContext,
+        samplers: &[SamplerHandle],
+    ) {
+        for handle in samplers {
+            if !self.release_material_use(*handle) {
+                continue;
+            }
+
+            if let Some(sampler) = self.storage.remove(*handle) {
+                device.device.destroy_sampler(sampler.0);
+            }
+        }
+    }
+
+    /// Mark a sampler as destroyed, returns `true` if it can be freed right away.
+    fn retire(&mut self, sampler: SamplerHandle) -> bool {
+        if self.material_uses.contains_key(&sampler) {
+            self.retired.insert(sampler);
+            false
+        } else {
+            true
+        }
+    }
+
+    /// Remove one material use of a sampler, returns `true` if the sampler was destroyed and
+    /// is not used anymore.
+    fn release_material_use(&mut self, sampler: SamplerHandle) -> bool {
+        let uses = match self.material_uses.get_mut(&sampler) {
+            Some(uses) => uses,
+            None => return false,
+        };
+
+        *uses -= 1;
+
+        if *uses > 0 {
+            return false;
+        }
+
+        self.material_uses.remove(&sampler);
+        self.retired.remove(&sampler)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn material_uses() {
+        let mut samplers = SamplerStorage::new();
+
+        let used = storage::Handle::new(0, 0);
+        let unused = storage::Handle::new(1, 0);
+
+        // two materials use the sampler
+        samplers.add_material_use(used);
+        samplers.add_material_use(used);
+
+        assert!(samplers.retire(unused));
+
+        // destroying a used sampler, even twice, has to wait for the materials
+        assert!(!samplers.retire(used));
+        assert!(!samplers.retire(used));
+
+        assert!(!samplers.release_material_use(used));
+        assert!(samplers.release_material_use(used));
+        assert!(!samplers.release_material_use(used));
+
+        // samplers which are still alive are not freed with the material
+        samplers.add_material_use(unused);
+        assert!(!samplers.release_material_use(unused));
+        assert!(samplers.retire(unused));
+    }
 }
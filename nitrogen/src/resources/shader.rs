@@ -4,10 +4,28 @@
 
 //! Storage for shader programs.
 
+use crate::device::DeviceContext;
+use crate::graph::Specialization;
+use crate::resources::shader_variant::ShaderVariantError;
+use crate::resources::spirv::{self, ReflectError};
+use crate::types;
 use crate::util::storage::{Handle, Storage};
 use crate::util::CowString;
 use std::marker::PhantomData;
 
+#[cfg(feature = "shaderc")]
+use crate::resources::shader_compiler::{
+    IncludeResolver, ShaderCompileError, ShaderCompiler, ShaderKind, ShaderSource, SourceLanguage,
+};
+#[cfg(feature = "shaderc")]
+use std::collections::HashMap;
+#[cfg(feature = "shaderc")]
+use std::rc::Rc;
+
+use bitflags::bitflags;
+use derive_more::Display;
+use gfx::Device;
+
 /// Name of the "entry point" of a shader program.
 ///
 /// The entry point is the name of the function which will be invoked during
@@ -30,6 +48,15 @@ pub struct Shader<T> {
     pub(crate) spirv_content: Vec<u8>,
     pub(crate) entry_point: EntryPoint,
 
+    /// Incremented every time the content of the shader is replaced.
+    pub(crate) version: u64,
+
+    /// Shader module created from the content, kept alive for pipeline creations.
+    pub(crate) module: Option<types::ShaderModule>,
+
+    /// Reflected interface of the entry point, used to validate pipelines.
+    pub(crate) interface: spirv::EntryPoint,
+
     pub(crate) _marker: PhantomData<T>,
 }
 
@@ -41,6 +68,71 @@ pub struct Vertex;
 pub struct Fragment;
 /// Type denoting a geometry shader program.
 pub struct Geometry;
+/// Type denoting a tessellation control (hull) shader program.
+pub struct TessControl;
+/// Type denoting a tessellation evaluation (domain) shader program.
+pub struct TessEvaluation;
+
+/// Shader types and the stage their programs are executed in.
+pub(crate) trait ShaderType {
+    const STAGE: ShaderStages;
+}
+
+impl ShaderType for Compute {
+    const STAGE: ShaderStages = ShaderStages::COMPUTE;
+}
+
+impl ShaderType for Vertex {
+    const STAGE: ShaderStages = ShaderStages::VERTEX;
+}
+
+impl ShaderType for Fragment {
+    const STAGE: ShaderStages = ShaderStages::FRAGMENT;
+}
+
+impl ShaderType for Geometry {
+    const STAGE: ShaderStages = ShaderStages::GEOMETRY;
+}
+
+impl ShaderType for TessControl {
+    const STAGE: ShaderStages = ShaderStages::TESS_CONTROL;
+}
+
+impl ShaderType for TessEvaluation {
+    const STAGE: ShaderStages = ShaderStages::TESS_EVALUATION;
+}
+
+bitflags!(
+    /// Set of shader stages.
+    pub struct ShaderStages: u32 {
+        /// Vertex shader stage.
+        const VERTEX = 0x1;
+        /// Tessellation control (hull) shader stage.
+        const TESS_CONTROL = 0x2;
+        /// Tessellation evaluation (domain) shader stage.
+        const TESS_EVALUATION = 0x4;
+        /// Geometry shader stage.
+        const GEOMETRY = 0x8;
+        /// Fragment shader stage.
+        const FRAGMENT = 0x10;
+        /// Compute shader stage.
+        const COMPUTE = 0x20;
+        /// All stages used in graphics pipelines.
+        const GRAPHICS = Self::VERTEX.bits
+            | Self::TESS_CONTROL.bits
+            | Self::TESS_EVALUATION.bits
+            | Self::GEOMETRY.bits
+            | Self::FRAGMENT.bits;
+        /// All stages.
+        const ALL = Self::GRAPHICS.bits | Self::COMPUTE.bits;
+    }
+);
+
+impl From<ShaderStages> for gfx::pso::ShaderStageFlags {
+    fn from(stages: ShaderStages) -> Self {
+        gfx::pso::ShaderStageFlags::from_bits_truncate(stages.bits())
+    }
+}
 
 /// Opaque handle to a compute shader program resource.
 pub type ComputeShaderHandle = Handle<Shader<Compute>>;
@@ -50,12 +142,106 @@ pub type VertexShaderHandle = Handle<Shader<Vertex>>;
 pub type FragmentShaderHandle = Handle<Shader<Fragment>>;
 /// Opaque handle to a geometry shader program resource.
 pub type GeometryShaderHandle = Handle<Shader<Geometry>>;
+/// Opaque handle to a tessellation control shader program resource.
+pub type TessControlShaderHandle = Handle<Shader<TessControl>>;
+/// Opaque handle to a tessellation evaluation shader program resource.
+pub type TessEvaluationShaderHandle = Handle<Shader<TessEvaluation>>;
+
+/// Handle to a shader program resource of any type.
+#[allow(missing_docs)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ShaderHandle {
+    Compute(ComputeShaderHandle),
+    Vertex(VertexShaderHandle),
+    Fragment(FragmentShaderHandle),
+    Geometry(GeometryShaderHandle),
+    TessControl(TessControlShaderHandle),
+    TessEvaluation(TessEvaluationShaderHandle),
+}
+
+impl From<ComputeShaderHandle> for ShaderHandle {
+    fn from(handle: ComputeShaderHandle) -> Self {
+        ShaderHandle::Compute(handle)
+    }
+}
+
+impl From<VertexShaderHandle> for ShaderHandle {
+    fn from(handle: VertexShaderHandle) -> Self {
+        ShaderHandle::Vertex(handle)
+    }
+}
+
+impl From<FragmentShaderHandle> for ShaderHandle {
+    fn from(handle: FragmentShaderHandle) -> Self {
+        ShaderHandle::Fragment(handle)
+    }
+}
+
+impl From<GeometryShaderHandle> for ShaderHandle {
+    fn from(handle: GeometryShaderHandle) -> Self {
+        ShaderHandle::Geometry(handle)
+    }
+}
+
+impl From<TessControlShaderHandle> for ShaderHandle {
+    fn from(handle: TessControlShaderHandle) -> Self {
+        ShaderHandle::TessControl(handle)
+    }
+}
+
+impl From<TessEvaluationShaderHandle> for ShaderHandle {
+    fn from(handle: TessEvaluationShaderHandle) -> Self {
+        ShaderHandle::TessEvaluation(handle)
+    }
+}
+
+/// Parts of a shader of any type.
+pub(crate) struct RawShader<'a> {
+    pub(crate) entry_point: &'a EntryPoint,
+    pub(crate) module: Option<&'a types::ShaderModule>,
+    pub(crate) interface: &'a spirv::EntryPoint,
+}
+
+impl<'a, T> From<&'a Shader<T>> for RawShader<'a> {
+    fn from(shader: &'a Shader<T>) -> Self {
+        RawShader {
+            entry_point: &shader.entry_point,
+            module: shader.module.as_ref(),
+            interface: &shader.interface,
+        }
+    }
+}
+
+/// Source code of a shader, kept to compile variants with additional defines.
+#[cfg(feature = "shaderc")]
+struct SourceInfo {
+    kind: ShaderKind,
+    name: String,
+    language: SourceLanguage,
+    code: String,
+    entry: String,
+    defines: Vec<(String, Option<String>)>,
+    include_resolver: Option<Rc<dyn IncludeResolver>>,
+
+    /// compiled variants, keyed by the sorted names of the additional defines.
+    variants: HashMap<Vec<CowString>, ShaderHandle>,
+}
 
 pub(crate) struct ShaderStorage {
     pub(crate) compute_storage: Storage<Shader<Compute>>,
     pub(crate) vertex_storage: Storage<Shader<Vertex>>,
     pub(crate) fragment_storage: Storage<Shader<Fragment>>,
     pub(crate) geometry_storage: Storage<Shader<Geometry>>,
+    pub(crate) tess_control_storage: Storage<Shader<TessControl>>,
+    pub(crate) tess_evaluation_storage: Storage<Shader<TessEvaluation>>,
+
+    /// modules of destroyed or replaced shaders, destroyed once a device is available.
+    retired_modules: Vec<types::ShaderModule>,
+
+    #[cfg(feature = "shaderc")]
+    compiler: ShaderCompiler,
+    #[cfg(feature = "shaderc")]
+    sources: HashMap<ShaderHandle, SourceInfo>,
 }
 
 impl ShaderStorage {
@@ -65,87 +251,829 @@ impl ShaderStorage {
             vertex_storage: Storage::new(),
             fragment_storage: Storage::new(),
             geometry_storage: Storage::new(),
+            tess_control_storage: Storage::new(),
+            tess_evaluation_storage: Storage::new(),
+
+            retired_modules: vec![],
+
+            #[cfg(feature = "shaderc")]
+            compiler: ShaderCompiler::new(),
+            #[cfg(feature = "shaderc")]
+            sources: HashMap::new(),
+        }
+    }
+
+    pub(crate) unsafe fn release(self, device: &DeviceContext) {
+        fn modules<T>(storage: Storage<Shader<T>>) -> impl Iterator<Item = types::ShaderModule> {
+            storage.into_iter().filter_map(|(_, shader)| shader.module)
+        }
+
+        let modules = modules(self.compute_storage)
+            .chain(modules(self.vertex_storage))
+            .chain(modules(self.fragment_storage))
+            .chain(modules(self.geometry_storage))
+            .chain(modules(self.tess_control_storage))
+            .chain(modules(self.tess_evaluation_storage))
+            .chain(self.retired_modules);
+
+        for module in modules {
+            device.device.destroy_shader_module(module);
         }
     }
 
-    fn create_shader<T>(
+    fn create_shader<T: ShaderType>(
         storage: &mut Storage<Shader<T>>,
         info: ShaderInfo<'_>,
-    ) -> Handle<Shader<T>> {
+    ) -> Result<Handle<Shader<T>>, ShaderError> {
+        let interface = validate_module(&info, T::STAGE)?;
+
         let shader = Shader {
             spirv_content: info.spirv_content.to_owned(),
             entry_point: info.entry_point.clone(),
 
+            version: 0,
+
+            module: None,
+
+            interface,
+
             _marker: PhantomData,
         };
 
-        storage.insert(shader)
+        Ok(storage.insert(shader))
+    }
+
+    fn replace_shader<T: ShaderType>(
+        storage: &mut Storage<Shader<T>>,
+        retired: &mut Vec<types::ShaderModule>,
+        handle: Handle<Shader<T>>,
+        info: ShaderInfo<'_>,
+    ) -> Result<(), ShaderError> {
+        let shader = storage.get_mut(handle).ok_or(ShaderError::InvalidHandle)?;
+
+        let interface = validate_module(&info, T::STAGE)?;
+
+        shader.spirv_content = info.spirv_content.to_owned();
+        shader.entry_point = info.entry_point.clone();
+        shader.interface = interface;
+        shader.version += 1;
+
+        retired.extend(shader.module.take());
+
+        Ok(())
+    }
+
+    /// Replace the content of a shader of any type.
+    ///
+    /// The content is validated first, an invalid module leaves the shader untouched. Variants
+    /// compiled from the source of the shader are destroyed, as the source no longer matches the
+    /// content of the shader.
+    pub(crate) fn replace(
+        &mut self,
+        handle: ShaderHandle,
+        info: ShaderInfo<'_>,
+    ) -> Result<(), ShaderError> {
+        self.replace_content(handle, info)?;
+
+        #[cfg(feature = "shaderc")]
+        self.destroy_variants(handle);
+
+        Ok(())
     }
 
-    fn destroy_shader<T>(storage: &mut Storage<Shader<T>>, handle: Handle<Shader<T>>) {
+    fn replace_content(
+        &mut self,
+        handle: ShaderHandle,
+        info: ShaderInfo<'_>,
+    ) -> Result<(), ShaderError> {
+        match handle {
+            ShaderHandle::Compute(h) => Self::replace_shader(
+                &mut self.compute_storage,
+                &mut self.retired_modules,
+                h,
+                info,
+            ),
+            ShaderHandle::Vertex(h) => {
+                Self::replace_shader(&mut self.vertex_storage, &mut self.retired_modules, h, info)
+            }
+            ShaderHandle::Fragment(h) => Self::replace_shader(
+                &mut self.fragment_storage,
+                &mut self.retired_modules,
+                h,
+                info,
+            ),
+            ShaderHandle::Geometry(h) => Self::replace_shader(
+                &mut self.geometry_storage,
+                &mut self.retired_modules,
+                h,
+                info,
+            ),
+            ShaderHandle::TessControl(h) => Self::replace_shader(
+                &mut self.tess_control_storage,
+                &mut self.retired_modules,
+                h,
+                info,
+            ),
+            ShaderHandle::TessEvaluation(h) => Self::replace_shader(
+                &mut self.tess_evaluation_storage,
+                &mut self.retired_modules,
+                h,
+                info,
+            ),
+        }
+    }
+
+    /// Retrieve the current version of a shader, `None` if the handle is invalid.
+    pub(crate) fn version(&self, handle: ShaderHandle) -> Option<u64> {
+        match handle {
+            ShaderHandle::Compute(h) => self.compute_storage.get(h).map(|s| s.version),
+            ShaderHandle::Vertex(h) => self.vertex_storage.get(h).map(|s| s.version),
+            ShaderHandle::Fragment(h) => self.fragment_storage.get(h).map(|s| s.version),
+            ShaderHandle::Geometry(h) => self.geometry_storage.get(h).map(|s| s.version),
+            ShaderHandle::TessControl(h) => self.tess_control_storage.get(h).map(|s| s.version),
+            ShaderHandle::TessEvaluation(h) => {
+                self.tess_evaluation_storage.get(h).map(|s| s.version)
+            }
+        }
+    }
+
+    fn destroy_shader<T>(
+        storage: &mut Storage<Shader<T>>,
+        retired: &mut Vec<types::ShaderModule>,
+        handle: Handle<Shader<T>>,
+    ) {
         if let Some(shader) = storage.remove(handle) {
-            // TODO some de-initialization behavior?
-            drop(shader);
+            retired.extend(shader.module);
         }
     }
 
-    fn raw<T>(storage: &Storage<Shader<T>>, handle: Handle<Shader<T>>) -> Option<&Shader<T>> {
-        storage.get(handle)
+    unsafe fn prepare_shader_module<T>(
+        device: &DeviceContext,
+        storage: &mut Storage<Shader<T>>,
+        handle: Handle<Shader<T>>,
+    ) -> Result<(), gfx::device::ShaderError> {
+        if let Some(shader) = storage.get_mut(handle) {
+            if shader.module.is_none() {
+                let module = device.device.create_shader_module(&shader.spirv_content)?;
+                shader.module = Some(module);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Create the shader module of a shader unless it exists already.
+    ///
+    /// Modules stay alive until the shader is destroyed or replaced, so pipelines using the
+    /// same shader don't need to create them again.
+    pub(crate) unsafe fn prepare_module(
+        &mut self,
+        device: &DeviceContext,
+        handle: ShaderHandle,
+    ) -> Result<(), gfx::device::ShaderError> {
+        for module in self.retired_modules.drain(..) {
+            device.device.destroy_shader_module(module);
+        }
+
+        match handle {
+            ShaderHandle::Compute(h) => {
+                Self::prepare_shader_module(device, &mut self.compute_storage, h)
+            }
+            ShaderHandle::Vertex(h) => {
+                Self::prepare_shader_module(device, &mut self.vertex_storage, h)
+            }
+            ShaderHandle::Fragment(h) => {
+                Self::prepare_shader_module(device, &mut self.fragment_storage, h)
+            }
+            ShaderHandle::Geometry(h) => {
+                Self::prepare_shader_module(device, &mut self.geometry_storage, h)
+            }
+            ShaderHandle::TessControl(h) => {
+                Self::prepare_shader_module(device, &mut self.tess_control_storage, h)
+            }
+            ShaderHandle::TessEvaluation(h) => {
+                Self::prepare_shader_module(device, &mut self.tess_evaluation_storage, h)
+            }
+        }
+    }
+
+    /// Retrieve the parts of a shader of any type.
+    pub(crate) fn raw_any(&self, handle: ShaderHandle) -> Option<RawShader<'_>> {
+        match handle {
+            ShaderHandle::Compute(h) => self.compute_storage.get(h).map(RawShader::from),
+            ShaderHandle::Vertex(h) => self.vertex_storage.get(h).map(RawShader::from),
+            ShaderHandle::Fragment(h) => self.fragment_storage.get(h).map(RawShader::from),
+            ShaderHandle::Geometry(h) => self.geometry_storage.get(h).map(RawShader::from),
+            ShaderHandle::TessControl(h) => self.tess_control_storage.get(h).map(RawShader::from),
+            ShaderHandle::TessEvaluation(h) => {
+                self.tess_evaluation_storage.get(h).map(RawShader::from)
+            }
+        }
+    }
+
+    /// Find the shader to use for the defines selected in `specialization`.
+    ///
+    /// Without defines that is the shader itself, otherwise the variant gets compiled from the
+    /// source of the shader the first time it is requested.
+    pub(crate) fn resolve_variant(
+        &mut self,
+        handle: ShaderHandle,
+        specialization: &[Specialization],
+    ) -> Result<ShaderHandle, ShaderVariantError> {
+        let mut defines = specialization
+            .iter()
+            .filter_map(|spec| spec.define.clone())
+            .collect::<Vec<_>>();
+
+        if defines.is_empty() {
+            return Ok(handle);
+        }
+
+        defines.sort();
+        defines.dedup();
+
+        self.compile_variant(handle, defines)
+    }
+
+    #[cfg(not(feature = "shaderc"))]
+    fn compile_variant(
+        &mut self,
+        _handle: ShaderHandle,
+        _defines: Vec<CowString>,
+    ) -> Result<ShaderHandle, ShaderVariantError> {
+        Err(ShaderVariantError::MissingSource)
+    }
+
+    #[cfg(feature = "shaderc")]
+    fn compile_variant(
+        &mut self,
+        handle: ShaderHandle,
+        defines: Vec<CowString>,
+    ) -> Result<ShaderHandle, ShaderVariantError> {
+        let (spirv, entry, kind) = {
+            let info = self
+                .sources
+                .get(&handle)
+                .ok_or(ShaderVariantError::MissingSource)?;
+
+            // replacing the base shader destroys its variants, so an existing variant always
+            // matches the current source.
+            if let Some(variant) = info.variants.get(&defines) {
+                return Ok(*variant);
+            }
+
+            let all_defines = info
+                .defines
+                .iter()
+                .map(|(name, value)| (name.as_str(), value.as_ref().map(String::as_str)))
+                .chain(defines.iter().map(|name| (name.as_ref(), None)))
+                .collect::<Vec<_>>();
+
+            let source = ShaderSource {
+                name: info.name.as_str(),
+                language: info.language,
+                code: info.code.as_str(),
+            };
+
+            let spirv = self.compiler.compile(
+                info.kind,
+                source,
+                &info.entry,
+                &all_defines,
+                info.include_resolver.as_ref().map(|resolver| &**resolver),
+            )?;
+
+            (spirv.to_vec(), info.entry.clone(), info.kind)
+        };
+
+        let info = ShaderInfo {
+            spirv_content: &spirv,
+            entry_point: entry.into(),
+        };
+
+        let variant = self
+            .create_of_kind(kind, info)
+            .map_err(ShaderCompileError::from)?;
+
+        if let Some(info) = self.sources.get_mut(&handle) {
+            info.variants.insert(defines, variant);
+        }
+
+        Ok(variant)
+    }
+
+    #[cfg(feature = "shaderc")]
+    fn create_of_kind(
+        &mut self,
+        kind: ShaderKind,
+        info: ShaderInfo<'_>,
+    ) -> Result<ShaderHandle, ShaderError> {
+        let handle = match kind {
+            ShaderKind::Vertex => self.create_vertex_shader(info)?.into(),
+            ShaderKind::Fragment => self.create_fragment_shader(info)?.into(),
+            ShaderKind::Geometry => self.create_geometry_shader(info)?.into(),
+            ShaderKind::TessControl => self.create_tess_control_shader(info)?.into(),
+            ShaderKind::TessEvaluation => self.create_tess_evaluation_shader(info)?.into(),
+            ShaderKind::Compute => self.create_compute_shader(info)?.into(),
+        };
+
+        Ok(handle)
+    }
+
+    /// Compile source code and create a shader of the given kind from it.
+    ///
+    /// The source is kept around so variants with additional defines can be compiled later.
+    #[cfg(feature = "shaderc")]
+    pub(crate) fn create_from_source(
+        &mut self,
+        kind: ShaderKind,
+        source: ShaderSource<'_>,
+        entry: &str,
+        defines: &[(&str, Option<&str>)],
+        include_resolver: Option<Rc<dyn IncludeResolver>>,
+    ) -> Result<ShaderHandle, ShaderCompileError> {
+        let spirv = self
+            .compiler
+            .compile(
+                kind,
+                source,
+                entry,
+                defines,
+                include_resolver.as_ref().map(|resolver| &**resolver),
+            )?
+            .to_vec();
+
+        let info = ShaderInfo {
+            spirv_content: &spirv,
+            entry_point: entry.to_string().into(),
+        };
+
+        let handle = self.create_of_kind(kind, info)?;
+
+        let source_info = SourceInfo {
+            kind,
+            name: source.name.to_string(),
+            language: source.language,
+            code: source.code.to_string(),
+            entry: entry.to_string(),
+            defines: defines
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.map(str::to_string)))
+                .collect(),
+            include_resolver,
+            variants: HashMap::new(),
+        };
+
+        self.sources.insert(handle, source_info);
+
+        Ok(handle)
+    }
+
+    /// Destroy the variants compiled from the source of a shader and forget the source.
+    #[cfg(feature = "shaderc")]
+    fn destroy_variants(&mut self, handle: ShaderHandle) {
+        let info = match self.sources.remove(&handle) {
+            Some(info) => info,
+            None => return,
+        };
+
+        for (_, variant) in info.variants {
+            let retired = &mut self.retired_modules;
+
+            match variant {
+                ShaderHandle::Compute(h) => {
+                    Self::destroy_shader(&mut self.compute_storage, retired, h)
+                }
+                ShaderHandle::Vertex(h) => {
+                    Self::destroy_shader(&mut self.vertex_storage, retired, h)
+                }
+                ShaderHandle::Fragment(h) => {
+                    Self::destroy_shader(&mut self.fragment_storage, retired, h)
+                }
+                ShaderHandle::Geometry(h) => {
+                    Self::destroy_shader(&mut self.geometry_storage, retired, h)
+                }
+                ShaderHandle::TessControl(h) => {
+                    Self::destroy_shader(&mut self.tess_control_storage, retired, h)
+                }
+                ShaderHandle::TessEvaluation(h) => {
+                    Self::destroy_shader(&mut self.tess_evaluation_storage, retired, h)
+                }
+            }
+        }
     }
 
     // compute
 
-    pub(crate) fn create_compute_shader(&mut self, info: ShaderInfo<'_>) -> ComputeShaderHandle {
+    pub(crate) fn create_compute_shader(
+        &mut self,
+        info: ShaderInfo<'_>,
+    ) -> Result<ComputeShaderHandle, ShaderError> {
         ShaderStorage::create_shader(&mut self.compute_storage, info)
     }
 
     pub(crate) fn destroy_compute_shader(&mut self, handle: ComputeShaderHandle) {
-        ShaderStorage::destroy_shader(&mut self.compute_storage, handle);
-    }
+        ShaderStorage::destroy_shader(&mut self.compute_storage, &mut self.retired_modules, handle);
 
-    pub(crate) fn raw_compute(&self, handle: ComputeShaderHandle) -> Option<&Shader<Compute>> {
-        ShaderStorage::raw(&self.compute_storage, handle)
+        #[cfg(feature = "shaderc")]
+        self.destroy_variants(handle.into());
     }
 
     // vertex
 
-    pub(crate) fn create_vertex_shader(&mut self, info: ShaderInfo<'_>) -> VertexShaderHandle {
+    pub(crate) fn create_vertex_shader(
+        &mut self,
+        info: ShaderInfo<'_>,
+    ) -> Result<VertexShaderHandle, ShaderError> {
         ShaderStorage::create_shader(&mut self.vertex_storage, info)
     }
 
     pub(crate) fn destroy_vertex_shader(&mut self, handle: VertexShaderHandle) {
-        ShaderStorage::destroy_shader(&mut self.vertex_storage, handle);
-    }
+        ShaderStorage::destroy_shader(&mut self.vertex_storage, &mut self.retired_modules, handle);
 
-    pub(crate) fn raw_vertex(&self, handle: VertexShaderHandle) -> Option<&Shader<Vertex>> {
-        ShaderStorage::raw(&self.vertex_storage, handle)
+        #[cfg(feature = "shaderc")]
+        self.destroy_variants(handle.into());
     }
 
     // fragment
 
-    pub(crate) fn create_fragment_shader(&mut self, info: ShaderInfo<'_>) -> FragmentShaderHandle {
+    pub(crate) fn create_fragment_shader(
+        &mut self,
+        info: ShaderInfo<'_>,
+    ) -> Result<FragmentShaderHandle, ShaderError> {
         ShaderStorage::create_shader(&mut self.fragment_storage, info)
     }
 
     pub(crate) fn destroy_fragment_shader(&mut self, handle: FragmentShaderHandle) {
-        ShaderStorage::destroy_shader(&mut self.fragment_storage, handle);
-    }
+        ShaderStorage::destroy_shader(
+            &mut self.fragment_storage,
+            &mut self.retired_modules,
+            handle,
+        );
 
-    pub(crate) fn raw_fragment(&self, handle: FragmentShaderHandle) -> Option<&Shader<Fragment>> {
-        ShaderStorage::raw(&self.fragment_storage, handle)
+        #[cfg(feature = "shaderc")]
+        self.destroy_variants(handle.into());
     }
 
     // geometry
 
-    pub(crate) fn create_geometry_shader(&mut self, info: ShaderInfo<'_>) -> GeometryShaderHandle {
+    pub(crate) fn create_geometry_shader(
+        &mut self,
+        info: ShaderInfo<'_>,
+    ) -> Result<GeometryShaderHandle, ShaderError> {
         ShaderStorage::create_shader(&mut self.geometry_storage, info)
     }
 
     pub(crate) fn destroy_geometry_shader(&mut self, handle: GeometryShaderHandle) {
-        ShaderStorage::destroy_shader(&mut self.geometry_storage, handle);
+        ShaderStorage::destroy_shader(
+            &mut self.geometry_storage,
+            &mut self.retired_modules,
+            handle,
+        );
+
+        #[cfg(feature = "shaderc")]
+        self.destroy_variants(handle.into());
+    }
+
+    // tessellation control
+
+    pub(crate) fn create_tess_control_shader(
+        &mut self,
+        info: ShaderInfo<'_>,
+    ) -> Result<TessControlShaderHandle, ShaderError> {
+        ShaderStorage::create_shader(&mut self.tess_control_storage, info)
+    }
+
+    pub(crate) fn destroy_tess_control_shader(&mut self, handle: TessControlShaderHandle) {
+        ShaderStorage::destroy_shader(
+            &mut self.tess_control_storage,
+            &mut self.retired_modules,
+            handle,
+        );
+
+        #[cfg(feature = "shaderc")]
+        self.destroy_variants(handle.into());
+    }
+
+    // tessellation evaluation
+
+    pub(crate) fn create_tess_evaluation_shader(
+        &mut self,
+        info: ShaderInfo<'_>,
+    ) -> Result<TessEvaluationShaderHandle, ShaderError> {
+        ShaderStorage::create_shader(&mut self.tess_evaluation_storage, info)
+    }
+
+    pub(crate) fn destroy_tess_evaluation_shader(&mut self, handle: TessEvaluationShaderHandle) {
+        ShaderStorage::destroy_shader(
+            &mut self.tess_evaluation_storage,
+            &mut self.retired_modules,
+            handle,
+        );
+
+        #[cfg(feature = "shaderc")]
+        self.destroy_variants(handle.into());
     }
+}
+
+/// Check that a SPIR-V module is well-formed and contains the entry point for a stage.
+///
+/// Returns the reflected interface of the entry point.
+fn validate_module(
+    info: &ShaderInfo<'_>,
+    stage: ShaderStages,
+) -> Result<spirv::EntryPoint, ShaderError> {
+    let reflection = spirv::reflect(info.spirv_content)?;
+
+    let name: &str = &info.entry_point;
+
+    if let Some(entry) = reflection.entry_point(name, stage) {
+        return Ok(entry.clone());
+    }
+
+    let found = reflection
+        .entry_points
+        .iter()
+        .filter(|entry| entry.name == name)
+        .fold(ShaderStages::empty(), |stages, entry| stages | entry.stage);
+
+    if found.is_empty() {
+        Err(ShaderError::MissingEntryPoint {
+            name: name.to_string(),
+        })
+    } else {
+        Err(ShaderError::StageMismatch {
+            name: name.to_string(),
+            expected: stage,
+            found,
+        })
+    }
+}
+
+// error stuff
+
+/// Errors that can occur when creating shader programs.
+#[derive(Debug, Display)]
+pub enum ShaderError {
+    /// The handle does not refer to a live shader.
+    #[display(fmt = "Invalid shader handle used")]
+    InvalidHandle,
+
+    /// The code is not a valid SPIR-V module.
+    #[display(fmt = "Invalid SPIR-V module: {}", _0)]
+    InvalidSpirv(ReflectError),
+
+    /// The module has no entry point with the given name.
+    #[display(fmt = "Shader program has no entry point named \"{}\"", name)]
+    MissingEntryPoint {
+        /// Name of the entry point.
+        name: String,
+    },
 
-    pub(crate) fn raw_geometry(&self, handle: GeometryShaderHandle) -> Option<&Shader<Geometry>> {
-        ShaderStorage::raw(&self.geometry_storage, handle)
+    /// The entry point exists, but with an execution model of a different shader type.
+    #[display(
+        fmt = "Entry point \"{}\" is used for {:?}, but {:?} was expected",
+        name,
+        found,
+        expected
+    )]
+    StageMismatch {
+        /// Name of the entry point.
+        name: String,
+        /// Stage of the shader that was created.
+        expected: ShaderStages,
+        /// Stages the entry point is declared for.
+        found: ShaderStages,
+    },
+}
+
+impl std::error::Error for ShaderError {}
+
+impl From<ReflectError> for ShaderError {
+    fn from(err: ReflectError) -> Self {
+        ShaderError::InvalidSpirv(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A module with a single vertex shader entry point named "main".
+    fn vertex_module() -> Vec<u8> {
+        let words: [u32; 16] = [
+            // header
+            0x0723_0203,
+            0x0001_0000,
+            0,
+            4,
+            0,
+            // OpEntryPoint Vertex %1 "main"
+            5 << 16 | 15,
+            0,
+            1,
+            u32::from_le_bytes(*b"main"),
+            0,
+            // %1 = OpFunction %2 None %3
+            5 << 16 | 54,
+            2,
+            1,
+            0,
+            3,
+            // OpFunctionEnd
+            1 << 16 | 56,
+        ];
+
+        words
+            .iter()
+            .flat_map(|word| word.to_le_bytes().to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn validate_modules() {
+        let module = vertex_module();
+
+        fn info<'a>(content: &'a [u8], entry: &'static str) -> ShaderInfo<'a> {
+            ShaderInfo {
+                spirv_content: content,
+                entry_point: entry.into(),
+            }
+        }
+
+        assert!(validate_module(&info(&module, "main"), ShaderStages::VERTEX).is_ok());
+
+        match validate_module(
+            &info(&module[..module.len() - 2], "main"),
+            ShaderStages::VERTEX,
+        ) {
+            Err(ShaderError::InvalidSpirv(ReflectError::InvalidSize)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        let swapped = module
+            .chunks(4)
+            .flat_map(|word| word.iter().rev().cloned())
+            .collect::<Vec<_>>();
+
+        match validate_module(&info(&swapped, "main"), ShaderStages::VERTEX) {
+            Err(ShaderError::InvalidSpirv(ReflectError::WrongByteOrder)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        match validate_module(&info(&module, "mian"), ShaderStages::VERTEX) {
+            Err(ShaderError::MissingEntryPoint { .. }) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        match validate_module(&info(&module, "main"), ShaderStages::FRAGMENT) {
+            Err(ShaderError::StageMismatch { found, .. }) => {
+                assert_eq!(found, ShaderStages::VERTEX)
+            }
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+
+    #[test]
+    fn replace_bumps_version() {
+        let module = vertex_module();
+
+        let mut storage = ShaderStorage::new();
+
+        let handle = storage
+            .create_vertex_shader(ShaderInfo {
+                spirv_content: &module,
+                entry_point: "main".into(),
+            })
+            .unwrap();
+
+        assert_eq!(storage.version(handle.into()), Some(0));
+
+        storage
+            .replace(
+                handle.into(),
+                ShaderInfo {
+                    spirv_content: &module,
+                    entry_point: "main".into(),
+                },
+            )
+            .unwrap();
+
+        assert_eq!(storage.version(handle.into()), Some(1));
+
+        // invalid content leaves the shader untouched
+        let res = storage.replace(
+            handle.into(),
+            ShaderInfo {
+                spirv_content: &module,
+                entry_point: "other".into(),
+            },
+        );
+
+        assert!(res.is_err());
+        assert_eq!(storage.version(handle.into()), Some(1));
+
+        storage.destroy_vertex_shader(handle);
+
+        assert_eq!(storage.version(handle.into()), None);
+    }
+
+    #[cfg(feature = "shaderc")]
+    #[test]
+    fn replace_retires_variants() {
+        let module = vertex_module();
+
+        let info = || ShaderInfo {
+            spirv_content: &module,
+            entry_point: "main".into(),
+        };
+
+        let mut storage = ShaderStorage::new();
+
+        let base = ShaderHandle::from(storage.create_vertex_shader(info()).unwrap());
+        let variant = ShaderHandle::from(storage.create_vertex_shader(info()).unwrap());
+
+        let mut variants = HashMap::new();
+        variants.insert(vec![CowString::from("FOO")], variant);
+
+        storage.sources.insert(
+            base,
+            SourceInfo {
+                kind: ShaderKind::Vertex,
+                name: "test.vert".to_string(),
+                language: SourceLanguage::Glsl,
+                code: String::new(),
+                entry: "main".to_string(),
+                defines: vec![],
+                include_resolver: None,
+                variants,
+            },
+        );
+
+        storage.replace(base, info()).unwrap();
+
+        assert_eq!(storage.version(base), Some(1));
+        assert_eq!(storage.version(variant), None);
+
+        // the new content has no source, so no variants can be compiled from it.
+        match storage.resolve_variant(base, &[Specialization::define("FOO")]) {
+            Err(ShaderVariantError::MissingSource) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        assert_eq!(storage.resolve_variant(base, &[]).unwrap(), base);
+    }
+
+    #[test]
+    #[cfg(feature = "shaderc")]
+    fn existing_variants_are_reused() {
+        let module = vertex_module();
+
+        let info = || ShaderInfo {
+            spirv_content: &module,
+            entry_point: "main".into(),
+        };
+
+        let mut storage = ShaderStorage::new();
+
+        let base = ShaderHandle::from(storage.create_vertex_shader(info()).unwrap());
+        let variant = ShaderHandle::from(storage.create_vertex_shader(info()).unwrap());
+
+        let mut variants = HashMap::new();
+        variants.insert(
+            vec![CowString::from("BAR"), CowString::from("FOO")],
+            variant,
+        );
+
+        // the source does not compile, so only existing variants can be resolved.
+        storage.sources.insert(
+            base,
+            SourceInfo {
+                kind: ShaderKind::Vertex,
+                name: "test.vert".to_string(),
+                language: SourceLanguage::Glsl,
+                code: "not glsl".to_string(),
+                entry: "main".to_string(),
+                defines: vec![],
+                include_resolver: None,
+                variants,
+            },
+        );
+
+        let specialization = [
+            Specialization::define("FOO"),
+            Specialization::define("BAR"),
+            Specialization::define("FOO"),
+        ];
+
+        assert_eq!(
+            storage.resolve_variant(base, &specialization).unwrap(),
+            variant
+        );
+
+        match storage.resolve_variant(base, &[Specialization::define("BAZ")]) {
+            Err(ShaderVariantError::Compilation(_)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
     }
 }
@@ -0,0 +1,526 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Compilation of GLSL and HLSL source code into shaders at runtime.
+//!
+//! This module is only available with the `shaderc` feature enabled.
+//!
+//! Compiled SPIR-V code is cached by the [`Context`], keyed by the source code, the defines, the
+//! entry point and the shader kind. Files included by the source code are checked for changes
+//! before cached code is reused. Only the most recently used compilation results are kept.
+//!
+//! [`Context`]: ../struct.Context.html
+
+use crate::resources::shader::ShaderError;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+/// Maximum number of compilation results kept in the cache.
+const CACHE_CAPACITY: usize = 64;
+
+/// Type of shader program the source code describes.
+#[allow(missing_docs)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ShaderKind {
+    Vertex,
+    Fragment,
+    Geometry,
+    TessControl,
+    TessEvaluation,
+    Compute,
+}
+
+impl From<ShaderKind> for shaderc::ShaderKind {
+    fn from(kind: ShaderKind) -> Self {
+        match kind {
+            ShaderKind::Vertex => shaderc::ShaderKind::Vertex,
+            ShaderKind::Fragment => shaderc::ShaderKind::Fragment,
+            ShaderKind::Geometry => shaderc::ShaderKind::Geometry,
+            ShaderKind::TessControl => shaderc::ShaderKind::TessControl,
+            ShaderKind::TessEvaluation => shaderc::ShaderKind::TessEvaluation,
+            ShaderKind::Compute => shaderc::ShaderKind::Compute,
+        }
+    }
+}
+
+/// Language the shader source code is written in.
+#[allow(missing_docs)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum SourceLanguage {
+    Glsl,
+    Hlsl,
+}
+
+/// Shader source code to compile.
+#[derive(Debug, Copy, Clone)]
+pub struct ShaderSource<'a> {
+    /// Name of the source, for example the file name.
+    ///
+    /// The name is used in error messages and to resolve relative includes.
+    pub name: &'a str,
+
+    /// Language the source code is written in.
+    pub language: SourceLanguage,
+
+    /// The source code.
+    pub code: &'a str,
+}
+
+/// Kind of an `#include` directive.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum IncludeKind {
+    /// `#include "file"`, relative to the including file.
+    Relative,
+
+    /// `#include <file>`
+    Standard,
+}
+
+/// Content of an included file.
+#[derive(Debug, Clone)]
+pub struct ResolvedInclude {
+    /// Name of the included file, used to resolve nested includes and in error messages.
+    pub name: String,
+
+    /// Source code of the included file.
+    pub content: String,
+}
+
+/// Provides the content of files included by shader source code.
+pub trait IncludeResolver {
+    /// Resolve the file `requested` which is included by the file `requesting`.
+    fn resolve(
+        &self,
+        requested: &str,
+        kind: IncludeKind,
+        requesting: &str,
+    ) -> Result<ResolvedInclude, String>;
+}
+
+impl<F> IncludeResolver for F
+where
+    F: Fn(&str, IncludeKind, &str) -> Result<ResolvedInclude, String>,
+{
+    fn resolve(
+        &self,
+        requested: &str,
+        kind: IncludeKind,
+        requesting: &str,
+    ) -> Result<ResolvedInclude, String> {
+        self(requested, kind, requesting)
+    }
+}
+
+/// A single error reported by the shader compiler.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompileDiagnostic {
+    /// The file the error occurred in.
+    pub file: Option<String>,
+
+    /// The line the error occurred in.
+    pub line: Option<u32>,
+
+    /// The error message.
+    pub message: String,
+}
+
+impl std::fmt::Display for CompileDiagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match (&self.file, self.line) {
+            (Some(file), Some(line)) => write!(f, "{}:{}: {}", file, line, self.message),
+            (Some(file), None) => write!(f, "{}: {}", file, self.message),
+            _ => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// Errors that can occur when compiling shader source code.
+#[derive(Debug, Display)]
+pub enum ShaderCompileError {
+    /// The shader compiler could not be initialized.
+    #[display(fmt = "Shader compiler could not be initialized")]
+    CompilerUnavailable,
+
+    /// The source code contains errors.
+    #[display(fmt = "Shader compilation failed: {}", "join_diagnostics(_0)")]
+    Compilation(Vec<CompileDiagnostic>),
+
+    /// The compiler failed for reasons unrelated to the source code.
+    #[display(fmt = "Shader compiler failed: {}", _0)]
+    Internal(String),
+
+    /// The compiled code could not be used to create a shader.
+    #[display(fmt = "Compiled shader could not be created: {}", _0)]
+    Shader(ShaderError),
+}
+
+impl std::error::Error for ShaderCompileError {}
+
+impl From<ShaderError> for ShaderCompileError {
+    fn from(err: ShaderError) -> Self {
+        ShaderCompileError::Shader(err)
+    }
+}
+
+impl From<shaderc::Error> for ShaderCompileError {
+    fn from(err: shaderc::Error) -> Self {
+        use shaderc::Error;
+
+        match err {
+            Error::CompilationError(_, log) => {
+                ShaderCompileError::Compilation(parse_diagnostics(&log))
+            }
+            Error::InternalError(msg)
+            | Error::InvalidStage(msg)
+            | Error::InvalidAssembly(msg)
+            | Error::NullResultObject(msg) => ShaderCompileError::Internal(msg),
+        }
+    }
+}
+
+fn join_diagnostics(diagnostics: &[CompileDiagnostic]) -> String {
+    diagnostics
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Split the compiler log into diagnostics.
+///
+/// Errors are reported as `file:line: error: message`, summary lines are skipped.
+fn parse_diagnostics(log: &str) -> Vec<CompileDiagnostic> {
+    let mut diagnostics = log
+        .lines()
+        .filter_map(|line| {
+            let pos = line.find(": error: ")?;
+
+            let location = &line[..pos];
+            let message = line[pos + ": error: ".len()..].trim().to_string();
+
+            // file names can contain colons, so the line number is after the last one.
+            let (file, line) = match location.rfind(':') {
+                Some(colon) => match location[colon + 1..].trim().parse() {
+                    Ok(line) => (&location[..colon], Some(line)),
+                    Err(_) => (location, None),
+                },
+                None => (location, None),
+            };
+
+            Some(CompileDiagnostic {
+                file: Some(file.to_string()).filter(|file| !file.is_empty()),
+                line,
+                message,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    if diagnostics.is_empty() {
+        diagnostics.push(CompileDiagnostic {
+            file: None,
+            line: None,
+            message: log.trim().to_string(),
+        });
+    }
+
+    diagnostics
+}
+
+/// Everything that influences the result of a compilation, apart from included files.
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct CacheKey {
+    kind: ShaderKind,
+    name: String,
+    language: SourceLanguage,
+    code: String,
+    entry: String,
+    defines: Vec<(String, Option<String>)>,
+}
+
+impl CacheKey {
+    fn new(
+        kind: ShaderKind,
+        source: ShaderSource<'_>,
+        entry: &str,
+        defines: &[(&str, Option<&str>)],
+    ) -> Self {
+        CacheKey {
+            kind,
+            name: source.name.to_string(),
+            language: source.language,
+            code: source.code.to_string(),
+            entry: entry.to_string(),
+            defines: defines
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.map(str::to_string)))
+                .collect(),
+        }
+    }
+}
+
+struct IncludeRecord {
+    requested: String,
+    kind: IncludeKind,
+    requesting: String,
+    content: String,
+}
+
+struct CachedSpirv {
+    spirv: Vec<u8>,
+    includes: Vec<IncludeRecord>,
+    last_used: u64,
+}
+
+pub(crate) struct ShaderCompiler {
+    compiler: Option<shaderc::Compiler>,
+    cache: HashMap<CacheKey, CachedSpirv>,
+    lookups: u64,
+}
+
+impl ShaderCompiler {
+    pub(crate) fn new() -> Self {
+        ShaderCompiler {
+            compiler: None,
+            cache: HashMap::new(),
+            lookups: 0,
+        }
+    }
+
+    /// Compile source code into SPIR-V or retrieve the code from a previous compilation.
+    pub(crate) fn compile(
+        &mut self,
+        kind: ShaderKind,
+        source: ShaderSource<'_>,
+        entry: &str,
+        defines: &[(&str, Option<&str>)],
+        include_resolver: Option<&dyn IncludeResolver>,
+    ) -> Result<&[u8], ShaderCompileError> {
+        let key = CacheKey::new(kind, source, entry, defines);
+
+        self.lookups += 1;
+        let lookups = self.lookups;
+
+        let up_to_date = match self.cache.get_mut(&key) {
+            Some(cached) => {
+                cached.last_used = lookups;
+                includes_unchanged(&cached.includes, include_resolver)
+            }
+            None => false,
+        };
+
+        if !up_to_date {
+            let compiled = self.compile_uncached(kind, source, entry, defines, include_resolver)?;
+
+            if !self.cache.contains_key(&key) && self.cache.len() >= CACHE_CAPACITY {
+                self.evict_least_recently_used();
+            }
+
+            self.cache.insert(key.clone(), compiled);
+        }
+
+        Ok(&self.cache[&key].spirv)
+    }
+
+    fn evict_least_recently_used(&mut self) {
+        let oldest = self
+            .cache
+            .iter()
+            .min_by_key(|(_, cached)| cached.last_used)
+            .map(|(key, _)| key.clone());
+
+        if let Some(key) = oldest {
+            self.cache.remove(&key);
+        }
+    }
+
+    fn compile_uncached(
+        &mut self,
+        kind: ShaderKind,
+        source: ShaderSource<'_>,
+        entry: &str,
+        defines: &[(&str, Option<&str>)],
+        include_resolver: Option<&dyn IncludeResolver>,
+    ) -> Result<CachedSpirv, ShaderCompileError> {
+        if self.compiler.is_none() {
+            self.compiler = shaderc::Compiler::new();
+        }
+
+        let last_used = self.lookups;
+
+        let compiler = self
+            .compiler
+            .as_mut()
+            .ok_or(ShaderCompileError::CompilerUnavailable)?;
+
+        let includes = RefCell::new(vec![]);
+
+        let mut options =
+            shaderc::CompileOptions::new().ok_or(ShaderCompileError::CompilerUnavailable)?;
+
+        options.set_source_language(match source.language {
+            SourceLanguage::Glsl => shaderc::SourceLanguage::GLSL,
+            SourceLanguage::Hlsl => shaderc::SourceLanguage::HLSL,
+        });
+
+        for (name, value) in defines {
+            options.add_macro_definition(name, *value);
+        }
+
+        if let Some(resolver) = include_resolver {
+            let includes = &includes;
+
+            options.set_include_callback(move |requested, ty, requesting, _depth| {
+                let kind = match ty {
+                    shaderc::IncludeType::Relative => IncludeKind::Relative,
+                    shaderc::IncludeType::Standard => IncludeKind::Standard,
+                };
+
+                let resolved = resolver.resolve(requested, kind, requesting)?;
+
+                // remember the content, so cached code can be checked for changed includes.
+                includes.borrow_mut().push(IncludeRecord {
+                    requested: requested.to_string(),
+                    kind,
+                    requesting: requesting.to_string(),
+                    content: resolved.content.clone(),
+                });
+
+                Ok(shaderc::ResolvedInclude {
+                    resolved_name: resolved.name,
+                    content: resolved.content,
+                })
+            });
+        }
+
+        let artifact = compiler.compile_into_spirv(
+            source.code,
+            kind.into(),
+            source.name,
+            entry,
+            Some(&options),
+        )?;
+
+        let spirv = artifact.as_binary_u8().to_vec();
+
+        drop(options);
+
+        Ok(CachedSpirv {
+            spirv,
+            includes: includes.into_inner(),
+            last_used,
+        })
+    }
+}
+
+fn includes_unchanged(
+    includes: &[IncludeRecord],
+    include_resolver: Option<&dyn IncludeResolver>,
+) -> bool {
+    if includes.is_empty() {
+        return true;
+    }
+
+    let resolver = match include_resolver {
+        Some(resolver) => resolver,
+        None => return false,
+    };
+
+    includes.iter().all(|include| {
+        resolver
+            .resolve(&include.requested, include.kind, &include.requesting)
+            .map(|resolved| resolved.content == include.content)
+            .unwrap_or(false)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_compiler_log() {
+        let log = "shaders/light.frag:12: error: 'albedo' : undeclared identifier\n\
+                   C:\\shaders\\common.glsl:3: error: syntax error\n\
+                   2 errors generated.\n";
+
+        let diagnostics = parse_diagnostics(log);
+
+        assert_eq!(
+            diagnostics,
+            vec![
+                CompileDiagnostic {
+                    file: Some("shaders/light.frag".to_string()),
+                    line: Some(12),
+                    message: "'albedo' : undeclared identifier".to_string(),
+                },
+                CompileDiagnostic {
+                    file: Some("C:\\shaders\\common.glsl".to_string()),
+                    line: Some(3),
+                    message: "syntax error".to_string(),
+                },
+            ]
+        );
+
+        let diagnostics = parse_diagnostics("something went wrong");
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].file, None);
+        assert_eq!(diagnostics[0].message, "something went wrong");
+    }
+
+    #[test]
+    fn cache_lookup_and_eviction() {
+        let source = |code| ShaderSource {
+            name: "test.comp",
+            language: SourceLanguage::Glsl,
+            code,
+        };
+
+        let codes = (0..CACHE_CAPACITY)
+            .map(|i| format!("// {}", i))
+            .collect::<Vec<_>>();
+
+        let mut compiler = ShaderCompiler::new();
+
+        for (i, code) in codes.iter().enumerate() {
+            let key = CacheKey::new(ShaderKind::Compute, source(code), "main", &[]);
+
+            compiler.cache.insert(
+                key,
+                CachedSpirv {
+                    spirv: vec![i as u8],
+                    includes: vec![],
+                    last_used: i as u64,
+                },
+            );
+        }
+
+        compiler.lookups = CACHE_CAPACITY as u64;
+
+        // cached entries are only used when all inputs match.
+        let spirv = compiler
+            .compile(ShaderKind::Compute, source(&codes[0]), "main", &[], None)
+            .unwrap();
+        assert_eq!(spirv, &[0]);
+
+        let with_define = CacheKey::new(
+            ShaderKind::Compute,
+            source(&codes[0]),
+            "main",
+            &[("FOO", None)],
+        );
+        assert!(!compiler.cache.contains_key(&with_define));
+
+        // the lookup made the first entry the most recently used one.
+        compiler.evict_least_recently_used();
+
+        assert_eq!(compiler.cache.len(), CACHE_CAPACITY - 1);
+
+        let first = CacheKey::new(ShaderKind::Compute, source(&codes[0]), "main", &[]);
+        let second = CacheKey::new(ShaderKind::Compute, source(&codes[1]), "main", &[]);
+        assert!(compiler.cache.contains_key(&first));
+        assert!(!compiler.cache.contains_key(&second));
+    }
+}
@@ -0,0 +1,181 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Shader permutations selected by feature names.
+//!
+//! A [`ShaderVariantSet`] combines a shader with a set of named features. Passes select the
+//! features they need in `configure()` and get a [`Shader`] description which can be used in
+//! the pipeline info directly:
+//!
+//! ```rust,ignore
+//! fn configure(&self, config: &Self::Config) -> GraphicsPipelineInfo {
+//!     let mut features = vec![];
+//!     if config.shadows {
+//!         features.push("SHADOWS");
+//!     }
+//!
+//!     GraphicsPipelineInfo {
+//!         shaders: GraphicsShaders {
+//!             vertex: self.vertex_variants.variant(&features).unwrap(),
+//!             // ...
+//!         },
+//!         // ...
+//!     }
+//! }
+//! ```
+//!
+//! Features can either set a specialization constant or a preprocessor define. Defines require
+//! the shader to be created from source code, the concrete shader for a combination of defines
+//! is compiled and cached by nitrogen the first time a pipeline uses it.
+//!
+//! [`ShaderVariantSet`]: ./struct.ShaderVariantSet.html
+//! [`Shader`]: ../graph/pass/struct.Shader.html
+
+use crate::graph::{Shader, Specialization};
+use crate::util::CowString;
+
+#[cfg(feature = "shaderc")]
+use crate::resources::shader_compiler::ShaderCompileError;
+
+/// The way a feature of a [`ShaderVariantSet`] is applied to the shader.
+///
+/// [`ShaderVariantSet`]: ./struct.ShaderVariantSet.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum VariantFeature {
+    /// A boolean specialization constant with the given id.
+    ///
+    /// The constant is set to `true` if the feature is enabled and to `false` otherwise.
+    Specialization(u32),
+
+    /// A preprocessor define with the name of the feature.
+    ///
+    /// Only available for shaders created from source code.
+    Define,
+}
+
+/// Errors that can occur when selecting shader variants.
+#[derive(Debug, Display)]
+pub enum ShaderVariantError {
+    /// The feature is not part of the variant set.
+    #[display(fmt = "Unknown shader feature \"{}\"", _0)]
+    UnknownFeature(String),
+
+    /// Defines were selected for a shader which was not created from source code.
+    #[display(fmt = "Shader variants with defines require a shader created from source")]
+    MissingSource,
+
+    /// The variant could not be compiled.
+    #[cfg(feature = "shaderc")]
+    #[display(fmt = "Shader variant could not be compiled: {}", _0)]
+    Compilation(ShaderCompileError),
+}
+
+impl std::error::Error for ShaderVariantError {}
+
+#[cfg(feature = "shaderc")]
+impl From<ShaderCompileError> for ShaderVariantError {
+    fn from(err: ShaderCompileError) -> Self {
+        ShaderVariantError::Compilation(err)
+    }
+}
+
+/// A shader together with a set of features which can be switched on and off.
+#[derive(Debug, Clone)]
+pub struct ShaderVariantSet<HandleType> {
+    handle: HandleType,
+    features: Vec<(CowString, VariantFeature)>,
+}
+
+impl<HandleType: Copy> ShaderVariantSet<HandleType> {
+    /// Create a variant set for the shader `handle`.
+    pub fn new<I, N>(handle: HandleType, features: I) -> Self
+    where
+        I: IntoIterator<Item = (N, VariantFeature)>,
+        N: Into<CowString>,
+    {
+        ShaderVariantSet {
+            handle,
+            features: features
+                .into_iter()
+                .map(|(name, feature)| (name.into(), feature))
+                .collect(),
+        }
+    }
+
+    /// The shader all variants are derived from.
+    pub fn handle(&self) -> HandleType {
+        self.handle
+    }
+
+    /// Describe the variant of the shader with the features in `enabled` switched on.
+    ///
+    /// All other features are switched off.
+    pub fn variant(&self, enabled: &[&str]) -> Result<Shader<HandleType>, ShaderVariantError> {
+        if let Some(unknown) = enabled
+            .iter()
+            .find(|name| !self.features.iter().any(|(feature, _)| feature == *name))
+        {
+            return Err(ShaderVariantError::UnknownFeature(unknown.to_string()));
+        }
+
+        let specialization = self
+            .features
+            .iter()
+            .filter_map(|(name, feature)| {
+                let is_enabled = enabled.contains(&&**name);
+
+                match feature {
+                    // VkBool32 is 32 bits wide
+                    VariantFeature::Specialization(id) => {
+                        Some(Specialization::new(*id, is_enabled as u32))
+                    }
+                    VariantFeature::Define if is_enabled => {
+                        Some(Specialization::define(name.clone()))
+                    }
+                    VariantFeature::Define => None,
+                }
+            })
+            .collect();
+
+        Ok(Shader {
+            handle: self.handle,
+            specialization,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn select_features() {
+        let set = ShaderVariantSet::new(
+            0u32,
+            vec![
+                ("SHADOWS", VariantFeature::Define),
+                ("FOG", VariantFeature::Specialization(3)),
+            ],
+        );
+
+        let shader = set.variant(&["SHADOWS"]).unwrap();
+
+        assert_eq!(
+            shader.specialization,
+            vec![
+                Specialization::define("SHADOWS"),
+                Specialization::new(3, 0u32)
+            ]
+        );
+
+        let shader = set.variant(&["FOG"]).unwrap();
+
+        assert_eq!(shader.specialization, vec![Specialization::new(3, 1u32)]);
+
+        match set.variant(&["BLOOM"]) {
+            Err(ShaderVariantError::UnknownFeature(name)) => assert_eq!(name, "BLOOM"),
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+}
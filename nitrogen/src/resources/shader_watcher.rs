@@ -0,0 +1,132 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Reloading of shader programs when their files change on disk.
+//!
+//! The [`ShaderWatcher`] polls the modification time of SPIR-V files and replaces the
+//! content of the associated shaders when a file changed. Pipelines using a replaced shader get
+//! recreated the next time they are used, so the effect of an edit is visible in the next
+//! graph execution.
+//!
+//! [`ShaderWatcher`]: ./struct.ShaderWatcher.html
+
+use crate::resources::shader::{EntryPoint, ShaderError, ShaderHandle, ShaderInfo};
+use crate::Context;
+
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Errors that can occur when reloading a shader program.
+#[derive(Debug, Display, From)]
+pub enum ShaderReloadError {
+    /// The shader file could not be read.
+    #[display(fmt = "Shader file could not be read: {}", _0)]
+    Io(std::io::Error),
+
+    /// The new content could not be used, the previous program is still in use.
+    #[display(fmt = "Shader could not be replaced: {}", _0)]
+    Shader(ShaderError),
+}
+
+impl std::error::Error for ShaderReloadError {}
+
+struct WatchedShader {
+    handle: ShaderHandle,
+    path: PathBuf,
+    entry_point: EntryPoint,
+    modified: Option<SystemTime>,
+}
+
+/// Watches SPIR-V files and replaces shaders when the files change.
+#[derive(Default)]
+pub struct ShaderWatcher {
+    watched: Vec<WatchedShader>,
+}
+
+impl ShaderWatcher {
+    /// Create a watcher without any watched files.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Watch the file at `path` and use it as the program of the shader `handle`.
+    ///
+    /// The file is not loaded right away, only when it changes after this call.
+    pub fn watch(
+        &mut self,
+        handle: impl Into<ShaderHandle>,
+        path: impl Into<PathBuf>,
+        entry_point: impl Into<EntryPoint>,
+    ) {
+        let handle = handle.into();
+        let path = path.into();
+
+        let modified = modified_time(&path);
+
+        self.unwatch(handle);
+
+        self.watched.push(WatchedShader {
+            handle,
+            path,
+            entry_point: entry_point.into(),
+            modified,
+        });
+    }
+
+    /// Stop watching the file of a shader.
+    pub fn unwatch(&mut self, handle: impl Into<ShaderHandle>) {
+        let handle = handle.into();
+        self.watched.retain(|watched| watched.handle != handle);
+    }
+
+    /// Reload all shaders whose files changed since the last check.
+    ///
+    /// Returns the handles of the shaders that were replaced and the errors of the shaders that
+    /// failed to reload. A shader which fails to reload keeps its previous program and is retried
+    /// once the file changes again.
+    pub fn reload_changed(
+        &mut self,
+        ctx: &mut Context,
+    ) -> (Vec<ShaderHandle>, Vec<(ShaderHandle, ShaderReloadError)>) {
+        let mut replaced = vec![];
+        let mut errors = vec![];
+
+        for watched in &mut self.watched {
+            let modified = modified_time(&watched.path);
+
+            if modified.is_none() || modified == watched.modified {
+                // either the file is gone (possibly only temporarily while being written) or
+                // nothing changed.
+                continue;
+            }
+
+            watched.modified = modified;
+
+            let result = std::fs::read(&watched.path)
+                .map_err(ShaderReloadError::from)
+                .and_then(|content| {
+                    let info = ShaderInfo {
+                        spirv_content: &content,
+                        entry_point: watched.entry_point.clone(),
+                    };
+
+                    ctx.shader_replace(watched.handle, info)
+                        .map_err(ShaderReloadError::from)
+                });
+
+            match result {
+                Ok(()) => replaced.push(watched.handle),
+                Err(err) => errors.push((watched.handle, err)),
+            }
+        }
+
+        (replaced, errors)
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+}
@@ -0,0 +1,1337 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Reflection of SPIR-V shader programs.
+//!
+//! Only the parts of a module that describe its interface are extracted: entry points and the
+//! descriptor bindings, push-constant block and vertex inputs they use. This information is used
+//! to validate pipelines against the shaders they use.
+
+use crate::resources::shader::ShaderStages;
+
+use derive_more::Display;
+
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+const MAGIC_NUMBER: u32 = 0x0723_0203;
+const HEADER_SIZE: usize = 5;
+
+/// Maximum nesting depth of types, guards against recursive type definitions.
+const MAX_TYPE_DEPTH: u32 = 64;
+
+// opcodes
+const OP_LINE: u32 = 8;
+const OP_ENTRY_POINT: u32 = 15;
+const OP_EXECUTION_MODE: u32 = 16;
+const OP_TYPE_BOOL: u32 = 20;
+const OP_TYPE_INT: u32 = 21;
+const OP_TYPE_FLOAT: u32 = 22;
+const OP_TYPE_VECTOR: u32 = 23;
+const OP_TYPE_MATRIX: u32 = 24;
+const OP_TYPE_IMAGE: u32 = 25;
+const OP_TYPE_SAMPLER: u32 = 26;
+const OP_TYPE_SAMPLED_IMAGE: u32 = 27;
+const OP_TYPE_ARRAY: u32 = 28;
+const OP_TYPE_RUNTIME_ARRAY: u32 = 29;
+const OP_TYPE_STRUCT: u32 = 30;
+const OP_TYPE_POINTER: u32 = 32;
+const OP_CONSTANT: u32 = 43;
+const OP_SPEC_CONSTANT: u32 = 50;
+const OP_FUNCTION: u32 = 54;
+const OP_FUNCTION_END: u32 = 56;
+const OP_FUNCTION_CALL: u32 = 57;
+const OP_VARIABLE: u32 = 59;
+const OP_DECORATE: u32 = 71;
+const OP_MEMBER_DECORATE: u32 = 72;
+
+// decorations
+const DECORATION_BUFFER_BLOCK: u32 = 3;
+const DECORATION_ARRAY_STRIDE: u32 = 6;
+const DECORATION_MATRIX_STRIDE: u32 = 7;
+const DECORATION_BUILT_IN: u32 = 11;
+const DECORATION_LOCATION: u32 = 30;
+const DECORATION_BINDING: u32 = 33;
+const DECORATION_DESCRIPTOR_SET: u32 = 34;
+const DECORATION_OFFSET: u32 = 35;
+
+// storage classes
+const STORAGE_CLASS_UNIFORM_CONSTANT: u32 = 0;
+const STORAGE_CLASS_INPUT: u32 = 1;
+const STORAGE_CLASS_UNIFORM: u32 = 2;
+const STORAGE_CLASS_PUSH_CONSTANT: u32 = 9;
+const STORAGE_CLASS_STORAGE_BUFFER: u32 = 12;
+
+const EXECUTION_MODE_LOCAL_SIZE: u32 = 17;
+
+const DIM_BUFFER: u32 = 5;
+const DIM_SUBPASS_DATA: u32 = 6;
+
+/// Errors that can occur when reflecting a SPIR-V module.
+#[derive(Debug, Display)]
+pub enum ReflectError {
+    /// The size of the code is not a multiple of 4 or too small to contain a header.
+    #[display(fmt = "SPIR-V code has an invalid size")]
+    InvalidSize,
+
+    /// The code does not start with the SPIR-V magic number.
+    #[display(fmt = "Invalid SPIR-V magic number {:#x}", _0)]
+    InvalidMagicNumber(u32),
+
+    /// The module is stored in the opposite byte order.
+    ///
+    /// The code is passed to the driver as-is, so it has to be stored in little-endian words.
+    #[display(fmt = "SPIR-V module is stored in the wrong byte order")]
+    WrongByteOrder,
+
+    /// An instruction at the given word offset is truncated or has invalid operands.
+    #[display(fmt = "Malformed SPIR-V instruction at word {}", _0)]
+    MalformedInstruction(usize),
+
+    /// An id is referenced which is not defined in the module.
+    #[display(fmt = "SPIR-V id {} is referenced but not defined", _0)]
+    UndefinedId(u32),
+
+    /// A type is nested too deeply or refers to itself.
+    #[display(fmt = "SPIR-V type {} is nested too deeply or recursive", _0)]
+    RecursiveType(u32),
+
+    /// The size or descriptor count of a type does not fit into 32 bits.
+    #[display(fmt = "Size of SPIR-V type {} overflows", _0)]
+    SizeOverflow(u32),
+
+    /// An array or matrix type has a length that can't be used by a valid module.
+    #[display(fmt = "SPIR-V type {} has an invalid length", _0)]
+    InvalidLength(u32),
+}
+
+impl std::error::Error for ReflectError {}
+
+/// Type of a descriptor used by a shader program.
+#[allow(missing_docs)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum DescriptorType {
+    Sampler,
+    CombinedImageSampler,
+    SampledImage,
+    StorageImage,
+    UniformTexelBuffer,
+    StorageTexelBuffer,
+    UniformBuffer,
+    StorageBuffer,
+    InputAttachment,
+}
+
+/// A descriptor binding used by a shader program.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct DescriptorBinding {
+    /// Descriptor set of the binding.
+    pub set: u32,
+    /// Binding inside the descriptor set.
+    pub binding: u32,
+    /// Type of the descriptor.
+    pub ty: DescriptorType,
+    /// Number of descriptors in the binding, `0` for runtime sized arrays.
+    pub count: u32,
+}
+
+/// Base type of the components of a shader input.
+#[allow(missing_docs)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum ScalarType {
+    Float,
+    Int,
+    Uint,
+}
+
+/// An input variable of a vertex shader.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct InputVariable {
+    /// First location of the input.
+    pub location: u32,
+    /// Number of consecutive locations used by the input.
+    ///
+    /// Matrices use one location per column and arrays one per element. 64-bit vectors with
+    /// more than two components use two locations each.
+    pub locations: u32,
+    /// Base type of the components.
+    pub ty: ScalarType,
+    /// Number of components.
+    pub components: u32,
+}
+
+/// An entry point of a shader program.
+#[derive(Debug, Clone)]
+pub struct EntryPoint {
+    /// Name of the entry point function.
+    pub name: String,
+    /// Shader stage the entry point is used in.
+    pub stage: ShaderStages,
+    /// Workgroup size of compute shaders.
+    pub local_size: Option<[u32; 3]>,
+    /// Inputs of vertex shaders, empty for other stages.
+    pub inputs: Vec<InputVariable>,
+    /// Descriptors used by the entry point and the functions it calls.
+    pub descriptors: Vec<DescriptorBinding>,
+    /// Range (in bytes) of the push-constant block, if the entry point uses one.
+    pub push_constants: Option<Range<u32>>,
+}
+
+/// Interface of a SPIR-V shader program.
+#[derive(Debug, Clone)]
+pub struct ShaderReflection {
+    /// Entry points contained in the module.
+    pub entry_points: Vec<EntryPoint>,
+}
+
+impl ShaderReflection {
+    /// Find the entry point with the given name for a stage.
+    pub fn entry_point(&self, name: &str, stage: ShaderStages) -> Option<&EntryPoint> {
+        self.entry_points
+            .iter()
+            .find(|entry| entry.name == name && entry.stage == stage)
+    }
+}
+
+/// Extract the interface of a SPIR-V module.
+pub fn reflect(spirv: &[u8]) -> Result<ShaderReflection, ReflectError> {
+    let words = to_words(spirv)?;
+
+    let module = Module::parse(&words)?;
+
+    module.reflect()
+}
+
+fn to_words(spirv: &[u8]) -> Result<Vec<u32>, ReflectError> {
+    if spirv.len() % 4 != 0 || spirv.len() < HEADER_SIZE * 4 {
+        return Err(ReflectError::InvalidSize);
+    }
+
+    let words = spirv
+        .chunks(4)
+        .map(|bytes| {
+            u32::from(bytes[0])
+                | u32::from(bytes[1]) << 8
+                | u32::from(bytes[2]) << 16
+                | u32::from(bytes[3]) << 24
+        })
+        .collect::<Vec<_>>();
+
+    if words[0] == MAGIC_NUMBER.swap_bytes() {
+        return Err(ReflectError::WrongByteOrder);
+    }
+
+    if words[0] != MAGIC_NUMBER {
+        return Err(ReflectError::InvalidMagicNumber(words[0]));
+    }
+
+    Ok(words)
+}
+
+enum Type {
+    Bool,
+    Int { width: u32, signed: bool },
+    Float { width: u32 },
+    Vector { component: u32, count: u32 },
+    Matrix { column: u32, count: u32 },
+    Image { dim: u32, sampled: u32 },
+    Sampler,
+    SampledImage,
+    Array { element: u32, length: u32 },
+    RuntimeArray,
+    Struct { members: Vec<u32> },
+    Pointer { pointee: u32 },
+}
+
+#[derive(Default)]
+struct Decorations {
+    set: Option<u32>,
+    binding: Option<u32>,
+    location: Option<u32>,
+    built_in: bool,
+    buffer_block: bool,
+    array_stride: Option<u32>,
+}
+
+#[derive(Default)]
+struct MemberDecorations {
+    offset: Option<u32>,
+    matrix_stride: Option<u32>,
+}
+
+struct RawEntryPoint {
+    model: u32,
+    id: u32,
+    name: String,
+    interface: Vec<u32>,
+}
+
+struct Variable {
+    id: u32,
+    ty: u32,
+    storage_class: u32,
+}
+
+#[derive(Default)]
+struct Function {
+    /// Functions called by this function.
+    calls: Vec<u32>,
+    /// Ids used as operands by the instructions of this function.
+    references: HashSet<u32>,
+}
+
+#[derive(Default)]
+struct Module {
+    entry_points: Vec<RawEntryPoint>,
+    local_sizes: HashMap<u32, [u32; 3]>,
+    types: HashMap<u32, Type>,
+    constants: HashMap<u32, u32>,
+    variables: Vec<Variable>,
+    functions: HashMap<u32, Function>,
+    decorations: HashMap<u32, Decorations>,
+    member_decorations: HashMap<(u32, u32), MemberDecorations>,
+
+    /// Function whose body is currently being parsed.
+    current_function: Option<u32>,
+}
+
+struct Instruction<'a> {
+    offset: usize,
+    operands: &'a [u32],
+}
+
+impl<'a> Instruction<'a> {
+    fn get(&self, idx: usize) -> Result<u32, ReflectError> {
+        self.operands
+            .get(idx)
+            .cloned()
+            .ok_or(ReflectError::MalformedInstruction(self.offset))
+    }
+
+    /// Read a null-terminated string starting at operand `idx`.
+    ///
+    /// Returns the string and the number of words it occupies.
+    fn string(&self, idx: usize) -> Result<(String, usize), ReflectError> {
+        let mut bytes = vec![];
+
+        for (i, word) in self.operands.iter().skip(idx).enumerate() {
+            for shift in 0..4 {
+                let byte = (word >> (shift * 8)) as u8;
+
+                if byte == 0 {
+                    let string = String::from_utf8(bytes)
+                        .map_err(|_| ReflectError::MalformedInstruction(self.offset))?;
+                    return Ok((string, i + 1));
+                }
+
+                bytes.push(byte);
+            }
+        }
+
+        Err(ReflectError::MalformedInstruction(self.offset))
+    }
+}
+
+impl Module {
+    fn parse(words: &[u32]) -> Result<Self, ReflectError> {
+        let mut module = Module::default();
+
+        let mut offset = HEADER_SIZE;
+
+        while offset < words.len() {
+            let word_count = (words[offset] >> 16) as usize;
+            let opcode = words[offset] & 0xffff;
+
+            if word_count == 0 || offset + word_count > words.len() {
+                return Err(ReflectError::MalformedInstruction(offset));
+            }
+
+            let inst = Instruction {
+                offset,
+                operands: &words[offset + 1..offset + word_count],
+            };
+
+            module.parse_instruction(opcode, &inst)?;
+
+            offset += word_count;
+        }
+
+        Ok(module)
+    }
+
+    fn parse_instruction(&mut self, opcode: u32, inst: &Instruction) -> Result<(), ReflectError> {
+        if let Some(function) = self.current_function {
+            self.parse_function_instruction(function, opcode, inst)?;
+        }
+
+        match opcode {
+            OP_ENTRY_POINT => {
+                let (name, name_len) = inst.string(2)?;
+
+                self.entry_points.push(RawEntryPoint {
+                    model: inst.get(0)?,
+                    id: inst.get(1)?,
+                    name,
+                    interface: inst.operands[2 + name_len..].to_vec(),
+                });
+            }
+            OP_EXECUTION_MODE => {
+                if inst.get(1)? == EXECUTION_MODE_LOCAL_SIZE {
+                    let size = [inst.get(2)?, inst.get(3)?, inst.get(4)?];
+                    self.local_sizes.insert(inst.get(0)?, size);
+                }
+            }
+            OP_TYPE_BOOL => {
+                self.types.insert(inst.get(0)?, Type::Bool);
+            }
+            OP_TYPE_INT => {
+                let ty = Type::Int {
+                    width: inst.get(1)?,
+                    signed: inst.get(2)? != 0,
+                };
+                self.types.insert(inst.get(0)?, ty);
+            }
+            OP_TYPE_FLOAT => {
+                let ty = Type::Float {
+                    width: inst.get(1)?,
+                };
+                self.types.insert(inst.get(0)?, ty);
+            }
+            OP_TYPE_VECTOR => {
+                let ty = Type::Vector {
+                    component: inst.get(1)?,
+                    count: inst.get(2)?,
+                };
+                self.types.insert(inst.get(0)?, ty);
+            }
+            OP_TYPE_MATRIX => {
+                let ty = Type::Matrix {
+                    column: inst.get(1)?,
+                    count: inst.get(2)?,
+                };
+                self.types.insert(inst.get(0)?, ty);
+            }
+            OP_TYPE_IMAGE => {
+                let ty = Type::Image {
+                    dim: inst.get(2)?,
+                    sampled: inst.get(6)?,
+                };
+                self.types.insert(inst.get(0)?, ty);
+            }
+            OP_TYPE_SAMPLER => {
+                self.types.insert(inst.get(0)?, Type::Sampler);
+            }
+            OP_TYPE_SAMPLED_IMAGE => {
+                self.types.insert(inst.get(0)?, Type::SampledImage);
+            }
+            OP_TYPE_ARRAY => {
+                let ty = Type::Array {
+                    element: inst.get(1)?,
+                    length: inst.get(2)?,
+                };
+                self.types.insert(inst.get(0)?, ty);
+            }
+            OP_TYPE_RUNTIME_ARRAY => {
+                self.types.insert(inst.get(0)?, Type::RuntimeArray);
+            }
+            OP_TYPE_STRUCT => {
+                let id = inst.get(0)?;
+                let ty = Type::Struct {
+                    members: inst.operands[1..].to_vec(),
+                };
+                self.types.insert(id, ty);
+            }
+            OP_TYPE_POINTER => {
+                let ty = Type::Pointer {
+                    pointee: inst.get(2)?,
+                };
+                self.types.insert(inst.get(0)?, ty);
+            }
+            OP_CONSTANT | OP_SPEC_CONSTANT => {
+                // only the low word is needed, constants are only used as array lengths.
+                self.constants.insert(inst.get(1)?, inst.get(2)?);
+            }
+            OP_FUNCTION => {
+                let id = inst.get(1)?;
+                self.functions.entry(id).or_default();
+                self.current_function = Some(id);
+            }
+            OP_FUNCTION_END => {
+                self.current_function = None;
+            }
+            OP_VARIABLE => {
+                self.variables.push(Variable {
+                    ty: inst.get(0)?,
+                    id: inst.get(1)?,
+                    storage_class: inst.get(2)?,
+                });
+            }
+            OP_DECORATE => {
+                let decorations = self.decorations.entry(inst.get(0)?).or_default();
+
+                match inst.get(1)? {
+                    DECORATION_BUFFER_BLOCK => decorations.buffer_block = true,
+                    DECORATION_ARRAY_STRIDE => decorations.array_stride = Some(inst.get(2)?),
+                    DECORATION_BUILT_IN => decorations.built_in = true,
+                    DECORATION_LOCATION => decorations.location = Some(inst.get(2)?),
+                    DECORATION_BINDING => decorations.binding = Some(inst.get(2)?),
+                    DECORATION_DESCRIPTOR_SET => decorations.set = Some(inst.get(2)?),
+                    _ => {}
+                }
+            }
+            OP_MEMBER_DECORATE => {
+                let key = (inst.get(0)?, inst.get(1)?);
+                let decorations = self.member_decorations.entry(key).or_default();
+
+                match inst.get(2)? {
+                    DECORATION_OFFSET => decorations.offset = Some(inst.get(3)?),
+                    DECORATION_MATRIX_STRIDE => decorations.matrix_stride = Some(inst.get(3)?),
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Record the functions called and the ids used by an instruction inside a function body.
+    ///
+    /// Operands are not decoded per opcode, every operand is treated as a potential id. Literal
+    /// operands that happen to match the id of a variable can only make the reflection
+    /// report a resource the function does not actually use.
+    fn parse_function_instruction(
+        &mut self,
+        function: u32,
+        opcode: u32,
+        inst: &Instruction,
+    ) -> Result<(), ReflectError> {
+        let function = self
+            .functions
+            .get_mut(&function)
+            .ok_or(ReflectError::UndefinedId(function))?;
+
+        match opcode {
+            // line numbers are the most common literals, skip them.
+            OP_LINE => {}
+            OP_FUNCTION_CALL => {
+                function.calls.push(inst.get(2)?);
+                function.references.extend(&inst.operands[3..]);
+            }
+            _ => {
+                function.references.extend(inst.operands);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn ty(&self, id: u32) -> Result<&Type, ReflectError> {
+        self.types.get(&id).ok_or(ReflectError::UndefinedId(id))
+    }
+
+    fn constant(&self, id: u32) -> Result<u32, ReflectError> {
+        self.constants
+            .get(&id)
+            .cloned()
+            .ok_or(ReflectError::UndefinedId(id))
+    }
+
+    /// Nesting depth of the types contained in `ty`, which is nested `depth` levels deep.
+    fn nested(ty: u32, depth: u32) -> Result<u32, ReflectError> {
+        if depth >= MAX_TYPE_DEPTH {
+            Err(ReflectError::RecursiveType(ty))
+        } else {
+            Ok(depth + 1)
+        }
+    }
+
+    fn pointee(&self, pointer: u32) -> Result<u32, ReflectError> {
+        match self.ty(pointer)? {
+            Type::Pointer { pointee } => Ok(*pointee),
+            _ => Err(ReflectError::UndefinedId(pointer)),
+        }
+    }
+
+    fn reflect(&self) -> Result<ShaderReflection, ReflectError> {
+        let entry_points = self
+            .entry_points
+            .iter()
+            .filter_map(|entry| {
+                let stage = match entry.model {
+                    0 => ShaderStages::VERTEX,
+                    1 => ShaderStages::TESS_CONTROL,
+                    2 => ShaderStages::TESS_EVALUATION,
+                    3 => ShaderStages::GEOMETRY,
+                    4 => ShaderStages::FRAGMENT,
+                    5 => ShaderStages::COMPUTE,
+                    _ => return None,
+                };
+
+                Some((entry, stage))
+            })
+            .map(|(entry, stage)| self.entry_point(entry, stage))
+            .collect::<Result<Vec<_>, ReflectError>>()?;
+
+        Ok(ShaderReflection { entry_points })
+    }
+
+    fn entry_point(
+        &self,
+        entry: &RawEntryPoint,
+        stage: ShaderStages,
+    ) -> Result<EntryPoint, ReflectError> {
+        let referenced = self.referenced_ids(entry.id)?;
+
+        let mut descriptors = vec![];
+        let mut push_constants = None;
+
+        for var in &self.variables {
+            if !referenced.contains(&var.id) {
+                continue;
+            }
+
+            match var.storage_class {
+                STORAGE_CLASS_UNIFORM_CONSTANT
+                | STORAGE_CLASS_UNIFORM
+                | STORAGE_CLASS_STORAGE_BUFFER => {
+                    if let Some(desc) = self.descriptor(var)? {
+                        descriptors.push(desc);
+                    }
+                }
+                STORAGE_CLASS_PUSH_CONSTANT => {
+                    let ty = self.pointee(var.ty)?;
+                    push_constants = Some(self.struct_range(ty, 0)?);
+                }
+                _ => {}
+            }
+        }
+
+        descriptors.sort_by_key(|desc| (desc.set, desc.binding));
+
+        let inputs = if stage == ShaderStages::VERTEX {
+            self.vertex_inputs(&entry.interface)?
+        } else {
+            vec![]
+        };
+
+        Ok(EntryPoint {
+            name: entry.name.clone(),
+            stage,
+            local_size: self.local_sizes.get(&entry.id).cloned(),
+            inputs,
+            descriptors,
+            push_constants,
+        })
+    }
+
+    /// Ids used by a function and all functions it (indirectly) calls.
+    fn referenced_ids(&self, function: u32) -> Result<HashSet<u32>, ReflectError> {
+        let mut referenced = HashSet::new();
+        let mut visited = HashSet::new();
+        let mut pending = vec![function];
+
+        while let Some(id) = pending.pop() {
+            if !visited.insert(id) {
+                continue;
+            }
+
+            let function = self
+                .functions
+                .get(&id)
+                .ok_or(ReflectError::UndefinedId(id))?;
+
+            referenced.extend(&function.references);
+            pending.extend(&function.calls);
+        }
+
+        Ok(referenced)
+    }
+
+    fn descriptor(&self, var: &Variable) -> Result<Option<DescriptorBinding>, ReflectError> {
+        let mut ty_id = self.pointee(var.ty)?;
+        let mut count = 1u32;
+        let mut depth = 0;
+
+        // arrays of descriptors
+        loop {
+            match self.ty(ty_id)? {
+                Type::Array { element, length } => {
+                    depth = Self::nested(ty_id, depth)?;
+                    count = count
+                        .checked_mul(self.constant(*length)?)
+                        .ok_or(ReflectError::SizeOverflow(ty_id))?;
+                    ty_id = *element;
+                }
+                Type::RuntimeArray => {
+                    count = 0;
+                    break;
+                }
+                _ => break,
+            }
+        }
+
+        let ty = match self.ty(ty_id)? {
+            Type::Sampler => DescriptorType::Sampler,
+            Type::SampledImage => DescriptorType::CombinedImageSampler,
+            Type::Image { dim, sampled } => match (*dim, *sampled) {
+                (DIM_BUFFER, 2) => DescriptorType::StorageTexelBuffer,
+                (DIM_BUFFER, _) => DescriptorType::UniformTexelBuffer,
+                (DIM_SUBPASS_DATA, _) => DescriptorType::InputAttachment,
+                (_, 2) => DescriptorType::StorageImage,
+                (_, _) => DescriptorType::SampledImage,
+            },
+            Type::Struct { .. } => {
+                let buffer_block = self
+                    .decorations
+                    .get(&ty_id)
+                    .map(|dec| dec.buffer_block)
+                    .unwrap_or(false);
+
+                if var.storage_class == STORAGE_CLASS_STORAGE_BUFFER || buffer_block {
+                    DescriptorType::StorageBuffer
+                } else {
+                    DescriptorType::UniformBuffer
+                }
+            }
+            _ => return Ok(None),
+        };
+
+        let decorations = self.decorations.get(&var.id);
+
+        Ok(Some(DescriptorBinding {
+            set: decorations.and_then(|dec| dec.set).unwrap_or(0),
+            binding: decorations.and_then(|dec| dec.binding).unwrap_or(0),
+            ty,
+            count,
+        }))
+    }
+
+    /// Range in bytes covered by the members of a struct.
+    fn struct_range(&self, ty: u32, depth: u32) -> Result<Range<u32>, ReflectError> {
+        let members = match self.ty(ty)? {
+            Type::Struct { members } => members,
+            _ => return Ok(0..self.size_of(ty, None, depth)?),
+        };
+
+        let depth = Self::nested(ty, depth)?;
+
+        let mut start = u32::max_value();
+        let mut end = 0;
+        let mut next_offset = 0;
+
+        for (idx, member) in members.iter().enumerate() {
+            let decorations = self.member_decorations.get(&(ty, idx as u32));
+
+            let offset = decorations
+                .and_then(|dec| dec.offset)
+                .unwrap_or(next_offset);
+            let matrix_stride = decorations.and_then(|dec| dec.matrix_stride);
+
+            let size = self.size_of(*member, matrix_stride, depth)?;
+            let member_end = offset
+                .checked_add(size)
+                .ok_or(ReflectError::SizeOverflow(ty))?;
+
+            start = start.min(offset);
+            end = end.max(member_end);
+            next_offset = member_end;
+        }
+
+        if members.is_empty() {
+            start = 0;
+        }
+
+        Ok(start..end)
+    }
+
+    fn size_of(
+        &self,
+        ty: u32,
+        matrix_stride: Option<u32>,
+        depth: u32,
+    ) -> Result<u32, ReflectError> {
+        let size = match self.ty(ty)? {
+            Type::Bool => Some(4),
+            Type::Int { width, .. } | Type::Float { width } => Some(width / 8),
+            Type::Vector { component, count } => {
+                let depth = Self::nested(ty, depth)?;
+                count.checked_mul(self.size_of(*component, None, depth)?)
+            }
+            Type::Matrix { column, count } => {
+                let depth = Self::nested(ty, depth)?;
+                let stride = match matrix_stride {
+                    Some(stride) => stride,
+                    None => self.size_of(*column, None, depth)?,
+                };
+                count.checked_mul(stride)
+            }
+            Type::Array { element, length } => {
+                let depth = Self::nested(ty, depth)?;
+                let stride = self.decorations.get(&ty).and_then(|dec| dec.array_stride);
+
+                let stride = match stride {
+                    Some(stride) => stride,
+                    None => self.size_of(*element, matrix_stride, depth)?,
+                };
+
+                self.constant(*length)?.checked_mul(stride)
+            }
+            Type::Struct { .. } => Some(self.struct_range(ty, depth)?.end),
+            _ => Some(0),
+        };
+
+        size.ok_or(ReflectError::SizeOverflow(ty))
+    }
+
+    fn vertex_inputs(&self, interface: &[u32]) -> Result<Vec<InputVariable>, ReflectError> {
+        let mut inputs = vec![];
+
+        for var in &self.variables {
+            if var.storage_class != STORAGE_CLASS_INPUT || !interface.contains(&var.id) {
+                continue;
+            }
+
+            let decorations = match self.decorations.get(&var.id) {
+                Some(dec) => dec,
+                None => continue,
+            };
+
+            let location = match decorations.location {
+                Some(location) if !decorations.built_in => location,
+                _ => continue,
+            };
+
+            let ty = self.pointee(var.ty)?;
+
+            if let Some((scalar, components, locations)) = self.input_type(ty, 0)? {
+                if location.checked_add(locations).is_none() {
+                    return Err(ReflectError::SizeOverflow(ty));
+                }
+
+                inputs.push(InputVariable {
+                    location,
+                    locations,
+                    ty: scalar,
+                    components,
+                });
+            }
+        }
+
+        inputs.sort_by_key(|input| input.location);
+
+        Ok(inputs)
+    }
+
+    /// Component type, component count and number of locations of an input of type `ty`.
+    ///
+    /// Returns `None` for types that don't consist of numeric components.
+    fn input_type(
+        &self,
+        ty: u32,
+        depth: u32,
+    ) -> Result<Option<(ScalarType, u32, u32)>, ReflectError> {
+        match self.ty(ty)? {
+            Type::Vector { component, count } => self.scalar_input(*component, *count),
+            Type::Matrix { column, count } => {
+                let depth = Self::nested(ty, depth)?;
+                self.input_elements(ty, *column, *count, depth)
+            }
+            Type::Array { element, length } => {
+                let depth = Self::nested(ty, depth)?;
+                let length = self.constant(*length)?;
+                self.input_elements(ty, *element, length, depth)
+            }
+            _ => self.scalar_input(ty, 1),
+        }
+    }
+
+    /// Input type of a scalar or a vector of `components` scalars.
+    fn scalar_input(
+        &self,
+        ty: u32,
+        components: u32,
+    ) -> Result<Option<(ScalarType, u32, u32)>, ReflectError> {
+        let scalar = match self.scalar_type(ty)? {
+            Some(scalar) => scalar,
+            None => return Ok(None),
+        };
+
+        let wide = match self.ty(ty)? {
+            Type::Int { width, .. } | Type::Float { width } => *width > 32,
+            _ => false,
+        };
+
+        let locations = if wide && components > 2 { 2 } else { 1 };
+
+        Ok(Some((scalar, components, locations)))
+    }
+
+    /// Input type of `count` consecutive elements of type `element`.
+    fn input_elements(
+        &self,
+        ty: u32,
+        element: u32,
+        count: u32,
+        depth: u32,
+    ) -> Result<Option<(ScalarType, u32, u32)>, ReflectError> {
+        if count == 0 {
+            return Err(ReflectError::InvalidLength(ty));
+        }
+
+        let (scalar, components, locations) = match self.input_type(element, depth)? {
+            Some(input) => input,
+            None => return Ok(None),
+        };
+
+        let locations = locations
+            .checked_mul(count)
+            .ok_or(ReflectError::SizeOverflow(ty))?;
+
+        Ok(Some((scalar, components, locations)))
+    }
+
+    fn scalar_type(&self, ty: u32) -> Result<Option<ScalarType>, ReflectError> {
+        let scalar = match self.ty(ty)? {
+            Type::Float { .. } => Some(ScalarType::Float),
+            Type::Int { signed: true, .. } => Some(ScalarType::Int),
+            Type::Int { signed: false, .. } => Some(ScalarType::Uint),
+            _ => None,
+        };
+
+        Ok(scalar)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OP_LOAD: u32 = 61;
+
+    struct Assembler {
+        words: Vec<u32>,
+    }
+
+    impl Assembler {
+        fn new() -> Self {
+            Assembler {
+                words: vec![MAGIC_NUMBER, 0x0001_0000, 0, 100, 0],
+            }
+        }
+
+        fn op(&mut self, opcode: u32, operands: &[u32]) {
+            let word_count = operands.len() as u32 + 1;
+            self.words.push(word_count << 16 | opcode);
+            self.words.extend_from_slice(operands);
+        }
+
+        fn string(s: &str) -> Vec<u32> {
+            let mut bytes = s.as_bytes().to_vec();
+            bytes.push(0);
+            while bytes.len() % 4 != 0 {
+                bytes.push(0);
+            }
+
+            bytes
+                .chunks(4)
+                .map(|b| {
+                    u32::from(b[0])
+                        | u32::from(b[1]) << 8
+                        | u32::from(b[2]) << 16
+                        | u32::from(b[3]) << 24
+                })
+                .collect()
+        }
+
+        fn bytes(&self) -> Vec<u8> {
+            self.words
+                .iter()
+                .flat_map(|word| {
+                    vec![
+                        *word as u8,
+                        (word >> 8) as u8,
+                        (word >> 16) as u8,
+                        (word >> 24) as u8,
+                    ]
+                })
+                .collect()
+        }
+    }
+
+    fn entry_point(asm: &mut Assembler, model: u32, id: u32, name: &str, interface: &[u32]) {
+        let mut operands = vec![model, id];
+        operands.extend(Assembler::string(name));
+        operands.extend_from_slice(interface);
+        asm.op(OP_ENTRY_POINT, &operands);
+    }
+
+    /// Function `id` which loads the `used` variables and calls the `called` functions.
+    fn function(asm: &mut Assembler, id: u32, used: &[u32], called: &[u32]) {
+        // result type and function type are not needed for reflection.
+        asm.op(OP_FUNCTION, &[900, id, 0, 901]);
+
+        for var in used {
+            asm.op(OP_LOAD, &[902, 903, *var]);
+        }
+
+        for function in called {
+            asm.op(OP_FUNCTION_CALL, &[900, 904, *function]);
+        }
+
+        asm.op(OP_FUNCTION_END, &[]);
+    }
+
+    #[test]
+    fn invalid_modules() {
+        match reflect(&[0; 7]) {
+            Err(ReflectError::InvalidSize) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        match reflect(&[0; 20]) {
+            Err(ReflectError::InvalidMagicNumber(0)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        let mut swapped = Assembler::new();
+        for word in &mut swapped.words {
+            *word = word.swap_bytes();
+        }
+
+        match reflect(&swapped.bytes()) {
+            Err(ReflectError::WrongByteOrder) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        let mut asm = Assembler::new();
+        // claims 4 words but only has 2
+        asm.words.push(4 << 16 | OP_TYPE_INT);
+        asm.words.push(1);
+
+        match reflect(&asm.bytes()) {
+            Err(ReflectError::MalformedInstruction(5)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+
+    #[test]
+    fn recursive_types() {
+        // push-constant struct containing itself
+        let mut asm = Assembler::new();
+        entry_point(&mut asm, 5, 1, "main", &[]);
+        asm.op(OP_TYPE_STRUCT, &[30, 30]);
+        asm.op(OP_TYPE_POINTER, &[31, STORAGE_CLASS_PUSH_CONSTANT, 30]);
+        asm.op(OP_VARIABLE, &[31, 32, STORAGE_CLASS_PUSH_CONSTANT]);
+        function(&mut asm, 1, &[32], &[]);
+
+        match reflect(&asm.bytes()) {
+            Err(ReflectError::RecursiveType(30)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        // descriptor array of itself
+        let mut asm = Assembler::new();
+        entry_point(&mut asm, 5, 1, "main", &[]);
+        asm.op(OP_TYPE_INT, &[3, 32, 0]);
+        asm.op(OP_CONSTANT, &[3, 5, 1]);
+        asm.op(OP_TYPE_ARRAY, &[22, 22, 5]);
+        asm.op(OP_TYPE_POINTER, &[23, STORAGE_CLASS_UNIFORM_CONSTANT, 22]);
+        asm.op(OP_VARIABLE, &[23, 20, STORAGE_CLASS_UNIFORM_CONSTANT]);
+        function(&mut asm, 1, &[20], &[]);
+
+        match reflect(&asm.bytes()) {
+            Err(ReflectError::RecursiveType(22)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+
+    #[test]
+    fn overflowing_arrays() {
+        let huge = u32::max_value();
+
+        // push constants: vec4[huge]
+        let mut asm = Assembler::new();
+        entry_point(&mut asm, 5, 1, "main", &[]);
+        asm.op(OP_TYPE_FLOAT, &[2, 32]);
+        asm.op(OP_TYPE_INT, &[3, 32, 0]);
+        asm.op(OP_TYPE_VECTOR, &[4, 2, 4]);
+        asm.op(OP_CONSTANT, &[3, 5, huge]);
+        asm.op(OP_TYPE_ARRAY, &[6, 4, 5]);
+        asm.op(OP_TYPE_STRUCT, &[30, 6]);
+        asm.op(OP_TYPE_POINTER, &[31, STORAGE_CLASS_PUSH_CONSTANT, 30]);
+        asm.op(OP_VARIABLE, &[31, 32, STORAGE_CLASS_PUSH_CONSTANT]);
+        function(&mut asm, 1, &[32], &[]);
+
+        match reflect(&asm.bytes()) {
+            Err(ReflectError::SizeOverflow(6)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        // descriptor count: sampler[huge][2]
+        let mut asm = Assembler::new();
+        entry_point(&mut asm, 5, 1, "main", &[]);
+        asm.op(OP_TYPE_INT, &[3, 32, 0]);
+        asm.op(OP_CONSTANT, &[3, 5, huge]);
+        asm.op(OP_CONSTANT, &[3, 6, 2]);
+        asm.op(OP_TYPE_SAMPLER, &[21]);
+        asm.op(OP_TYPE_ARRAY, &[22, 21, 5]);
+        asm.op(OP_TYPE_ARRAY, &[23, 22, 6]);
+        asm.op(OP_TYPE_POINTER, &[24, STORAGE_CLASS_UNIFORM_CONSTANT, 23]);
+        asm.op(OP_VARIABLE, &[24, 20, STORAGE_CLASS_UNIFORM_CONSTANT]);
+        function(&mut asm, 1, &[20], &[]);
+
+        match reflect(&asm.bytes()) {
+            Err(ReflectError::SizeOverflow(22)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        // vertex input locations: float[huge] at location 0 and float[huge][2] at location 1
+        let vertex_input = |location: u32, ty: u32| {
+            let mut asm = Assembler::new();
+            entry_point(&mut asm, 0, 1, "main", &[10]);
+            asm.op(OP_DECORATE, &[10, DECORATION_LOCATION, location]);
+            asm.op(OP_TYPE_FLOAT, &[2, 32]);
+            asm.op(OP_TYPE_INT, &[3, 32, 0]);
+            asm.op(OP_CONSTANT, &[3, 5, huge]);
+            asm.op(OP_CONSTANT, &[3, 6, 2]);
+            asm.op(OP_TYPE_ARRAY, &[7, 2, 5]);
+            asm.op(OP_TYPE_ARRAY, &[8, 7, 6]);
+            asm.op(OP_TYPE_POINTER, &[11, STORAGE_CLASS_INPUT, ty]);
+            asm.op(OP_VARIABLE, &[11, 10, STORAGE_CLASS_INPUT]);
+            function(&mut asm, 1, &[], &[]);
+
+            reflect(&asm.bytes())
+        };
+
+        // the locations are counted, not enumerated.
+        let reflection = vertex_input(0, 7).unwrap();
+        assert_eq!(reflection.entry_points[0].inputs[0].locations, huge);
+
+        match vertex_input(1, 7) {
+            Err(ReflectError::SizeOverflow(7)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        match vertex_input(0, 8) {
+            Err(ReflectError::SizeOverflow(8)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+
+    #[test]
+    fn compute_interface() {
+        let mut asm = Assembler::new();
+
+        entry_point(&mut asm, 5, 1, "main", &[]);
+        asm.op(OP_EXECUTION_MODE, &[1, EXECUTION_MODE_LOCAL_SIZE, 64, 2, 1]);
+
+        // storage buffer at set 1, binding 3
+        asm.op(OP_DECORATE, &[10, DECORATION_DESCRIPTOR_SET, 1]);
+        asm.op(OP_DECORATE, &[10, DECORATION_BINDING, 3]);
+        asm.op(OP_DECORATE, &[11, DECORATION_BUFFER_BLOCK]);
+
+        // array of 4 storage images at set 0, binding 1
+        asm.op(OP_DECORATE, &[20, DECORATION_DESCRIPTOR_SET, 0]);
+        asm.op(OP_DECORATE, &[20, DECORATION_BINDING, 1]);
+
+        // push constants: { float a; (pad) vec4 b; }
+        asm.op(OP_MEMBER_DECORATE, &[30, 0, DECORATION_OFFSET, 0]);
+        asm.op(OP_MEMBER_DECORATE, &[30, 1, DECORATION_OFFSET, 16]);
+
+        asm.op(OP_TYPE_FLOAT, &[2, 32]);
+        asm.op(OP_TYPE_INT, &[3, 32, 0]);
+        asm.op(OP_TYPE_VECTOR, &[4, 2, 4]);
+        asm.op(OP_CONSTANT, &[3, 5, 4]);
+
+        asm.op(OP_TYPE_STRUCT, &[11, 2]);
+        asm.op(OP_TYPE_POINTER, &[12, STORAGE_CLASS_UNIFORM, 11]);
+        asm.op(OP_VARIABLE, &[12, 10, STORAGE_CLASS_UNIFORM]);
+
+        // image type: result, sampled type, dim, depth, arrayed, ms, sampled, format
+        asm.op(OP_TYPE_IMAGE, &[21, 2, 1, 0, 0, 0, 2, 1]);
+        asm.op(OP_TYPE_ARRAY, &[22, 21, 5]);
+        asm.op(OP_TYPE_POINTER, &[23, STORAGE_CLASS_UNIFORM_CONSTANT, 22]);
+        asm.op(OP_VARIABLE, &[23, 20, STORAGE_CLASS_UNIFORM_CONSTANT]);
+
+        asm.op(OP_TYPE_STRUCT, &[30, 2, 4]);
+        asm.op(OP_TYPE_POINTER, &[31, STORAGE_CLASS_PUSH_CONSTANT, 30]);
+        asm.op(OP_VARIABLE, &[31, 32, STORAGE_CLASS_PUSH_CONSTANT]);
+
+        // main only accesses the storage buffer itself
+        function(&mut asm, 1, &[10], &[40]);
+        function(&mut asm, 40, &[20, 32], &[]);
+
+        let reflection = reflect(&asm.bytes()).unwrap();
+
+        let entry = reflection
+            .entry_point("main", ShaderStages::COMPUTE)
+            .unwrap();
+        assert_eq!(entry.local_size, Some([64, 2, 1]));
+        assert!(reflection
+            .entry_point("main", ShaderStages::VERTEX)
+            .is_none());
+
+        assert_eq!(
+            entry.descriptors,
+            vec![
+                DescriptorBinding {
+                    set: 0,
+                    binding: 1,
+                    ty: DescriptorType::StorageImage,
+                    count: 4,
+                },
+                DescriptorBinding {
+                    set: 1,
+                    binding: 3,
+                    ty: DescriptorType::StorageBuffer,
+                    count: 1,
+                },
+            ]
+        );
+
+        assert_eq!(entry.push_constants, Some(0..32));
+    }
+
+    #[test]
+    fn vertex_inputs() {
+        let mut asm = Assembler::new();
+
+        entry_point(&mut asm, 0, 1, "VertexMain", &[10, 20, 30, 50]);
+
+        asm.op(OP_DECORATE, &[10, DECORATION_LOCATION, 0]);
+        asm.op(OP_DECORATE, &[20, DECORATION_LOCATION, 1]);
+        asm.op(OP_DECORATE, &[50, DECORATION_LOCATION, 3]);
+        asm.op(OP_DECORATE, &[30, DECORATION_BUILT_IN, 42]);
+        // not part of the entry point interface
+        asm.op(OP_DECORATE, &[40, DECORATION_LOCATION, 5]);
+
+        asm.op(OP_TYPE_FLOAT, &[2, 32]);
+        asm.op(OP_TYPE_INT, &[3, 32, 1]);
+        asm.op(OP_TYPE_VECTOR, &[4, 2, 2]);
+        asm.op(OP_TYPE_MATRIX, &[5, 4, 2]);
+        // dvec3[2]
+        asm.op(OP_TYPE_FLOAT, &[6, 64]);
+        asm.op(OP_TYPE_VECTOR, &[7, 6, 3]);
+        asm.op(OP_TYPE_INT, &[8, 32, 0]);
+        asm.op(OP_CONSTANT, &[8, 9, 2]);
+        asm.op(OP_TYPE_ARRAY, &[14, 7, 9]);
+
+        asm.op(OP_TYPE_POINTER, &[11, STORAGE_CLASS_INPUT, 4]);
+        asm.op(OP_TYPE_POINTER, &[12, STORAGE_CLASS_INPUT, 5]);
+        asm.op(OP_TYPE_POINTER, &[13, STORAGE_CLASS_INPUT, 3]);
+        asm.op(OP_TYPE_POINTER, &[15, STORAGE_CLASS_INPUT, 14]);
+
+        asm.op(OP_VARIABLE, &[11, 10, STORAGE_CLASS_INPUT]);
+        asm.op(OP_VARIABLE, &[12, 20, STORAGE_CLASS_INPUT]);
+        asm.op(OP_VARIABLE, &[13, 30, STORAGE_CLASS_INPUT]);
+        asm.op(OP_VARIABLE, &[13, 40, STORAGE_CLASS_INPUT]);
+        asm.op(OP_VARIABLE, &[15, 50, STORAGE_CLASS_INPUT]);
+
+        function(&mut asm, 1, &[10, 20, 50], &[]);
+
+        let reflection = reflect(&asm.bytes()).unwrap();
+
+        let entry = reflection
+            .entry_point("VertexMain", ShaderStages::VERTEX)
+            .unwrap();
+
+        let input = |location, locations, components| InputVariable {
+            location,
+            locations,
+            ty: ScalarType::Float,
+            components,
+        };
+
+        // vec2, mat2 (two columns), dvec3[2] (two locations per element)
+        assert_eq!(
+            entry.inputs,
+            vec![input(0, 1, 2), input(1, 2, 2), input(3, 4, 3)]
+        );
+        assert!(entry.descriptors.is_empty());
+        assert_eq!(entry.push_constants, None);
+    }
+
+    #[test]
+    fn multiple_entry_points() {
+        let mut asm = Assembler::new();
+
+        entry_point(&mut asm, 0, 1, "vertex", &[]);
+        entry_point(&mut asm, 4, 2, "fragment", &[]);
+
+        // uniform buffer at binding 0, only used by the vertex entry point
+        asm.op(OP_DECORATE, &[10, DECORATION_DESCRIPTOR_SET, 0]);
+        asm.op(OP_DECORATE, &[10, DECORATION_BINDING, 0]);
+        // sampler at binding 1, only used by the fragment entry point
+        asm.op(OP_DECORATE, &[20, DECORATION_DESCRIPTOR_SET, 0]);
+        asm.op(OP_DECORATE, &[20, DECORATION_BINDING, 1]);
+        // sampler at binding 2, not used at all
+        asm.op(OP_DECORATE, &[30, DECORATION_DESCRIPTOR_SET, 0]);
+        asm.op(OP_DECORATE, &[30, DECORATION_BINDING, 2]);
+
+        asm.op(OP_TYPE_FLOAT, &[3, 32]);
+        asm.op(OP_TYPE_STRUCT, &[11, 3]);
+        asm.op(OP_TYPE_POINTER, &[12, STORAGE_CLASS_UNIFORM, 11]);
+        asm.op(OP_VARIABLE, &[12, 10, STORAGE_CLASS_UNIFORM]);
+
+        asm.op(OP_TYPE_SAMPLER, &[21]);
+        asm.op(OP_TYPE_POINTER, &[22, STORAGE_CLASS_UNIFORM_CONSTANT, 21]);
+        asm.op(OP_VARIABLE, &[22, 20, STORAGE_CLASS_UNIFORM_CONSTANT]);
+        asm.op(OP_VARIABLE, &[22, 30, STORAGE_CLASS_UNIFORM_CONSTANT]);
+
+        asm.op(OP_TYPE_POINTER, &[41, STORAGE_CLASS_PUSH_CONSTANT, 11]);
+        asm.op(OP_VARIABLE, &[41, 40, STORAGE_CLASS_PUSH_CONSTANT]);
+
+        // the vertex entry point uses the uniform buffer through a helper function
+        function(&mut asm, 1, &[], &[50]);
+        function(&mut asm, 50, &[10], &[]);
+
+        // the fragment entry point recurses, and has a line number matching an unused variable
+        asm.op(OP_FUNCTION, &[900, 2, 0, 901]);
+        asm.op(OP_LINE, &[905, 30, 1]);
+        asm.op(OP_LOAD, &[902, 903, 20]);
+        asm.op(OP_LOAD, &[902, 903, 40]);
+        asm.op(OP_FUNCTION_CALL, &[900, 904, 2]);
+        asm.op(OP_FUNCTION_END, &[]);
+
+        let reflection = reflect(&asm.bytes()).unwrap();
+
+        let vertex = reflection
+            .entry_point("vertex", ShaderStages::VERTEX)
+            .unwrap();
+        assert_eq!(
+            vertex.descriptors,
+            vec![DescriptorBinding {
+                set: 0,
+                binding: 0,
+                ty: DescriptorType::UniformBuffer,
+                count: 1,
+            }]
+        );
+        assert_eq!(vertex.push_constants, None);
+
+        let fragment = reflection
+            .entry_point("fragment", ShaderStages::FRAGMENT)
+            .unwrap();
+        assert_eq!(
+            fragment.descriptors,
+            vec![DescriptorBinding {
+                set: 0,
+                binding: 1,
+                ty: DescriptorType::Sampler,
+                count: 1,
+            }]
+        );
+        assert_eq!(fragment.push_constants, Some(0..4));
+
+        // an entry point without a function is malformed
+        let mut asm = Assembler::new();
+        entry_point(&mut asm, 5, 1, "main", &[]);
+
+        match reflect(&asm.bytes()) {
+            Err(ReflectError::UndefinedId(1)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+}
@@ -4,6 +4,9 @@
 
 //! Description of vertex buffers and elements used in graphics pipelines.
 
+#[cfg(feature = "derive")]
+pub use nitrogen_derive::Vertex;
+
 /// Vertex attribute description.
 #[derive(Debug, Clone)]
 pub(crate) struct VertexAttribResource {
@@ -16,6 +19,7 @@ pub(crate) struct VertexAttribResource {
 pub(crate) struct VertexBufferDesc {
     pub(crate) stride: usize,
     pub(crate) binding: usize,
+    pub(crate) rate: VertexInputRate,
 }
 
 #[derive(Debug, Clone, Hash, Eq, PartialEq)]
@@ -32,10 +36,50 @@ pub struct VertexAttribBufferInfo {
     pub stride: usize,
     /// Index used when binding a vertex buffer.
     pub index: u32,
+    /// Rate at which the buffer advances to the next element.
+    pub rate: VertexInputRate,
     /// Description of the vertex-data.
     pub elements: Vec<VertexAttribBufferElementInfo>,
 }
 
+/// Rate at which a vertex buffer advances to the next element.
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub enum VertexInputRate {
+    /// The buffer advances once per vertex.
+    Vertex,
+    /// The buffer advances once per `divisor` instances.
+    ///
+    /// Divisors other than `1` need device support for instance rate divisors.
+    Instance {
+        /// Number of instances that use the same element.
+        divisor: u32,
+    },
+}
+
+impl Default for VertexInputRate {
+    fn default() -> Self {
+        VertexInputRate::Vertex
+    }
+}
+
+impl From<VertexInputRate> for gfx::pso::VertexInputRate {
+    fn from(rate: VertexInputRate) -> Self {
+        match rate {
+            VertexInputRate::Vertex => gfx::pso::VertexInputRate::Vertex,
+            VertexInputRate::Instance { divisor } => gfx::pso::VertexInputRate::Instance(divisor),
+        }
+    }
+}
+
+/// Types that describe the layout of a single vertex in a vertex buffer.
+///
+/// With the `derive` feature enabled this can be derived for `#[repr(C)]` structs whose fields
+/// are annotated with `#[location(n)]`.
+pub trait Vertex {
+    /// Create the description of a vertex buffer containing values of this type.
+    fn buffer_info(index: u32, rate: VertexInputRate) -> VertexAttribBufferInfo;
+}
+
 #[derive(Debug, Clone, Hash, Eq, PartialEq)]
 /// Description of an element in a vertex buffer.
 pub struct VertexAttribBufferElementInfo {
@@ -79,6 +123,7 @@ pub(crate) fn crate_resource(info: &VertexAttrib) -> VertexAttribResource {
     let bufs_iter = info.buffer_infos.iter().map(|buf_info| VertexBufferDesc {
         stride: buf_info.stride,
         binding: buf_info.index as _,
+        rate: buf_info.rate,
     });
 
     bufs.extend(bufs_iter);
@@ -524,8 +524,20 @@ impl SubmitGroup {
             .destroy(&mut self.res_destroys, buffers);
     }
 
+    /// Queue the deletion of a [`BufferView`] object.
+    ///
+    /// [`BufferView`]: ../../resources/buffer/struct.BufferView.html
+    pub fn buffer_view_destroy(&mut self, ctx: &mut Context, views: &[buffer::BufferViewHandle]) {
+        ctx.buffer_storage
+            .borrow_mut()
+            .view_destroy(&mut self.res_destroys, views);
+    }
+
     /// Queue the deletion of a [`Sampler`] object.
     ///
+    /// Samplers used as immutable samplers are kept alive until the materials using them are
+    /// destroyed.
+    ///
     /// [`Sampler`]: ../../resources/sampler/index.html
     pub fn sampler_destroy(&mut self, ctx: &mut Context, samplers: &[sampler::SamplerHandle]) {
         ctx.sampler_storage
@@ -604,6 +616,7 @@ pub(crate) struct ResourceList {
 
     framebuffers: SmallVec<[types::Framebuffer; 16]>,
     buffers: SmallVec<[BufferTypeInternal; 16]>,
+    buffer_views: SmallVec<[types::BufferView; 16]>,
     images: SmallVec<[ImageType; 16]>,
     samplers: SmallVec<[types::Sampler; 16]>,
     image_views: SmallVec<[types::ImageView; 16]>,
@@ -617,11 +630,12 @@ pub(crate) struct ResourceList {
 }
 
 impl ResourceList {
-    fn new(device: Arc<DeviceContext>) -> Self {
+    pub(crate) fn new(device: Arc<DeviceContext>) -> Self {
         ResourceList {
             device,
             framebuffers: SmallVec::new(),
             buffers: SmallVec::new(),
+            buffer_views: SmallVec::new(),
             images: SmallVec::new(),
             samplers: SmallVec::new(),
             image_views: SmallVec::new(),
@@ -643,6 +657,10 @@ impl ResourceList {
         self.buffers.push(buffer);
     }
 
+    pub(crate) fn queue_buffer_view(&mut self, buffer_view: types::BufferView) {
+        self.buffer_views.push(buffer_view);
+    }
+
     pub(crate) fn queue_image(&mut self, image: ImageType) {
         self.images.push(image);
     }
@@ -679,11 +697,15 @@ impl ResourceList {
         self.material_instances.push(mat);
     }
 
-    unsafe fn free_resources(&mut self, ctx: &mut Context) {
+    pub(crate) unsafe fn free_resources(&mut self, ctx: &mut Context) {
         let mut alloc = self.device.allocator();
 
         let device = &self.device.device;
 
+        for buffer_view in self.buffer_views.drain() {
+            device.destroy_buffer_view(buffer_view);
+        }
+
         for buffer in self.buffers.drain() {
             alloc.destroy_buffer(device, buffer);
         }
@@ -721,9 +743,11 @@ impl ResourceList {
         }
 
         {
-            ctx.material_storage
-                .borrow_mut()
-                .destroy(&ctx.device_ctx, self.materials.as_slice());
+            ctx.material_storage.borrow_mut().destroy(
+                &ctx.device_ctx,
+                &mut *ctx.sampler_storage.borrow_mut(),
+                self.materials.as_slice(),
+            );
             self.materials.clear();
         }
 
@@ -4,6 +4,7 @@
 
 pub(crate) type GraphicsPipeline = <back::Backend as gfx::Backend>::GraphicsPipeline;
 pub(crate) type ComputePipeline = <back::Backend as gfx::Backend>::ComputePipeline;
+pub(crate) type PipelineCache = <back::Backend as gfx::Backend>::PipelineCache;
 pub(crate) type PipelineLayout = <back::Backend as gfx::Backend>::PipelineLayout;
 pub(crate) type DescriptorSetLayout = <back::Backend as gfx::Backend>::DescriptorSetLayout;
 pub(crate) type DescriptorSet = <back::Backend as gfx::Backend>::DescriptorSet;
@@ -20,3 +21,4 @@ pub(crate) type Semaphore = <back::Backend as gfx::Backend>::Semaphore;
 pub(crate) type QueueGroup<T> = gfx::QueueGroup<back::Backend, T>;
 pub(crate) type CommandQueue<T> = gfx::CommandQueue<back::Backend, T>;
 pub(crate) type Buffer = <back::Backend as gfx::Backend>::Buffer;
+pub(crate) type BufferView = <back::Backend as gfx::Backend>::BufferView;